@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub(crate) const MAX_ARTIFACT_FILE_BYTES: u64 = 50 * 1024 * 1024;
+pub(crate) const MAX_ARTIFACT_TASK_BYTES: u64 = 200 * 1024 * 1024;
+pub(crate) const MAX_ARTIFACT_FILES: usize = 200;
+
+/// Extensions opened with the system's default handler. Everything else, notably
+/// executables, scripts, shortcuts and HTML, is only revealed in its folder, since
+/// opening it on Windows would run agent-produced code.
+const INERT_EXTENSIONS: &[&str] = &[
+    "txt", "log", "md", "json", "xml", "csv", "tsv", "yaml", "yml", "toml", "png", "jpg", "jpeg", "gif", "bmp", "webp",
+    "pdf",
+];
+
+pub(crate) fn is_inert_artifact(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| INERT_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ArtifactLimits {
+    pub(crate) file_bytes: u64,
+    pub(crate) task_bytes: u64,
+    pub(crate) files: usize,
+}
+
+impl Default for ArtifactLimits {
+    fn default() -> Self {
+        Self {
+            file_bytes: MAX_ARTIFACT_FILE_BYTES,
+            task_bytes: MAX_ARTIFACT_TASK_BYTES,
+            files: MAX_ARTIFACT_FILES,
+        }
+    }
+}
+
+pub(crate) struct CollectedArtifact {
+    pub(crate) relative_path: String,
+    pub(crate) stored_path: PathBuf,
+    pub(crate) size: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct ArtifactCollection {
+    pub(crate) artifacts: Vec<CollectedArtifact>,
+    pub(crate) skipped: Vec<String>,
+    /// Matching files left out because the task's file or byte limit was reached.
+    /// They are counted rather than listed, since a broad pattern can match thousands.
+    pub(crate) over_limit: usize,
+}
+
+pub(crate) fn validate_artifact_pattern(pattern: &str) -> Result<(), String> {
+    let trimmed = pattern.trim();
+    if trimmed.is_empty() {
+        return Err("Artifact pattern cannot be empty.".to_string());
+    }
+
+    let path = Path::new(trimmed);
+    if path.is_absolute() {
+        return Err(format!("Artifact pattern must be relative to the workspace: {trimmed}"));
+    }
+    if path
+        .components()
+        .any(|component| matches!(component, Component::ParentDir | Component::RootDir | Component::Prefix(_)))
+    {
+        return Err(format!("Artifact pattern cannot leave the workspace: {trimmed}"));
+    }
+
+    glob::Pattern::new(trimmed)
+        .map(|_| ())
+        .map_err(|err| format!("Invalid artifact pattern {trimmed}: {err}"))
+}
+
+/// Copies files in `workspace` matching `patterns` into `destination`, keeping their
+/// relative layout. Files resolving outside the workspace or too large on their own
+/// are reported in `skipped` instead of failing the whole collection; files past the
+/// task limits are only counted in `over_limit`.
+pub(crate) fn collect_artifacts(workspace: &Path, patterns: &[String], destination: &Path) -> ArtifactCollection {
+    collect_artifacts_with_limits(workspace, patterns, destination, ArtifactLimits::default())
+}
+
+fn collect_artifacts_with_limits(
+    workspace: &Path,
+    patterns: &[String],
+    destination: &Path,
+    limits: ArtifactLimits,
+) -> ArtifactCollection {
+    let mut collection = ArtifactCollection::default();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut total_bytes: u64 = 0;
+    let workspace_pattern = glob::Pattern::escape(&workspace.to_string_lossy());
+
+    for pattern in patterns {
+        let full_pattern = format!("{}/{}", workspace_pattern, pattern.trim());
+        let matches = match glob::glob(&full_pattern) {
+            Ok(matches) => matches,
+            Err(err) => {
+                collection.skipped.push(format!("{pattern}: {err}"));
+                continue;
+            }
+        };
+
+        for path in matches.filter_map(Result::ok) {
+            if collection.artifacts.len() >= limits.files {
+                // Nothing more is copied; the rest of the matches are only counted.
+                if path.is_file() && seen.insert(path) {
+                    collection.over_limit += 1;
+                }
+                continue;
+            }
+            let canonical = match fs::canonicalize(&path) {
+                Ok(canonical) => canonical,
+                Err(_) => continue,
+            };
+            if !canonical.is_file() || !seen.insert(canonical.clone()) {
+                continue;
+            }
+
+            let relative = match canonical.strip_prefix(workspace) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => {
+                    collection
+                        .skipped
+                        .push(format!("{}: resolves outside the workspace", path.display()));
+                    continue;
+                }
+            };
+            let relative_path = relative.to_string_lossy().replace('\\', "/");
+
+            let size = match fs::metadata(&canonical) {
+                Ok(meta) => meta.len(),
+                Err(err) => {
+                    collection.skipped.push(format!("{relative_path}: {err}"));
+                    continue;
+                }
+            };
+            if size > limits.file_bytes {
+                collection
+                    .skipped
+                    .push(format!("{relative_path}: exceeds {} bytes", limits.file_bytes));
+                continue;
+            }
+            if total_bytes + size > limits.task_bytes {
+                collection.over_limit += 1;
+                continue;
+            }
+
+            let stored_path = destination.join(&relative);
+            let copied = stored_path
+                .parent()
+                .map(fs::create_dir_all)
+                .unwrap_or(Ok(()))
+                .and_then(|_| fs::copy(&canonical, &stored_path));
+            match copied {
+                Ok(size) => {
+                    total_bytes += size;
+                    collection.artifacts.push(CollectedArtifact {
+                        relative_path,
+                        stored_path,
+                        size,
+                    });
+                }
+                Err(err) => collection.skipped.push(format!("{relative_path}: {err}")),
+            }
+        }
+    }
+
+    collection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_path;
+
+    /// A fresh workspace with `files` (relative path, size in bytes) and an empty
+    /// destination next to it.
+    fn scratch_workspace(name: &str, files: &[(&str, usize)]) -> (PathBuf, PathBuf) {
+        let root = scratch_path("artifacts", name);
+        let workspace = root.join("workspace");
+        for (path, size) in files {
+            let path = workspace.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; *size]).unwrap();
+        }
+        fs::create_dir_all(&workspace).unwrap();
+        (fs::canonicalize(&workspace).unwrap(), root.join("stored"))
+    }
+
+    fn collected(collection: &ArtifactCollection) -> Vec<&str> {
+        let mut paths: Vec<&str> = collection
+            .artifacts
+            .iter()
+            .map(|artifact| artifact.relative_path.as_str())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn copies_files_matching_the_patterns() {
+        let (workspace, stored) = scratch_workspace(
+            "glob",
+            &[("out/report.xml", 10), ("out/nested/junit.xml", 20), ("out/notes.txt", 5), ("src/main.rs", 5)],
+        );
+        let patterns = vec!["out/**/*.xml".to_string(), "out/*.xml".to_string()];
+        let collection = collect_artifacts(&workspace, &patterns, &stored);
+        // Overlapping patterns still copy each file once.
+        assert_eq!(collected(&collection), ["out/nested/junit.xml", "out/report.xml"]);
+        assert!(collection.skipped.is_empty(), "{:?}", collection.skipped);
+        assert_eq!(fs::read(stored.join("out/nested/junit.xml")).unwrap().len(), 20);
+    }
+
+    #[test]
+    fn enforces_file_size_task_size_and_count_limits() {
+        assert_eq!(MAX_ARTIFACT_FILE_BYTES, 50 * 1024 * 1024);
+        assert_eq!(MAX_ARTIFACT_TASK_BYTES, 200 * 1024 * 1024);
+        assert_eq!(MAX_ARTIFACT_FILES, 200);
+
+        let (workspace, stored) = scratch_workspace("limits", &[("a.bin", 40), ("b.bin", 120), ("c.bin", 40), ("d.bin", 40)]);
+        let limits = ArtifactLimits {
+            file_bytes: 100,
+            task_bytes: 90,
+            files: 10,
+        };
+        let collection = collect_artifacts_with_limits(&workspace, &["*.bin".to_string()], &stored, limits);
+        assert_eq!(collected(&collection), ["a.bin", "c.bin"]);
+        assert_eq!(collection.skipped.len(), 1, "{:?}", collection.skipped);
+        assert!(collection.skipped[0].starts_with("b.bin: exceeds 100 bytes"), "{:?}", collection.skipped);
+        assert_eq!(collection.over_limit, 1);
+
+        // Past the file limit, further matches are counted once each and not listed.
+        let limits = ArtifactLimits { files: 2, ..ArtifactLimits::default() };
+        let files: Vec<(String, usize)> = (0..50).map(|index| (format!("{index}.log"), 1)).collect();
+        let files: Vec<(&str, usize)> = files.iter().map(|(path, size)| (path.as_str(), *size)).collect();
+        let (workspace, stored) = scratch_workspace("count", &files);
+        let patterns = vec!["*.log".to_string(), "**/*.log".to_string()];
+        let collection = collect_artifacts_with_limits(&workspace, &patterns, &stored, limits);
+        assert_eq!(collection.artifacts.len(), 2);
+        assert!(collection.skipped.is_empty(), "{:?}", collection.skipped);
+        assert_eq!(collection.over_limit, 48);
+    }
+
+    #[test]
+    fn stays_inside_the_workspace() {
+        assert!(validate_artifact_pattern("../secrets/*").is_err());
+        assert!(validate_artifact_pattern("out/../../x").is_err());
+        assert!(validate_artifact_pattern("/etc/passwd").is_err());
+        assert!(validate_artifact_pattern("  ").is_err());
+        assert!(validate_artifact_pattern("out/[").is_err());
+        assert!(validate_artifact_pattern("out/**/*.xml").is_ok());
+
+        #[cfg(unix)]
+        {
+            let (outside, _) = scratch_workspace("outside", &[("secret.txt", 3)]);
+            let (workspace, stored) = scratch_workspace("escape", &[("out/ok.txt", 3)]);
+            std::os::unix::fs::symlink(outside.join("secret.txt"), workspace.join("out/link.txt")).unwrap();
+            let collection = collect_artifacts(&workspace, &["out/*.txt".to_string()], &stored);
+            assert_eq!(collected(&collection), ["out/ok.txt"]);
+            assert!(collection.skipped[0].contains("resolves outside the workspace"), "{:?}", collection.skipped);
+        }
+    }
+
+    #[test]
+    fn only_inert_types_are_opened() {
+        for path in ["report.xml", "coverage/INDEX.TXT", "shot.png", "build.log"] {
+            assert!(is_inert_artifact(Path::new(path)), "{path}");
+        }
+        for path in ["setup.exe", "run.bat", "run.cmd", "a.ps1", "link.lnk", "index.html", "page.htm", "x.svg", "noext"] {
+            assert!(!is_inert_artifact(Path::new(path)), "{path}");
+        }
+    }
+}
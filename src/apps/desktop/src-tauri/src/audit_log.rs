@@ -0,0 +1,471 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// `prev_hash` of the first entry in a log.
+pub(crate) const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log. `hash` covers every other field, including `prev_hash`,
+/// so editing, reordering or deleting an entry breaks the chain from that point on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditEntry {
+    pub(crate) seq: u64,
+    pub(crate) created_at: i64,
+    pub(crate) actor: String,
+    pub(crate) action: String,
+    #[serde(default)]
+    pub(crate) thread_id: Option<String>,
+    #[serde(default)]
+    pub(crate) task_id: Option<String>,
+    pub(crate) details: Value,
+    pub(crate) prev_hash: String,
+    pub(crate) hash: String,
+}
+
+/// The fields an entry's hash is computed over, in a fixed order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HashedFields<'a> {
+    seq: u64,
+    created_at: i64,
+    actor: &'a str,
+    action: &'a str,
+    thread_id: Option<&'a str>,
+    task_id: Option<&'a str>,
+    details: &'a Value,
+    prev_hash: &'a str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditVerification {
+    pub(crate) valid: bool,
+    pub(crate) entries: u64,
+    pub(crate) head_hash: Option<String>,
+    /// 1-based line of the first entry that does not verify.
+    pub(crate) broken_at_line: Option<u64>,
+    pub(crate) error: Option<String>,
+    /// Newest entry the anchor vouches for, when an anchor was checked.
+    pub(crate) anchored_seq: Option<u64>,
+}
+
+/// Sequence number and hash of the newest entry, kept outside the log. Verifying
+/// against it catches entries dropped from the end and a log replaced wholesale,
+/// which the chain alone cannot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditAnchor {
+    pub(crate) seq: u64,
+    pub(crate) hash: String,
+}
+
+/// A repair `AuditLog::open` made so the app could start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AuditRecovery {
+    /// The last entry was only partly written, e.g. the app stopped mid-append. Its
+    /// bytes were moved to `saved_to` and the log continues from the entry before it.
+    TornTail { bytes: u64, saved_to: PathBuf },
+    /// The last complete line is not an entry, so the chain cannot be continued. The
+    /// log was moved to `saved_to` and a new one started.
+    Rotated { saved_to: PathBuf, reason: String },
+}
+
+/// Append-only writer that remembers the tail of the chain between appends.
+#[derive(Debug, Default)]
+pub(crate) struct AuditLog {
+    path: Option<PathBuf>,
+    next_seq: u64,
+    last_hash: String,
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn entry_hash(entry: &AuditEntry) -> Result<String, String> {
+    let fields = HashedFields {
+        seq: entry.seq,
+        created_at: entry.created_at,
+        actor: &entry.actor,
+        action: &entry.action,
+        thread_id: entry.thread_id.as_deref(),
+        task_id: entry.task_id.as_deref(),
+        details: &entry.details,
+        prev_hash: &entry.prev_hash,
+    };
+    let bytes = serde_json::to_vec(&fields).map_err(|err| format!("Failed to encode audit entry: {err}"))?;
+    Ok(sha256_hex(&bytes))
+}
+
+/// The OS account running the app. The desktop app has no accounts of its own, so this
+/// is who approved whatever the entry records.
+pub(crate) fn current_actor() -> String {
+    ["USERNAME", "USER", "LOGNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.trim().is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl AuditEntry {
+    pub(crate) fn anchor(&self) -> AuditAnchor {
+        AuditAnchor {
+            seq: self.seq,
+            hash: self.hash.clone(),
+        }
+    }
+}
+
+/// `audit-log.jsonl` -> `audit-log.<now>.<extension>` in the same directory.
+fn set_aside_path(path: &Path, now: i64, extension: &str) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!("{stem}.{now}.{extension}"))
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, continuing the chain from its last entry. A damaged
+    /// tail is repaired rather than refused, so the app can still start and record the
+    /// repair; `now` names the files anything removed is kept in.
+    pub(crate) fn open(path: PathBuf, now: i64) -> Result<(Self, Option<AuditRecovery>), String> {
+        let mut log = Self {
+            path: Some(path.clone()),
+            next_seq: 1,
+            last_hash: GENESIS_HASH.to_string(),
+        };
+        if !path.exists() {
+            return Ok((log, None));
+        }
+
+        let contents = fs::read(&path).map_err(|err| format!("Failed to read audit log: {err}"))?;
+        let Some(end) = contents.iter().rposition(|byte| !byte.is_ascii_whitespace()).map(|index| index + 1) else {
+            return Ok((log, None));
+        };
+        let start = contents[..end].iter().rposition(|byte| *byte == b'\n').map_or(0, |index| index + 1);
+        let terminated = contents[end..].contains(&b'\n');
+
+        match serde_json::from_slice::<AuditEntry>(&contents[start..end]) {
+            Ok(entry) => {
+                if !terminated {
+                    // Only the newline is missing; appending must not join two entries.
+                    OpenOptions::new()
+                        .append(true)
+                        .open(&path)
+                        .and_then(|mut file| file.write_all(b"\n"))
+                        .map_err(|err| format!("Failed to repair audit log: {err}"))?;
+                }
+                log.next_seq = entry.seq + 1;
+                log.last_hash = entry.hash;
+                Ok((log, None))
+            }
+            Err(_) if !terminated => {
+                let saved_to = set_aside_path(&path, now, "torn");
+                fs::write(&saved_to, &contents[start..])
+                    .map_err(|err| format!("Failed to save the torn audit log entry: {err}"))?;
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| {
+                        file.set_len(start as u64)?;
+                        file.sync_all()
+                    })
+                    .map_err(|err| format!("Failed to truncate the torn audit log entry: {err}"))?;
+                let torn = AuditRecovery::TornTail {
+                    bytes: (contents.len() - start) as u64,
+                    saved_to,
+                };
+                // The entry before the torn one is complete, but may itself be damaged.
+                let (log, rotated) = Self::open(path, now)?;
+                Ok((log, rotated.or(Some(torn))))
+            }
+            Err(err) => {
+                let saved_to = set_aside_path(&path, now, "jsonl");
+                fs::rename(&path, &saved_to).map_err(|err| format!("Failed to set the audit log aside: {err}"))?;
+                Ok((
+                    log,
+                    Some(AuditRecovery::Rotated {
+                        saved_to,
+                        reason: format!("Last entry is not valid: {err}"),
+                    }),
+                ))
+            }
+        }
+    }
+
+    /// Writes and syncs a new entry. Callers record before acting, so a failed append
+    /// stops the action instead of leaving it unrecorded.
+    pub(crate) fn append(
+        &mut self,
+        action: &str,
+        thread_id: Option<&str>,
+        task_id: Option<&str>,
+        details: Value,
+        created_at: i64,
+    ) -> Result<AuditEntry, String> {
+        let path = self.path.as_ref().ok_or_else(|| "Audit log is not open.".to_string())?;
+        let mut entry = AuditEntry {
+            seq: self.next_seq,
+            created_at,
+            actor: current_actor(),
+            action: action.to_string(),
+            thread_id: thread_id.map(str::to_string),
+            task_id: task_id.map(str::to_string),
+            details,
+            prev_hash: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&entry)?;
+
+        let mut line = serde_json::to_string(&entry).map_err(|err| format!("Failed to encode audit entry: {err}"))?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("Failed to open audit log: {err}"))?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|err| format!("Failed to write audit log: {err}"))?;
+
+        self.next_seq += 1;
+        self.last_hash = entry.hash.clone();
+        Ok(entry)
+    }
+}
+
+/// Re-computes every hash and link in the log at `path`, then checks that the entry
+/// `anchor` recorded is still there unchanged. A missing file is an empty log.
+pub(crate) fn verify(path: &Path, anchor: Option<&AuditAnchor>) -> Result<AuditVerification, String> {
+    let mut verification = AuditVerification {
+        valid: true,
+        entries: 0,
+        head_hash: None,
+        broken_at_line: None,
+        error: None,
+        anchored_seq: anchor.map(|anchor| anchor.seq),
+    };
+    if !path.exists() {
+        check_anchor(&mut verification, anchor);
+        return Ok(verification);
+    }
+
+    let file = fs::File::open(path).map_err(|err| format!("Failed to open audit log: {err}"))?;
+    let mut expected_prev = GENESIS_HASH.to_string();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| format!("Failed to read audit log: {err}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index as u64 + 1;
+        let problem = match serde_json::from_str::<AuditEntry>(&line) {
+            Err(err) => Some(format!("Entry is not valid JSON: {err}")),
+            Ok(entry) => {
+                let expected_seq = verification.entries + 1;
+                if entry.seq != expected_seq {
+                    Some(format!("Expected sequence {expected_seq}, found {}.", entry.seq))
+                } else if entry.prev_hash != expected_prev {
+                    Some("Entry does not link to the previous entry.".to_string())
+                } else if entry_hash(&entry)? != entry.hash {
+                    Some("Entry hash does not match its contents.".to_string())
+                } else if anchor.is_some_and(|anchor| anchor.seq == entry.seq && anchor.hash != entry.hash) {
+                    Some("Entry differs from the one recorded when it was written.".to_string())
+                } else {
+                    expected_prev = entry.hash;
+                    None
+                }
+            }
+        };
+
+        if let Some(problem) = problem {
+            verification.valid = false;
+            verification.broken_at_line = Some(line_number);
+            verification.error = Some(problem);
+            break;
+        }
+        verification.entries += 1;
+    }
+
+    if verification.entries > 0 {
+        verification.head_hash = Some(expected_prev);
+    }
+    if verification.valid {
+        check_anchor(&mut verification, anchor);
+    }
+    Ok(verification)
+}
+
+/// Flags a log that ends before the anchored entry. Entries at or below the anchor
+/// were already compared while walking the chain.
+fn check_anchor(verification: &mut AuditVerification, anchor: Option<&AuditAnchor>) {
+    let Some(anchor) = anchor.filter(|anchor| anchor.seq > verification.entries) else {
+        return;
+    };
+    verification.valid = false;
+    verification.error = Some(format!(
+        "The log ends at entry {}, but entry {} was recorded; entries were removed from the end.",
+        verification.entries, anchor.seq
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_path;
+    use serde_json::json;
+
+    fn scratch_log(name: &str) -> PathBuf {
+        let dir = scratch_path("audit", name);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("audit-log.jsonl")
+    }
+
+    /// Writes `count` entries and returns the anchor of the last one.
+    fn write_entries(path: &Path, count: u64) -> AuditAnchor {
+        let (mut log, recovery) = AuditLog::open(path.to_path_buf(), 0).unwrap();
+        assert_eq!(recovery, None);
+        let mut anchor = None;
+        for index in 0..count {
+            let entry = log
+                .append("task.run", Some("thread-1"), None, json!({ "index": index }), index as i64)
+                .unwrap();
+            anchor = Some(entry.anchor());
+        }
+        anchor.unwrap()
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn write_lines(path: &Path, lines: &[String]) {
+        fs::write(path, lines.iter().map(|line| format!("{line}\n")).collect::<String>()).unwrap();
+    }
+
+    #[test]
+    fn appends_a_chain_that_verifies() {
+        let path = scratch_log("append");
+        let anchor = write_entries(&path, 3);
+        // Reopening continues the chain instead of starting a new one.
+        let (mut log, _) = AuditLog::open(path.clone(), 0).unwrap();
+        let fourth = log.append("task.cancel", None, Some("task-1"), json!({}), 9).unwrap();
+        assert_eq!(fourth.seq, 4);
+        assert_eq!(fourth.prev_hash, anchor.hash);
+
+        let verification = verify(&path, Some(&fourth.anchor())).unwrap();
+        assert!(verification.valid, "{:?}", verification.error);
+        assert_eq!(verification.entries, 4);
+        assert_eq!(verification.head_hash.as_deref(), Some(fourth.hash.as_str()));
+        assert_eq!(verification.anchored_seq, Some(4));
+        // An anchor that lags behind the log still holds.
+        assert!(verify(&path, Some(&anchor)).unwrap().valid);
+        assert!(verify(&path.with_file_name("missing.jsonl"), None).unwrap().valid);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn detects_edited_reordered_and_deleted_entries() {
+        let path = scratch_log("tamper");
+        write_entries(&path, 3);
+        let original = lines(&path);
+
+        let mut edited = original.clone();
+        edited[1] = edited[1].replace("\"index\":1", "\"index\":7");
+        write_lines(&path, &edited);
+        let verification = verify(&path, None).unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.broken_at_line, Some(2));
+        assert_eq!(verification.entries, 1);
+
+        let mut reordered = original.clone();
+        reordered.swap(1, 2);
+        write_lines(&path, &reordered);
+        assert_eq!(verify(&path, None).unwrap().broken_at_line, Some(2));
+
+        let mut deleted = original.clone();
+        deleted.remove(1);
+        write_lines(&path, &deleted);
+        assert_eq!(verify(&path, None).unwrap().broken_at_line, Some(2));
+
+        write_lines(&path, &original[..1]);
+        let verification = verify(&path, None).unwrap();
+        assert!(verification.valid, "the chain alone cannot see a shortened log");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn anchor_catches_truncated_and_replaced_logs() {
+        let path = scratch_log("anchor");
+        let anchor = write_entries(&path, 3);
+        let original = lines(&path);
+
+        write_lines(&path, &original[..2]);
+        let verification = verify(&path, Some(&anchor)).unwrap();
+        assert!(!verification.valid);
+        assert!(verification.error.unwrap().contains("removed from the end"));
+
+        fs::remove_file(&path).unwrap();
+        assert!(!verify(&path, Some(&anchor)).unwrap().valid);
+
+        // A freshly written chain of the same length is internally consistent but is
+        // not the log the anchor recorded.
+        let (mut log, _) = AuditLog::open(path.clone(), 0).unwrap();
+        for index in 0..3 {
+            log.append("task.run", None, None, json!({ "forged": index }), index).unwrap();
+        }
+        let verification = verify(&path, Some(&anchor)).unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.broken_at_line, Some(3));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn truncates_a_torn_last_entry() {
+        let path = scratch_log("torn");
+        let anchor = write_entries(&path, 2);
+        let mut contents = fs::read(&path).unwrap();
+        let torn = br#"{"seq":3,"createdAt":5,"act"#;
+        contents.extend_from_slice(torn);
+        fs::write(&path, &contents).unwrap();
+
+        let (mut log, recovery) = AuditLog::open(path.clone(), 42).unwrap();
+        let Some(AuditRecovery::TornTail { bytes, saved_to }) = recovery else {
+            panic!("expected a torn tail, got {recovery:?}");
+        };
+        assert_eq!(bytes, torn.len() as u64);
+        assert_eq!(saved_to, path.with_file_name("audit-log.42.torn"));
+        assert_eq!(fs::read(&saved_to).unwrap(), torn);
+
+        let entry = log.append("audit.recovered", None, None, json!({}), 6).unwrap();
+        assert_eq!(entry.seq, 3);
+        assert_eq!(entry.prev_hash, anchor.hash);
+        assert!(verify(&path, Some(&entry.anchor())).unwrap().valid);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn repairs_a_missing_newline_and_rotates_a_corrupt_tail() {
+        let path = scratch_log("rotate");
+        write_entries(&path, 2);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents.trim_end()).unwrap();
+        let (mut log, recovery) = AuditLog::open(path.clone(), 0).unwrap();
+        assert_eq!(recovery, None);
+        log.append("task.run", None, None, json!({}), 3).unwrap();
+        assert!(verify(&path, None).unwrap().valid);
+
+        let mut corrupt = lines(&path);
+        corrupt.push("not an entry".to_string());
+        write_lines(&path, &corrupt);
+        let (mut log, recovery) = AuditLog::open(path.clone(), 7).unwrap();
+        let Some(AuditRecovery::Rotated { saved_to, .. }) = recovery else {
+            panic!("expected a rotation, got {recovery:?}");
+        };
+        assert_eq!(saved_to, path.with_file_name("audit-log.7.jsonl"));
+        assert_eq!(lines(&saved_to), corrupt);
+        let entry = log.append("audit.recovered", None, None, json!({}), 8).unwrap();
+        assert_eq!((entry.seq, entry.prev_hash.as_str()), (1, GENESIS_HASH));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use crate::oauth::{OAuthConfig, TokenResponse};
+use serde::Serialize;
+use std::thread::Thread;
+use std::time::Duration;
+
+/// Refresh this long before the access token expires, or halfway through the token's
+/// lifetime when it is shorter than twice this.
+pub(crate) const REFRESH_MARGIN_MS: i64 = 60_000;
+/// Network failures retried per refresh before the session is given up.
+pub(crate) const MAX_REFRESH_ATTEMPTS: u32 = 6;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuthState {
+    #[default]
+    SignedOut,
+    SigningIn,
+    Active,
+    Refreshing,
+    Expired,
+}
+
+/// Payload of `auth:state`. Never carries tokens.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuthStateEvent {
+    pub(crate) state: AuthState,
+    pub(crate) expires_at: Option<i64>,
+    pub(crate) scope: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+/// Tokens of the signed-in account. The access token only ever lives here, in
+/// process memory; the refresh token goes to the secret store.
+pub(crate) struct AuthSession {
+    pub(crate) access_token: String,
+    pub(crate) token_type: String,
+    pub(crate) scope: Option<String>,
+    pub(crate) expires_at: Option<i64>,
+    issued_at: i64,
+}
+
+impl AuthSession {
+    pub(crate) fn from_tokens(tokens: &TokenResponse, now_ms: i64) -> Self {
+        Self {
+            access_token: tokens.access_token.clone(),
+            token_type: tokens.token_type.clone(),
+            scope: tokens.scope.clone(),
+            expires_at: tokens.expires_in.map(|secs| now_ms + secs as i64 * 1000),
+            issued_at: now_ms,
+        }
+    }
+}
+
+/// What the UI learns about the session; never the tokens themselves.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuthSessionInfo {
+    pub(crate) token_type: String,
+    pub(crate) scope: Option<String>,
+    pub(crate) expires_at: Option<i64>,
+}
+
+impl From<&AuthSession> for AuthSessionInfo {
+    fn from(session: &AuthSession) -> Self {
+        Self {
+            token_type: session.token_type.clone(),
+            scope: session.scope.clone(),
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Where the refresh token is redeemed.
+#[derive(Debug, Clone)]
+pub(crate) struct RefreshEndpoint {
+    pub(crate) token_url: String,
+    pub(crate) client_id: String,
+    pub(crate) revocation_url: Option<String>,
+}
+
+impl From<OAuthConfig> for RefreshEndpoint {
+    fn from(config: OAuthConfig) -> Self {
+        Self {
+            token_url: config.token_url,
+            client_id: config.client_id,
+            revocation_url: config.revocation_url.filter(|url| !url.trim().is_empty()),
+        }
+    }
+}
+
+/// Session state machine. Every sign-in, restore and sign-out starts a new
+/// generation; a refresh worker stops as soon as its generation is no longer current.
+#[derive(Default)]
+pub(crate) struct SessionManager {
+    state: AuthState,
+    session: Option<AuthSession>,
+    endpoint: Option<RefreshEndpoint>,
+    error: Option<String>,
+    generation: u64,
+    worker: Option<Thread>,
+}
+
+impl SessionManager {
+    pub(crate) fn state(&self) -> AuthState {
+        self.state
+    }
+
+    pub(crate) fn snapshot(&self) -> AuthStateEvent {
+        AuthStateEvent {
+            state: self.state,
+            expires_at: self.session.as_ref().and_then(|session| session.expires_at),
+            scope: self.session.as_ref().and_then(|session| session.scope.clone()),
+            error: self.error.clone(),
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, state: AuthState, error: Option<String>) {
+        self.state = state;
+        self.error = error;
+    }
+
+    pub(crate) fn is_current(&self, generation: u64) -> bool {
+        self.generation == generation
+    }
+
+    /// Ends the current generation and wakes its worker so it can exit.
+    fn next_generation(&mut self) -> u64 {
+        self.generation += 1;
+        if let Some(worker) = self.worker.take() {
+            worker.unpark();
+        }
+        self.generation
+    }
+
+    /// Installs a freshly signed-in session and returns its generation.
+    pub(crate) fn activate(&mut self, session: AuthSession, endpoint: RefreshEndpoint) -> u64 {
+        let generation = self.next_generation();
+        self.session = Some(session);
+        self.endpoint = Some(endpoint);
+        self.set_state(AuthState::Active, None);
+        generation
+    }
+
+    /// Starts restoring a session from the stored refresh token.
+    pub(crate) fn restore(&mut self, endpoint: RefreshEndpoint) -> u64 {
+        let generation = self.next_generation();
+        self.session = None;
+        self.endpoint = Some(endpoint);
+        self.set_state(AuthState::Refreshing, None);
+        generation
+    }
+
+    /// Replaces the tokens after a refresh, keeping the generation.
+    pub(crate) fn refreshed(&mut self, session: AuthSession) {
+        self.session = Some(session);
+        self.set_state(AuthState::Active, None);
+    }
+
+    /// Drops the access token but keeps the state, so `Expired` stays visible.
+    pub(crate) fn expire(&mut self, error: Option<String>) {
+        self.next_generation();
+        self.session = None;
+        self.set_state(AuthState::Expired, error);
+    }
+
+    /// Forgets the session and returns what is needed to revoke it: the access token
+    /// (even if expired) and the endpoint it came from.
+    pub(crate) fn sign_out(&mut self) -> (Option<String>, Option<RefreshEndpoint>) {
+        self.next_generation();
+        self.set_state(AuthState::SignedOut, None);
+        (
+            self.session.take().map(|session| session.access_token),
+            self.endpoint.take(),
+        )
+    }
+
+    pub(crate) fn endpoint(&self) -> Option<RefreshEndpoint> {
+        self.endpoint.clone()
+    }
+
+    /// Registers the refresh worker of `generation`, unless it is already stale.
+    pub(crate) fn set_worker(&mut self, generation: u64, worker: Thread) -> bool {
+        if !self.is_current(generation) {
+            return false;
+        }
+        self.worker = Some(worker);
+        true
+    }
+
+    /// The access token while it is still valid.
+    pub(crate) fn access_token(&self, now_ms: i64) -> Option<&str> {
+        self.session
+            .as_ref()
+            .filter(|session| session.expires_at.is_none_or(|expires_at| expires_at > now_ms))
+            .map(|session| session.access_token.as_str())
+    }
+
+    /// When the worker should refresh; `None` when the token does not expire.
+    pub(crate) fn refresh_due_at(&self) -> Option<i64> {
+        match &self.session {
+            // Restoring: there is no access token until the first refresh.
+            None => Some(0),
+            // A fixed margin would refresh a short-lived token as soon as it is issued,
+            // over and over.
+            Some(session) => session.expires_at.map(|expires_at| {
+                let lifetime = (expires_at - session.issued_at).max(0);
+                expires_at - REFRESH_MARGIN_MS.min(lifetime / 2)
+            }),
+        }
+    }
+}
+
+/// Wait before retry `attempt` (1-based): 1s, 2s, 4s, ... up to a minute.
+pub(crate) fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.saturating_sub(1).min(6)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(expires_in: Option<u64>) -> TokenResponse {
+        TokenResponse {
+            access_token: "access".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in,
+            refresh_token: None,
+            scope: None,
+        }
+    }
+
+    fn endpoint() -> RefreshEndpoint {
+        RefreshEndpoint {
+            token_url: "https://auth.example.test/token".to_string(),
+            client_id: "desktop-client".to_string(),
+            revocation_url: None,
+        }
+    }
+
+    #[test]
+    fn tracks_expiry_and_refresh_time() {
+        let mut manager = SessionManager::default();
+        assert_eq!(manager.state(), AuthState::SignedOut);
+        manager.activate(AuthSession::from_tokens(&tokens(Some(3600)), 1_000), endpoint());
+        assert_eq!(manager.state(), AuthState::Active);
+        assert_eq!(manager.refresh_due_at(), Some(1_000 + 3_600_000 - REFRESH_MARGIN_MS));
+        assert_eq!(manager.access_token(1_000), Some("access"));
+        assert_eq!(manager.access_token(1_000 + 3_600_000), None);
+
+        manager.activate(AuthSession::from_tokens(&tokens(None), 1_000), endpoint());
+        assert_eq!(manager.refresh_due_at(), None);
+        assert_eq!(manager.access_token(i64::MAX), Some("access"));
+    }
+
+    #[test]
+    fn short_lived_tokens_refresh_halfway_through() {
+        let mut manager = SessionManager::default();
+        manager.activate(AuthSession::from_tokens(&tokens(Some(30)), 1_000), endpoint());
+        assert_eq!(manager.refresh_due_at(), Some(1_000 + 15_000));
+        manager.activate(AuthSession::from_tokens(&tokens(Some(120)), 1_000), endpoint());
+        assert_eq!(manager.refresh_due_at(), Some(1_000 + 120_000 - REFRESH_MARGIN_MS));
+        manager.activate(AuthSession::from_tokens(&tokens(Some(0)), 1_000), endpoint());
+        assert_eq!(manager.refresh_due_at(), Some(1_000));
+    }
+
+    #[test]
+    fn new_generations_retire_old_workers() {
+        let mut manager = SessionManager::default();
+        let first = manager.activate(AuthSession::from_tokens(&tokens(Some(60)), 0), endpoint());
+        assert!(manager.set_worker(first, std::thread::current()));
+
+        let second = manager.restore(endpoint());
+        assert!(!manager.is_current(first));
+        assert!(!manager.set_worker(first, std::thread::current()));
+        assert_eq!(manager.state(), AuthState::Refreshing);
+        assert_eq!(manager.access_token(0), None);
+        assert_eq!(manager.refresh_due_at(), Some(0));
+
+        manager.expire(Some("invalid_grant".to_string()));
+        assert!(!manager.is_current(second));
+        let snapshot = manager.snapshot();
+        assert_eq!(snapshot.state, AuthState::Expired);
+        assert_eq!(snapshot.error.as_deref(), Some("invalid_grant"));
+
+        manager.sign_out();
+        assert_eq!(manager.state(), AuthState::SignedOut);
+        assert!(manager.endpoint().is_none());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_minute() {
+        let delays: Vec<u64> = (1..=8).map(|attempt| backoff_delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+}
@@ -1,20 +1,68 @@
-use keyring::{Entry, Error as KeyringError};
+mod artifacts;
+mod audit_log;
+mod auth_session;
+mod command_policy;
+mod credential_profiles;
+mod log_capture;
+mod model_client;
+mod oauth;
+mod oauth_device;
+mod permissions;
+mod process_usage;
+mod redaction;
+mod sandbox;
+mod secret_store;
+mod shell_parse;
+mod store_crypto;
+mod test_results;
+#[cfg(test)]
+mod test_support;
+mod workspace_paths;
+
+use audit_log::{AuditAnchor, AuditLog, AuditRecovery, AuditVerification};
+use auth_session::{AuthSession, AuthSessionInfo, AuthState, AuthStateEvent, RefreshEndpoint, SessionManager};
+use command_policy::{
+    CommandPolicyRule, CommandPolicyRuleInput, PolicyAction, PolicyContext, PolicyEvaluation, PolicyScope,
+};
+use credential_profiles::{CredentialProfile, CredentialProfileInput};
+use log_capture::{read_segments, LogSegment};
+use model_client::{InputMessage, ResponsesRequest};
+use oauth::{CallbackCancel, CallbackServer, OAuthConfig, TokenFailure, TokenResponse};
+use oauth_device::DeviceCancel;
+use permissions::{CommandDecision, PermissionMode};
+use process_usage::TaskProcess;
+use redaction::{Redactor, StreamRedaction};
+use sandbox::{SandboxPolicy, SandboxViolation};
+use secret_store::{EncryptedSecretFile, SecretBackend};
+use shell_parse::ShellDialect;
+use store_crypto::{EncryptedStore, RecoveryWrap, StoreKey};
+use test_results::TestResults;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::TcpListener;
-use std::path::{Component, Path, PathBuf};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
 
 const TOKEN_SERVICE: &str = "codex-app-for-windows";
 const TOKEN_ACCOUNT: &str = "oauth-refresh-token";
 const API_KEY_ACCOUNT: &str = "openai-api-key";
+const DATA_STORE_KEY_ACCOUNT: &str = "data-store-key";
+/// Anchor of the audit log's newest entry, see `AuditAnchor`.
+const AUDIT_ANCHOR_ACCOUNT: &str = "audit-anchor";
+const API_KEY_SECRET_LABEL: &str = "api-key";
+const REFRESH_TOKEN_SECRET_LABEL: &str = "refresh-token";
+const ACCESS_TOKEN_SECRET_LABEL: &str = "access-token";
+
+const PROGRESS_EMIT_INTERVAL: Duration = Duration::from_millis(100);
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const TASK_STATS_HISTORY_LIMIT: usize = 10;
 
 static ID_COUNTER: AtomicU64 = AtomicU64::new(1);
 
@@ -26,6 +74,29 @@ struct ProjectRecord {
     path: String,
     last_accessed_at: i64,
     created_at: i64,
+    /// "untrusted" until the user decides, then "trusted" or "restricted". Only trusted
+    /// projects run tasks, terminal commands or repository-defined git hooks and config.
+    #[serde(default = "default_project_trust")]
+    trust: String,
+    #[serde(default)]
+    trust_decided_at: Option<i64>,
+    /// Credential profile threads in this project use instead of the app-wide key.
+    #[serde(default)]
+    credential_profile_id: Option<String>,
+    /// API base URL for this project, over the profile's or the app-wide one.
+    #[serde(default)]
+    api_base_url: Option<String>,
+}
+
+fn default_project_trust() -> String {
+    "untrusted".to_string()
+}
+
+fn validate_project_trust(trust: &str) -> Result<(), String> {
+    match trust {
+        "untrusted" | "trusted" | "restricted" => Ok(()),
+        _ => Err("Invalid trust state. Use untrusted, trusted, or restricted".to_string()),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +114,10 @@ struct ThreadRecord {
     worktree_branch: Option<String>,
     #[serde(default = "default_permission_mode")]
     permission_mode: String,
+    #[serde(default)]
+    sandbox_enabled: bool,
+    #[serde(default)]
+    network_disabled: bool,
     status: String,
     created_at: i64,
     updated_at: i64,
@@ -75,6 +150,42 @@ struct TaskRecord {
     started_at: Option<i64>,
     finished_at: Option<i64>,
     exit_code: Option<i32>,
+    #[serde(default)]
+    exit_signal: Option<i32>,
+    #[serde(default)]
+    wall_time_ms: Option<u64>,
+    #[serde(default)]
+    cpu_time_ms: Option<u64>,
+    #[serde(default)]
+    peak_memory_bytes: Option<u64>,
+    #[serde(default)]
+    artifact_patterns: Vec<String>,
+    #[serde(default)]
+    test_results: Option<TestResults>,
+    #[serde(default)]
+    sandboxed: bool,
+    #[serde(default)]
+    sandbox_violations: Vec<SandboxViolation>,
+    #[serde(default)]
+    network_disabled: bool,
+    #[serde(default)]
+    sandbox_root: Option<String>,
+    #[serde(default)]
+    approval: Option<TaskApproval>,
+}
+
+/// Confirmation request for a task the command policy flagged. `status` is "pending",
+/// "approved", "rejected", "expired" or "cancelled".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskApproval {
+    reason: String,
+    status: String,
+    requested_at: i64,
+    expires_at: Option<i64>,
+    decided_by: Option<String>,
+    decided_at: Option<i64>,
+    note: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,6 +198,17 @@ struct TaskLogRecord {
     created_at: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskArtifactRecord {
+    id: String,
+    task_id: String,
+    relative_path: String,
+    stored_path: String,
+    size: u64,
+    created_at: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct SkillRecord {
@@ -106,6 +228,17 @@ struct AppSettings {
     default_shell: String,
     default_workspace_root: String,
     theme: String,
+    /// Permission mode for terminal and file commands called without a thread.
+    default_permission_mode: String,
+    /// Rejects terminal and file commands that do not name a thread.
+    require_thread_context: bool,
+    /// Directories outside a workspace that links inside it may point to.
+    allowed_external_roots: Vec<String>,
+    /// Regexes redacted from task logs, messages and audit entries on top of the
+    /// built-in detectors.
+    redaction_patterns: Vec<String>,
+    /// API base URL for threads whose project and credential profile set none.
+    api_base_url: Option<String>,
 }
 
 impl Default for AppSettings {
@@ -115,6 +248,11 @@ impl Default for AppSettings {
             default_shell: "powershell".to_string(),
             default_workspace_root: "".to_string(),
             theme: "light".to_string(),
+            default_permission_mode: default_permission_mode(),
+            require_thread_context: false,
+            allowed_external_roots: Vec::new(),
+            redaction_patterns: Vec::new(),
+            api_base_url: None,
         }
     }
 }
@@ -127,6 +265,12 @@ struct AppDb {
     messages: Vec<ThreadMessage>,
     tasks: Vec<TaskRecord>,
     task_logs: Vec<TaskLogRecord>,
+    #[serde(default)]
+    task_artifacts: Vec<TaskArtifactRecord>,
+    #[serde(default)]
+    command_rules: Vec<CommandPolicyRule>,
+    #[serde(default)]
+    credential_profiles: Vec<CredentialProfile>,
     skills: Vec<SkillRecord>,
     settings: AppSettings,
 }
@@ -155,6 +299,32 @@ struct TaskStatusEvent {
     exit_code: Option<i32>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectTrustRequiredEvent {
+    project_id: String,
+    name: String,
+    path: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskApprovalRequestEvent {
+    task_id: String,
+    thread_id: String,
+    command: String,
+    reason: String,
+    expires_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskSandboxViolationEvent {
+    task_id: String,
+    thread_id: String,
+    violation: SandboxViolation,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct TaskLogEvent {
@@ -164,6 +334,42 @@ struct TaskLogEvent {
     line: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskRunStats {
+    task_id: String,
+    status: String,
+    started_at: Option<i64>,
+    exit_code: Option<i32>,
+    exit_signal: Option<i32>,
+    wall_time_ms: Option<u64>,
+    cpu_time_ms: Option<u64>,
+    peak_memory_bytes: Option<u64>,
+}
+
+impl From<&TaskRecord> for TaskRunStats {
+    fn from(task: &TaskRecord) -> Self {
+        Self {
+            task_id: task.id.clone(),
+            status: task.status.clone(),
+            started_at: task.started_at,
+            exit_code: task.exit_code,
+            exit_signal: task.exit_signal,
+            wall_time_ms: task.wall_time_ms,
+            cpu_time_ms: task.cpu_time_ms,
+            peak_memory_bytes: task.peak_memory_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskStats {
+    command: String,
+    current: TaskRunStats,
+    previous_runs: Vec<TaskRunStats>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct CommandResult {
@@ -189,13 +395,86 @@ struct QueuedTask {
     command: String,
     cwd: String,
     shell: String,
+    artifact_patterns: Vec<String>,
+    sandbox_root: Option<String>,
+    network_disabled: bool,
+}
+
+impl From<&TaskRecord> for QueuedTask {
+    fn from(task: &TaskRecord) -> Self {
+        Self {
+            task_id: task.id.clone(),
+            thread_id: task.thread_id.clone(),
+            command: task.command.clone(),
+            cwd: task.cwd.clone(),
+            shell: task.shell.clone(),
+            artifact_patterns: task.artifact_patterns.clone(),
+            sandbox_root: task.sandbox_root.clone(),
+            network_disabled: task.network_disabled,
+        }
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
     db: Arc<Mutex<AppDb>>,
     queue: Arc<Mutex<VecDeque<QueuedTask>>>,
-    running: Arc<Mutex<HashMap<String, Arc<Mutex<Child>>>>>,
+    running: Arc<Mutex<HashMap<String, Arc<Mutex<TaskProcess>>>>>,
+    audit: Arc<Mutex<AuditLog>>,
+    redactor: Arc<Mutex<Redactor>>,
+    data_store: Arc<Mutex<DataStore>>,
+    secrets: Arc<Mutex<SecretBackend>>,
+    /// Signed-in OAuth session. Never persisted.
+    auth: Arc<Mutex<SessionManager>>,
+    /// OAuth login in progress, keyed by its login id, so it can be cancelled.
+    oauth_login: Arc<Mutex<Option<(String, PendingLogin)>>>,
+}
+
+/// How to stop an OAuth login that is still waiting on the user.
+enum PendingLogin {
+    Loopback(CallbackCancel),
+    Device(DeviceCancel),
+}
+
+impl PendingLogin {
+    fn cancel(&self) {
+        match self {
+            Self::Loopback(cancel) => cancel.cancel(),
+            Self::Device(cancel) => cancel.cancel(),
+        }
+    }
+}
+
+/// Encryption state of `mvp-db.json`. With no key the file is written as plain JSON.
+#[derive(Default)]
+struct DataStore {
+    key: Option<StoreKey>,
+    recovery: Option<RecoveryWrap>,
+    /// Set when the file on disk is encrypted but could not be opened. Nothing is
+    /// written back until it is recovered, so the encrypted data is never replaced.
+    locked: Option<LockedDataStore>,
+}
+
+struct LockedDataStore {
+    envelope: EncryptedStore,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DataStoreStatus {
+    encrypted: bool,
+    locked: bool,
+    key_id: Option<String>,
+    lock_reason: Option<String>,
+}
+
+/// Returned whenever a new recovery key is issued. The key is not stored anywhere.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DataStoreRecovery {
+    key_id: String,
+    recovery_key: String,
 }
 
 impl AppState {
@@ -204,6 +483,12 @@ impl AppState {
             db: Arc::new(Mutex::new(AppDb::default())),
             queue: Arc::new(Mutex::new(VecDeque::new())),
             running: Arc::new(Mutex::new(HashMap::new())),
+            audit: Arc::new(Mutex::new(AuditLog::default())),
+            redactor: Arc::new(Mutex::new(Redactor::default())),
+            data_store: Arc::new(Mutex::new(DataStore::default())),
+            secrets: Arc::new(Mutex::new(SecretBackend::Keyring { service: TOKEN_SERVICE })),
+            auth: Arc::new(Mutex::new(SessionManager::default())),
+            oauth_login: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -229,6 +514,284 @@ fn db_file_path(app: &AppHandle) -> Result<PathBuf, String> {
     Ok(dir.join("mvp-db.json"))
 }
 
+fn artifacts_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("Unable to resolve app data dir: {err}"))?
+        .join("artifacts");
+    fs::create_dir_all(&dir).map_err(|err| format!("Unable to create artifacts dir: {err}"))?;
+    Ok(dir)
+}
+
+fn sandbox_temp_dir(app: &AppHandle, task_id: &str) -> Result<PathBuf, String> {
+    Ok(app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("Unable to resolve app data dir: {err}"))?
+        .join("sandbox-tmp")
+        .join(task_id))
+}
+
+fn audit_log_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("Unable to resolve app data dir: {err}"))?;
+    fs::create_dir_all(&dir).map_err(|err| format!("Unable to create app data dir: {err}"))?;
+    Ok(dir.join("audit-log.jsonl"))
+}
+
+/// Content-addressed copies of files overwritten by `write_workspace_file`, named by
+/// the SHA-256 recorded in the audit log.
+fn audit_blobs_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("Unable to resolve app data dir: {err}"))?
+        .join("audit-blobs");
+    fs::create_dir_all(&dir).map_err(|err| format!("Unable to create audit blob dir: {err}"))?;
+    Ok(dir)
+}
+
+/// Keeps the contents a file write replaces under their hash, which the audit entry
+/// records. While the data store is encrypted the copy is sealed with its key and kept
+/// as `<sha256>.enc`. The copy is written aside and renamed into place, so a crash never
+/// leaves a partial blob under that name, and a blob that does not match its name is
+/// replaced.
+fn keep_overwritten_file(app: &AppHandle, state: &AppState, contents: &[u8], sha256: &str) -> Result<(), String> {
+    let dir = audit_blobs_dir(app)?;
+    // Held until the copy is in place, so a key change cannot leave it under the old key.
+    let data_store = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?;
+    if data_store.locked.is_some() {
+        return Err("Data store is locked. Recover it with the recovery key before making changes.".to_string());
+    }
+    let key = data_store.key.as_ref();
+    let blob = audit_blob_path(&dir, sha256, key);
+    let kept = fs::read(&blob).ok().and_then(|raw| open_audit_blob(key, sha256, raw).ok());
+    if kept.is_some_and(|kept| audit_log::sha256_hex(&kept) == sha256) {
+        return Ok(());
+    }
+    let sealed = seal_audit_blob(key, sha256, contents)?;
+    write_audit_blob(&blob, &sealed).map_err(|err| format!("Failed to keep overwritten file: {err}"))
+}
+
+fn audit_blob_path(dir: &Path, sha256: &str, key: Option<&StoreKey>) -> PathBuf {
+    match key {
+        Some(_) => dir.join(format!("{sha256}.enc")),
+        None => dir.join(sha256),
+    }
+}
+
+fn seal_audit_blob(key: Option<&StoreKey>, sha256: &str, contents: &[u8]) -> Result<Vec<u8>, String> {
+    match key {
+        Some(key) => store_crypto::seal_blob(key, sha256, contents).map(String::into_bytes),
+        None => Ok(contents.to_vec()),
+    }
+}
+
+fn open_audit_blob(key: Option<&StoreKey>, sha256: &str, raw: Vec<u8>) -> Result<Vec<u8>, String> {
+    match key {
+        Some(key) => store_crypto::open_blob(key, sha256, &raw),
+        None => Ok(raw),
+    }
+}
+
+fn write_audit_blob(blob: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp = blob.with_extension(format!("{}.tmp", next_id("blob")));
+    fs::write(&tmp, contents).and_then(|()| fs::rename(&tmp, blob)).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Re-seals every kept blob from the `from` key to the `to` key, where `None` is a plain
+/// copy. Returns how many blobs were rewritten and how many could not be: those are left
+/// as they were, and one that no longer matches its hash is never rewritten.
+fn rewrite_audit_blobs(dir: &Path, from: Option<&StoreKey>, to: Option<&StoreKey>) -> (usize, usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    let (mut rewritten, mut failed) = (0, 0);
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".tmp") {
+            continue;
+        }
+        let (sha256, key) = match name.strip_suffix(".enc") {
+            Some(sha256) => (sha256.to_string(), from),
+            None => (name.clone(), None),
+        };
+        if key.is_none() && name.ends_with(".enc") {
+            failed += 1;
+            continue;
+        }
+        if key.is_none() && to.is_none() {
+            continue;
+        }
+        let source = entry.path();
+        let moved = fs::read(&source)
+            .map_err(|err| err.to_string())
+            .and_then(|raw| open_audit_blob(key, &sha256, raw))
+            .and_then(|contents| {
+                if audit_log::sha256_hex(&contents) != sha256 {
+                    return Err("contents do not match the blob's name".to_string());
+                }
+                let target = audit_blob_path(dir, &sha256, to);
+                let sealed = seal_audit_blob(to, &sha256, &contents)?;
+                write_audit_blob(&target, &sealed).map_err(|err| err.to_string())?;
+                if target != source {
+                    fs::remove_file(&source).map_err(|err| err.to_string())?;
+                }
+                Ok(())
+            });
+        match moved {
+            Ok(()) => rewritten += 1,
+            Err(_) => failed += 1,
+        }
+    }
+    (rewritten, failed)
+}
+
+/// Records the outcome of `rewrite_audit_blobs`. Best effort: the key change it follows
+/// has already happened.
+fn record_audit_blob_rewrite(state: &AppState, key_id: Option<&str>, (rewritten, failed): (usize, usize)) {
+    if rewritten == 0 && failed == 0 {
+        return;
+    }
+    let _ = record_audit(
+        state,
+        "audit.blobs_rewritten",
+        None,
+        None,
+        serde_json::json!({ "keyId": key_id, "rewritten": rewritten, "failed": failed }),
+    );
+}
+
+fn open_audit_log(app: &AppHandle, state: &AppState) -> Result<(), String> {
+    let (log, recovery) = AuditLog::open(audit_log_path(app)?, now_ms())?;
+    *state
+        .audit
+        .lock()
+        .map_err(|_| "Audit log lock poisoned".to_string())? = log;
+    let details = match recovery {
+        None => return Ok(()),
+        Some(AuditRecovery::TornTail { bytes, saved_to }) => serde_json::json!({
+            "repair": "truncated",
+            "discardedBytes": bytes,
+            "savedTo": saved_to.to_string_lossy(),
+        }),
+        Some(AuditRecovery::Rotated { saved_to, reason }) => serde_json::json!({
+            "repair": "rotated",
+            "previousLog": saved_to.to_string_lossy(),
+            "reason": reason,
+        }),
+    };
+    record_audit(state, "audit.recovered", None, None, details)
+}
+
+/// Keeps the newest entry's anchor in the secret store. Best effort: a busy or locked
+/// store leaves the previous anchor in place, which still vouches for every entry up
+/// to it.
+fn save_audit_anchor(state: &AppState, anchor: &AuditAnchor) {
+    let Ok(value) = serde_json::to_string(anchor) else {
+        return;
+    };
+    // Not `lock`: some callers record while holding the store, e.g. when switching backends.
+    if let Ok(mut secrets) = state.secrets.try_lock() {
+        let _ = secrets.save(AUDIT_ANCHOR_ACCOUNT, &value);
+    }
+}
+
+fn load_audit_anchor(state: &AppState) -> Option<AuditAnchor> {
+    load_secret(state, AUDIT_ANCHOR_ACCOUNT)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str(&value).ok())
+}
+
+fn redact_text(state: &AppState, text: &str) -> String {
+    match state.redactor.lock() {
+        Ok(redactor) => redactor.redact(text).into_owned(),
+        // Never let unredacted text through.
+        Err(_) => "[REDACTED:unavailable]".to_string(),
+    }
+}
+
+/// `redact_text` for one line of a task's output; see `Redactor::redact_line`.
+fn redact_output_line(state: &AppState, line: &str, in_private_key: &mut bool) -> String {
+    match state.redactor.lock() {
+        Ok(redactor) => redactor.redact_line(line, in_private_key).into_owned(),
+        Err(_) => "[REDACTED:unavailable]".to_string(),
+    }
+}
+
+/// Builds the redactor from saved settings and the credentials currently in the keyring.
+fn init_redactor(state: &AppState) -> Result<(), String> {
+    let patterns = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?
+        .settings
+        .redaction_patterns
+        .clone();
+    // Profile secrets are labelled by their account so each one is tracked separately.
+    // Everything is loaded before the redactor is locked: loading takes the secret
+    // store lock, and code holding that one records audit entries through the redactor.
+    let mut known = vec![
+        (API_KEY_SECRET_LABEL.to_string(), load_secret(state, API_KEY_ACCOUNT).ok().flatten()),
+        (REFRESH_TOKEN_SECRET_LABEL.to_string(), load_secret(state, TOKEN_ACCOUNT).ok().flatten()),
+    ];
+    for account in secret_accounts(state)? {
+        if !SECRET_ACCOUNTS.contains(&account.as_str()) {
+            let value = load_secret(state, &account).ok().flatten();
+            known.push((account, value));
+        }
+    }
+    let mut redactor = state
+        .redactor
+        .lock()
+        .map_err(|_| "Redactor lock poisoned".to_string())?;
+    // Patterns are validated by `update_app_settings` before they are saved.
+    let _ = redactor.set_custom_patterns(&patterns);
+    for (label, value) in &known {
+        redactor.set_known_secret(label, value.as_deref());
+    }
+    Ok(())
+}
+
+fn set_known_secret(state: &AppState, label: &str, value: Option<&str>) {
+    if let Ok(mut redactor) = state.redactor.lock() {
+        redactor.set_known_secret(label, value);
+    }
+}
+
+fn record_audit(
+    state: &AppState,
+    action: &str,
+    thread_id: Option<&str>,
+    task_id: Option<&str>,
+    mut details: serde_json::Value,
+) -> Result<(), String> {
+    state
+        .redactor
+        .lock()
+        .map_err(|_| "Redactor lock poisoned".to_string())?
+        .redact_json(&mut details);
+    let mut audit = state
+        .audit
+        .lock()
+        .map_err(|_| "Audit log lock poisoned".to_string())?;
+    let entry = audit.append(action, thread_id, task_id, details, now_ms())?;
+    save_audit_anchor(state, &entry.anchor());
+    Ok(())
+}
+
+/// Loads the store, decrypting it with the keyring key when it is encrypted. A store
+/// that cannot be decrypted leaves the app running with an empty, locked database so
+/// the user can recover it.
 fn load_db_from_disk(app: &AppHandle, state: &AppState) -> Result<(), String> {
     let path = db_file_path(app)?;
     if !path.exists() {
@@ -241,7 +804,33 @@ fn load_db_from_disk(app: &AppHandle, state: &AppState) -> Result<(), String> {
         return Ok(());
     }
 
-    let parsed: AppDb = serde_json::from_str(&raw).map_err(|err| format!("Invalid db json: {err}"))?;
+    let mut data_store = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?;
+    let json = match store_crypto::parse_envelope(&raw)? {
+        None => raw,
+        Some(envelope) => {
+            let opened = load_data_store_key(state).and_then(|key| {
+                let key = key.ok_or_else(|| "Data store key is missing from the secret store.".to_string())?;
+                let plaintext = store_crypto::decrypt(&envelope, &key)?;
+                Ok((key, plaintext))
+            });
+            match opened {
+                Ok((key, plaintext)) => {
+                    data_store.key = Some(key);
+                    data_store.recovery = Some(envelope.recovery.clone());
+                    String::from_utf8(plaintext).map_err(|_| "Decrypted db is not valid UTF-8.".to_string())?
+                }
+                Err(reason) => {
+                    data_store.locked = Some(LockedDataStore { envelope, reason });
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let parsed: AppDb = serde_json::from_str(&json).map_err(|err| format!("Invalid db json: {err}"))?;
     if let Ok(mut db) = state.db.lock() {
         *db = parsed;
     }
@@ -258,22 +847,32 @@ fn save_db_to_disk(app: &AppHandle, state: &AppState) -> Result<(), String> {
         serde_json::to_string_pretty(&*db).map_err(|err| format!("Failed serializing db: {err}"))?
     };
 
-    fs::write(path, snapshot).map_err(|err| format!("Failed writing db file: {err}"))
-}
-
-fn ensure_safe_relative_path(relative_path: &str) -> Result<PathBuf, String> {
-    let rel = Path::new(relative_path);
-    if rel.is_absolute() {
-        return Err("Relative path expected.".to_string());
+    let data_store = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?;
+    if data_store.locked.is_some() {
+        return Err("Data store is locked. Recover it with the recovery key before making changes.".to_string());
     }
+    let contents = match (&data_store.key, &data_store.recovery) {
+        (Some(key), Some(recovery)) => store_crypto::encrypt(key, recovery, snapshot.as_bytes())?,
+        _ => snapshot,
+    };
+    write_db_file(&path, &contents)
+}
 
-    for component in rel.components() {
-        if matches!(component, Component::ParentDir | Component::RootDir | Component::Prefix(_)) {
-            return Err("Path traversal is not allowed.".to_string());
-        }
-    }
+/// Replaces the store file through a rename so a crash never leaves half a file,
+/// which matters once the contents are encrypted.
+fn write_db_file(path: &Path, contents: &str) -> Result<(), String> {
+    let tmp = stage_db_file(path, contents)?;
+    fs::rename(&tmp, path).map_err(|err| format!("Failed writing db file: {err}"))
+}
 
-    Ok(rel.to_path_buf())
+/// Writes the next store file next to the current one, for `fs::rename` to put in place.
+fn stage_db_file(path: &Path, contents: &str) -> Result<PathBuf, String> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, contents).map_err(|err| format!("Failed writing db file: {err}"))?;
+    Ok(tmp)
 }
 
 fn canonical_workspace(workspace_path: &str) -> Result<PathBuf, String> {
@@ -287,15 +886,15 @@ fn canonical_workspace(workspace_path: &str) -> Result<PathBuf, String> {
     Ok(canonical)
 }
 
-fn resolve_workspace_target(workspace_root: &Path, relative_path: &str) -> Result<PathBuf, String> {
-    let rel = ensure_safe_relative_path(relative_path)?;
-    let joined = workspace_root.join(rel);
-
-    if !joined.starts_with(workspace_root) {
-        return Err("Target path is outside workspace.".to_string());
-    }
-
-    Ok(joined)
+fn resolve_workspace_target(state: &AppState, workspace_root: &Path, relative_path: &str) -> Result<PathBuf, String> {
+    let allowed_roots = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        workspace_paths::canonical_allowed_roots(&db.settings.allowed_external_roots)
+    };
+    workspace_paths::resolve_within(workspace_root, relative_path, &allowed_roots)
 }
 
 fn update_thread_status(state: &AppState, thread_id: &str, status: &str) {
@@ -307,26 +906,96 @@ fn update_thread_status(state: &AppState, thread_id: &str, status: &str) {
     }
 }
 
-fn is_destructive_command(command: &str) -> bool {
-    let value = command.to_lowercase();
-    let dangerous_patterns = [
-        "git reset --hard",
-        "git clean -fd",
-        "git clean -xdf",
-        "rm -rf",
-        "del /s /q",
-        "rmdir /s /q",
-        "format ",
-        "takeown ",
-    ];
+fn policy_context_for_thread(db: &AppDb, thread_id: &str) -> PolicyContext {
+    PolicyContext {
+        project_id: db
+            .threads
+            .iter()
+            .find(|thread| thread.id == thread_id)
+            .map(|thread| thread.project_id.clone()),
+        thread_id: Some(thread_id.to_string()),
+    }
+}
+
+/// Permission mode, policy scope and isolation that apply to work done for a thread,
+/// or the app-wide defaults when no thread is given.
+struct ExecutionContext {
+    trusted: bool,
+    permission_mode: String,
+    policy: PolicyContext,
+    sandbox_root: Option<PathBuf>,
+    network_disabled: bool,
+}
+
+fn execution_context(db: &AppDb, thread_id: Option<&str>, path: &Path) -> Result<ExecutionContext, String> {
+    let Some(thread_id) = thread_id.filter(|id| !id.trim().is_empty()) else {
+        if db.settings.require_thread_context {
+            return Err("A thread is required to run commands or write files. Select a thread or disable this requirement in settings.".to_string());
+        }
+        return Ok(ExecutionContext {
+            trusted: is_trusted_path(db, path),
+            permission_mode: db.settings.default_permission_mode.clone(),
+            policy: PolicyContext::default(),
+            sandbox_root: None,
+            network_disabled: false,
+        });
+    };
+
+    let thread = db
+        .threads
+        .iter()
+        .find(|t| t.id == thread_id)
+        .ok_or_else(|| "Thread not found".to_string())?;
+    let sandbox_root = if thread.sandbox_enabled {
+        let root = match &thread.worktree_path {
+            Some(path) => path.clone(),
+            None => db
+                .projects
+                .iter()
+                .find(|project| project.id == thread.project_id)
+                .map(|project| project.path.clone())
+                .ok_or_else(|| "Project not found".to_string())?,
+        };
+        Some(canonical_workspace(&root)?)
+    } else {
+        None
+    };
+
+    let project = db.projects.iter().find(|project| project.id == thread.project_id);
+    Ok(ExecutionContext {
+        trusted: project.is_some_and(|project| project.trust == "trusted"),
+        permission_mode: thread.permission_mode.clone(),
+        policy: policy_context_for_thread(db, thread_id),
+        sandbox_root,
+        network_disabled: thread.network_disabled,
+    })
+}
+
+/// Fails when `path` is outside the sandbox root or isolation is unavailable.
+fn check_execution_isolation(context: &ExecutionContext, path: &Path) -> Result<(), String> {
+    if let Some(root) = &context.sandbox_root {
+        sandbox::check_supported()?;
+        if !path.starts_with(root) {
+            return Err("Sandboxed threads can only work inside the project or worktree directory.".to_string());
+        }
+    }
+    if context.network_disabled {
+        sandbox::check_network_isolation_supported()?;
+    }
+    Ok(())
+}
 
-    dangerous_patterns.iter().any(|pattern| value.contains(pattern))
+fn evaluate_task_command(db: &AppDb, command: &str, shell: &str, context: &PolicyContext) -> PolicyEvaluation {
+    command_policy::evaluate(command, ShellDialect::for_task_shell(shell), &db.command_rules, context)
 }
 
-fn validate_permission_mode(mode: &str) -> Result<(), String> {
-    match mode {
-        "safe" | "normal" | "danger-confirm" => Ok(()),
-        _ => Err("Invalid permission mode. Use safe, normal, or danger-confirm".to_string()),
+fn validate_rule_scope(db: &AppDb, rule: &CommandPolicyRule) -> Result<(), String> {
+    command_policy::validate_rule(rule)?;
+    let scope_id = rule.scope_id.as_deref().unwrap_or_default();
+    match rule.scope {
+        PolicyScope::Project if !db.projects.iter().any(|p| p.id == scope_id) => Err("Project not found".to_string()),
+        PolicyScope::Thread if !db.threads.iter().any(|t| t.id == scope_id) => Err("Thread not found".to_string()),
+        _ => Ok(()),
     }
 }
 
@@ -343,12 +1012,13 @@ fn emit_task_status(app: &AppHandle, task_id: &str, thread_id: &str, status: &st
 }
 
 fn append_task_log(app: &AppHandle, state: &AppState, task_id: &str, thread_id: &str, stream: &str, line: &str) {
+    let line = redact_text(state, line);
     if let Ok(mut db) = state.db.lock() {
         db.task_logs.push(TaskLogRecord {
             id: next_id("log"),
             task_id: task_id.to_string(),
             stream: stream.to_string(),
-            line: line.to_string(),
+            line: line.clone(),
             created_at: now_ms(),
         });
     }
@@ -363,37 +1033,230 @@ fn append_task_log(app: &AppHandle, state: &AppState, task_id: &str, thread_id:
             task_id: task_id.to_string(),
             thread_id: thread_id.to_string(),
             stream: stream.to_string(),
-            line: line.to_string(),
+            line,
         },
     );
 
     let _ = save_db_to_disk(app, state);
 }
 
-fn run_shell_command(shell: &str, command: &str, cwd: &str) -> Result<Child, String> {
-    let mut process = if cfg!(target_os = "windows") {
-        let shell_name = shell.to_lowercase();
-        if shell_name.contains("cmd") {
-            let mut cmd = Command::new("cmd");
-            cmd.args(["/C", command]);
-            cmd
-        } else {
-            let mut cmd = Command::new("powershell");
-            cmd.args(["-NoProfile", "-Command", command]);
-            cmd
-        }
-    } else {
-        let mut cmd = Command::new("sh");
-        cmd.args(["-lc", command]);
-        cmd
-    };
-
-    process
-        .current_dir(cwd)
+fn emit_task_progress(app: &AppHandle, state: &AppState, task_id: &str, thread_id: &str, stream: &str, line: &str) {
+    let _ = app.emit(
+        "task:progress",
+        TaskLogEvent {
+            task_id: task_id.to_string(),
+            thread_id: thread_id.to_string(),
+            stream: stream.to_string(),
+            line: redact_text(state, line),
+        },
+    );
+}
+
+fn capture_task_output<R: Read>(
+    app: &AppHandle,
+    state: &AppState,
+    task_id: &str,
+    thread_id: &str,
+    stream: &str,
+    reader: R,
+    sandbox: Option<&SandboxPolicy>,
+) {
+    let mut last_progress_emit: Option<Instant> = None;
+    // Whether this stream is in the middle of printing a private key.
+    let mut in_private_key = false;
+
+    read_segments(reader, |segment| match segment {
+        LogSegment::Progress(line) => {
+            let line = redact_output_line(state, &line, &mut in_private_key);
+            let due = last_progress_emit
+                .map(|at| at.elapsed() >= PROGRESS_EMIT_INTERVAL)
+                .unwrap_or(true);
+            if due {
+                emit_task_progress(app, state, task_id, thread_id, stream, &line);
+                last_progress_emit = Some(Instant::now());
+            }
+        }
+        LogSegment::Line(line) => {
+            let redacted = redact_output_line(state, &line, &mut in_private_key);
+            append_task_log(app, state, task_id, thread_id, stream, &redacted);
+            if let Some(mut violation) = sandbox.and_then(|policy| sandbox::detect_violation(&line, policy, now_ms())) {
+                violation.message = redact_text(state, &violation.message);
+                record_sandbox_violation(app, state, task_id, thread_id, violation);
+            }
+            last_progress_emit = None;
+        }
+    });
+}
+
+fn record_sandbox_violation(
+    app: &AppHandle,
+    state: &AppState,
+    task_id: &str,
+    thread_id: &str,
+    violation: SandboxViolation,
+) {
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(task) = db.tasks.iter_mut().find(|task| task.id == task_id) {
+            if task.sandbox_violations.len() < sandbox::MAX_RECORDED_VIOLATIONS {
+                task.sandbox_violations.push(violation.clone());
+            }
+        }
+    }
+
+    let _ = app.emit(
+        "task:sandbox-violation",
+        TaskSandboxViolationEvent {
+            task_id: task_id.to_string(),
+            thread_id: thread_id.to_string(),
+            violation,
+        },
+    );
+}
+
+fn collect_task_artifacts(app: &AppHandle, state: &AppState, queued: &QueuedTask) {
+    let destination = match artifacts_dir(app) {
+        Ok(dir) => dir.join(&queued.task_id),
+        Err(err) => {
+            append_task_log(app, state, &queued.task_id, &queued.thread_id, "stderr", &err);
+            return;
+        }
+    };
+
+    let collection = artifacts::collect_artifacts(Path::new(&queued.cwd), &queued.artifact_patterns, &destination);
+    for skipped in &collection.skipped {
+        append_task_log(
+            app,
+            state,
+            &queued.task_id,
+            &queued.thread_id,
+            "stderr",
+            &format!("Artifact skipped: {skipped}"),
+        );
+    }
+    if collection.over_limit > 0 {
+        append_task_log(
+            app,
+            state,
+            &queued.task_id,
+            &queued.thread_id,
+            "stderr",
+            &format!(
+                "Artifacts skipped: {} more matching files past the task limit of {} files or {} bytes",
+                collection.over_limit,
+                artifacts::MAX_ARTIFACT_FILES,
+                artifacts::MAX_ARTIFACT_TASK_BYTES
+            ),
+        );
+    }
+
+    if let Ok(mut db) = state.db.lock() {
+        let now = now_ms();
+        db.task_artifacts
+            .extend(collection.artifacts.into_iter().map(|artifact| TaskArtifactRecord {
+                id: next_id("artifact"),
+                task_id: queued.task_id.clone(),
+                relative_path: artifact.relative_path,
+                stored_path: artifact.stored_path.to_string_lossy().to_string(),
+                size: artifact.size,
+                created_at: now,
+            }));
+    }
+}
+
+fn ingest_task_test_results(state: &AppState, task_id: &str) {
+    let (reports, stdout_lines) = match state.db.lock() {
+        Ok(db) => (
+            db.task_artifacts
+                .iter()
+                .filter(|artifact| artifact.task_id == task_id && artifact.relative_path.to_lowercase().ends_with(".xml"))
+                .map(|artifact| (artifact.relative_path.clone(), artifact.stored_path.clone()))
+                .collect::<Vec<_>>(),
+            db.task_logs
+                .iter()
+                .filter(|log| log.task_id == task_id && log.stream == "stdout")
+                .map(|log| log.line.clone())
+                .collect::<Vec<_>>(),
+        ),
+        Err(_) => return,
+    };
+
+    let mut parsed: Vec<TestResults> = reports
+        .iter()
+        .filter_map(|(relative_path, stored_path)| {
+            let xml = fs::read_to_string(stored_path).ok()?;
+            test_results::parse_junit(&xml, relative_path).ok().flatten()
+        })
+        .collect();
+    parsed.extend(test_results::parse_tap(stdout_lines.iter().map(String::as_str)));
+
+    let mut parsed = parsed.into_iter();
+    let Some(mut results) = parsed.next() else {
+        return;
+    };
+    for other in parsed {
+        results.merge(other);
+    }
+    // Failure output from the XML reports is stored as written, so it is redacted here
+    // like the task's own log lines.
+    for failure in &mut results.failures {
+        for text in [&mut failure.name, &mut failure.message, &mut failure.details] {
+            *text = redact_text(state, text);
+        }
+        if let Some(suite) = &mut failure.suite {
+            *suite = redact_text(state, suite);
+        }
+    }
+
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(task) = db.tasks.iter_mut().find(|task| task.id == task_id) {
+            task.test_results = Some(results);
+        }
+    }
+}
+
+fn run_shell_command(
+    shell: &str,
+    command: &str,
+    cwd: &str,
+    sandbox: Option<&SandboxPolicy>,
+    network_disabled: bool,
+) -> Result<(Child, Option<sandbox::Containment>), String> {
+    let mut process = match ShellDialect::for_task_shell(shell) {
+        ShellDialect::Cmd => {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", command]);
+            cmd
+        }
+        ShellDialect::PowerShell => {
+            let mut cmd = Command::new("powershell");
+            cmd.args(["-NoProfile", "-Command", command]);
+            cmd
+        }
+        ShellDialect::Posix => {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-lc", command]);
+            cmd
+        }
+    };
+
+    if network_disabled {
+        sandbox::disable_network(&mut process)?;
+    }
+    if let Some(policy) = sandbox {
+        policy.apply(&mut process)?;
+    }
+
+    let mut child = process
+        .current_dir(cwd)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|err| format!("Failed to spawn task process: {err}"))
+        .map_err(|err| format!("Failed to spawn task process: {err}"))?;
+    let containment = match sandbox {
+        Some(policy) => Some(sandbox::contain(&mut child, policy)?),
+        None => None,
+    };
+    Ok((child, containment))
 }
 
 fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
@@ -411,8 +1274,25 @@ fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
         let _ = save_db_to_disk(&app, &state);
         emit_task_status(&app, &task_id, &thread_id, "running", None);
 
-        let child = match run_shell_command(&queued.shell, &queued.command, &queued.cwd) {
-            Ok(child) => child,
+        let sandbox_policy = match &queued.sandbox_root {
+            Some(root) => sandbox_temp_dir(&app, &task_id)
+                .map(|temp_dir| Some(SandboxPolicy::new(Path::new(root), temp_dir, Path::new(&queued.cwd)))),
+            None => Ok(None),
+        };
+        let spawned = sandbox_policy.and_then(|policy| {
+            run_shell_command(
+                &queued.shell,
+                &queued.command,
+                &queued.cwd,
+                policy.as_ref(),
+                queued.network_disabled,
+            )
+            .map(|(child, containment)| (child, containment, policy))
+        });
+
+        // The containment has to outlive the child: on Windows dropping it kills the task.
+        let (mut child, _containment, sandbox_policy) = match spawned {
+            Ok(spawned) => spawned,
             Err(err) => {
                 if let Ok(mut db) = state.db.lock() {
                     if let Some(task) = db.tasks.iter_mut().find(|task| task.id == task_id) {
@@ -429,39 +1309,31 @@ fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
             }
         };
 
-        let child_arc = Arc::new(Mutex::new(child));
+        let started = Instant::now();
+        let out_reader = child.stdout.take();
+        let err_reader = child.stderr.take();
+        let child_arc = Arc::new(Mutex::new(TaskProcess::new(child)));
         if let Ok(mut running) = state.running.lock() {
             running.insert(task_id.clone(), child_arc.clone());
         }
 
-        let out_reader = {
-            let guard = child_arc.lock();
-            if let Ok(mut guard) = guard {
-                guard.stdout.take().map(BufReader::new)
-            } else {
-                None
-            }
-        };
-
-        let err_reader = {
-            let guard = child_arc.lock();
-            if let Ok(mut guard) = guard {
-                guard.stderr.take().map(BufReader::new)
-            } else {
-                None
-            }
-        };
-
         let app_out = app.clone();
         let state_out = state.clone();
         let task_out = task_id.clone();
         let thread_out = thread_id.clone();
+        let sandbox_out = sandbox_policy.clone();
 
         let stdout_handle = thread::spawn(move || {
             if let Some(reader) = out_reader {
-                for line in reader.lines().map_while(Result::ok) {
-                    append_task_log(&app_out, &state_out, &task_out, &thread_out, "stdout", &line);
-                }
+                capture_task_output(
+                    &app_out,
+                    &state_out,
+                    &task_out,
+                    &thread_out,
+                    "stdout",
+                    reader,
+                    sandbox_out.as_ref(),
+                );
             }
         });
 
@@ -469,26 +1341,36 @@ fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
         let state_err = state.clone();
         let task_err = task_id.clone();
         let thread_err = thread_id.clone();
+        let sandbox_err = sandbox_policy.clone();
 
         let stderr_handle = thread::spawn(move || {
             if let Some(reader) = err_reader {
-                for line in reader.lines().map_while(Result::ok) {
-                    append_task_log(&app_err, &state_err, &task_err, &thread_err, "stderr", &line);
-                }
+                capture_task_output(
+                    &app_err,
+                    &state_err,
+                    &task_err,
+                    &thread_err,
+                    "stderr",
+                    reader,
+                    sandbox_err.as_ref(),
+                );
             }
         });
 
-        let exit_code = {
-            let mut guard = child_arc.lock().ok();
-            if let Some(ref mut guard) = guard {
-                match guard.wait() {
-                    Ok(status) => status.code(),
-                    Err(_) => None,
-                }
-            } else {
-                None
+        let process_exit = loop {
+            let polled = match child_arc.lock() {
+                Ok(mut guard) => guard.try_reap(),
+                Err(_) => break None,
+            };
+            match polled {
+                Ok(Some(exit)) => break Some(exit),
+                Ok(None) => thread::sleep(CHILD_POLL_INTERVAL),
+                Err(_) => break None,
             }
         };
+        let wall_time_ms = started.elapsed().as_millis() as u64;
+        let process_exit = process_exit.unwrap_or_default();
+        let exit_code = process_exit.code;
 
         let _ = stdout_handle.join();
         let _ = stderr_handle.join();
@@ -496,6 +1378,9 @@ fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
         if let Ok(mut running) = state.running.lock() {
             running.remove(&task_id);
         }
+        if let Some(policy) = &sandbox_policy {
+            let _ = fs::remove_dir_all(&policy.temp_dir);
+        }
 
         let mut final_status = "success".to_string();
         if let Some(code) = exit_code {
@@ -515,9 +1400,20 @@ fn spawn_task_worker(app: AppHandle, state: AppState, queued: QueuedTask) {
                 }
                 task.finished_at = Some(now_ms());
                 task.exit_code = exit_code;
+                task.exit_signal = process_exit.signal;
+                task.wall_time_ms = Some(wall_time_ms);
+                task.cpu_time_ms = process_exit.cpu_time_ms;
+                task.peak_memory_bytes = process_exit.peak_memory_bytes;
             }
         }
 
+        if final_status != "cancelled" {
+            if !queued.artifact_patterns.is_empty() {
+                collect_task_artifacts(&app, &state, &queued);
+            }
+            ingest_task_test_results(&state, &task_id);
+        }
+
         update_thread_status(&state, &thread_id, &final_status);
         let _ = save_db_to_disk(&app, &state);
         emit_task_status(&app, &task_id, &thread_id, &final_status, exit_code);
@@ -582,6 +1478,10 @@ fn create_project(
         path: canonical_str.clone(),
         last_accessed_at: now,
         created_at: now,
+        trust: default_project_trust(),
+        trust_decided_at: None,
+        credential_profile_id: None,
+        api_base_url: None,
     };
 
     if let Ok(mut db) = state.db.lock() {
@@ -593,6 +1493,97 @@ fn create_project(
         }
     }
 
+    save_db_to_disk(&app, &state)?;
+    request_project_trust(&app, &project);
+    Ok(project)
+}
+
+/// Asks the UI to prompt for a trust decision when the project has none yet.
+fn request_project_trust(app: &AppHandle, project: &ProjectRecord) {
+    if project.trust == "untrusted" {
+        let _ = app.emit(
+            "project:trust-required",
+            ProjectTrustRequiredEvent {
+                project_id: project.id.clone(),
+                name: project.name.clone(),
+                path: project.path.clone(),
+            },
+        );
+    }
+}
+
+/// Project that owns `path`, either under its folder or under one of its threads'
+/// worktrees. The deepest project folder wins when projects are nested.
+fn project_for_path<'a>(db: &'a AppDb, path: &Path) -> Option<&'a ProjectRecord> {
+    let by_folder = db
+        .projects
+        .iter()
+        .filter(|project| path.starts_with(&project.path))
+        .max_by_key(|project| project.path.len());
+    by_folder.or_else(|| {
+        db.threads
+            .iter()
+            .filter(|thread| {
+                thread
+                    .worktree_path
+                    .as_ref()
+                    .and_then(|worktree| fs::canonicalize(worktree).ok())
+                    .is_some_and(|worktree| path.starts_with(worktree))
+            })
+            .find_map(|thread| db.projects.iter().find(|project| project.id == thread.project_id))
+    })
+}
+
+fn is_trusted_path(db: &AppDb, path: &Path) -> bool {
+    project_for_path(db, path).is_some_and(|project| project.trust == "trusted")
+}
+
+fn require_trusted_project(project: Option<&ProjectRecord>) -> Result<(), String> {
+    match project {
+        Some(project) if project.trust == "trusted" => Ok(()),
+        Some(project) if project.trust == "restricted" => Err(format!(
+            "Project {} is in restricted mode. Trust it to run tasks and commands.",
+            project.name
+        )),
+        Some(project) => Err(format!(
+            "Project {} is not trusted yet. Trust it to run tasks and commands.",
+            project.name
+        )),
+        None => Err("Commands can only run inside a trusted project.".to_string()),
+    }
+}
+
+#[tauri::command]
+fn set_project_trust(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    trust: String,
+) -> Result<ProjectRecord, String> {
+    validate_project_trust(&trust)?;
+
+    let mut updated: Option<ProjectRecord> = None;
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(project) = db.projects.iter_mut().find(|p| p.id == project_id) {
+            record_audit(
+                &state,
+                "project.trust",
+                None,
+                None,
+                serde_json::json!({
+                    "projectId": project.id,
+                    "path": project.path,
+                    "from": project.trust,
+                    "to": trust,
+                }),
+            )?;
+            project.trust = trust;
+            project.trust_decided_at = Some(now_ms());
+            updated = Some(project.clone());
+        }
+    }
+
+    let project = updated.ok_or_else(|| "Project not found".to_string())?;
     save_db_to_disk(&app, &state)?;
     Ok(project)
 }
@@ -611,12 +1602,18 @@ fn list_projects(state: State<AppState>) -> Result<Vec<ProjectRecord>, String> {
 
 #[tauri::command]
 fn touch_project(app: AppHandle, state: State<AppState>, project_id: String) -> Result<(), String> {
+    let mut touched: Option<ProjectRecord> = None;
     if let Ok(mut db) = state.db.lock() {
         if let Some(item) = db.projects.iter_mut().find(|p| p.id == project_id) {
             item.last_accessed_at = now_ms();
+            touched = Some(item.clone());
         }
     }
-    save_db_to_disk(&app, &state)
+    save_db_to_disk(&app, &state)?;
+    if let Some(project) = touched {
+        request_project_trust(&app, &project);
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -653,6 +1650,8 @@ fn create_thread(
         worktree_path: None,
         worktree_branch: None,
         permission_mode: default_permission_mode(),
+        sandbox_enabled: false,
+        network_disabled: false,
         status: "idle".to_string(),
         created_at: now,
         updated_at: now,
@@ -688,12 +1687,22 @@ fn add_thread_message(
     thread_id: String,
     role: String,
     content: String,
+) -> Result<ThreadMessage, String> {
+    append_thread_message(&app, &state, &thread_id, &role, &content)
+}
+
+fn append_thread_message(
+    app: &AppHandle,
+    state: &AppState,
+    thread_id: &str,
+    role: &str,
+    content: &str,
 ) -> Result<ThreadMessage, String> {
     let message = ThreadMessage {
         id: next_id("msg"),
-        thread_id: thread_id.clone(),
-        role,
-        content,
+        thread_id: thread_id.to_string(),
+        role: role.to_string(),
+        content: redact_text(state, content),
         created_at: now_ms(),
     };
 
@@ -704,7 +1713,7 @@ fn add_thread_message(
         }
     }
 
-    save_db_to_disk(&app, &state)?;
+    save_db_to_disk(app, state)?;
     Ok(message)
 }
 
@@ -724,6 +1733,7 @@ fn list_thread_messages(state: State<AppState>, thread_id: String) -> Result<Vec
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn run_task(
     app: AppHandle,
     state: State<AppState>,
@@ -731,13 +1741,24 @@ fn run_task(
     command: String,
     cwd: Option<String>,
     shell: Option<String>,
-    confirm_destructive: Option<bool>,
+    artifacts: Option<Vec<String>>,
+    disable_network: Option<bool>,
+    approval_timeout_secs: Option<u64>,
 ) -> Result<TaskRecord, String> {
     let resolved_cwd = cwd.unwrap_or_default();
     if resolved_cwd.trim().is_empty() {
         return Err("Task cwd is required".to_string());
     }
 
+    let artifact_patterns: Vec<String> = artifacts
+        .unwrap_or_default()
+        .iter()
+        .map(|pattern| pattern.trim().to_string())
+        .collect();
+    for pattern in &artifact_patterns {
+        artifacts::validate_artifact_pattern(pattern)?;
+    }
+
     let canonical = canonical_workspace(&resolved_cwd)?;
     let cwd_string = canonical.to_string_lossy().to_string();
     let shell_name = {
@@ -760,53 +1781,161 @@ fn run_task(
         }
     };
 
-    let thread_permission_mode = {
+    let (mut context, evaluation) = {
         let db = state
             .db
             .lock()
             .map_err(|_| "Database lock poisoned".to_string())?;
-        let thread = db
-            .threads
-            .iter()
-            .find(|t| t.id == thread_id)
-            .ok_or_else(|| "Thread not found".to_string())?;
-        thread.permission_mode.clone()
-    };
-
-    let destructive = is_destructive_command(&command);
-    let confirmed = confirm_destructive.unwrap_or(false);
-    if destructive {
-        match thread_permission_mode.as_str() {
-            "safe" => {
-                return Err(
-                    "Command blocked by safe mode. Change session permission to run destructive commands."
-                        .to_string(),
-                )
-            }
-            "normal" | "danger-confirm" => {
-                if !confirmed {
-                    return Err(
-                        "Destructive command requires explicit confirmation for this session.".to_string(),
-                    );
-                }
-            }
-            _ => {}
+        let context = execution_context(&db, Some(&thread_id), &canonical)?;
+        if !context.trusted {
+            let project = db
+                .threads
+                .iter()
+                .find(|thread| thread.id == thread_id)
+                .and_then(|thread| db.projects.iter().find(|project| project.id == thread.project_id));
+            require_trusted_project(project)?;
         }
+        let evaluation = evaluate_task_command(&db, &command, &shell_name, &context.policy);
+        (context, evaluation)
+    };
+    // A task can opt into network-off mode but cannot opt out of its thread's.
+    context.network_disabled |= disable_network.unwrap_or(false);
+    check_execution_isolation(&context, &canonical)?;
+    let ExecutionContext {
+        permission_mode: thread_permission_mode,
+        sandbox_root,
+        network_disabled,
+        ..
+    } = context;
+
+    let permission_mode = PermissionMode::from_stored(&thread_permission_mode);
+    let (rejection, confirmation_reason) = match permissions::decide_command(permission_mode, &evaluation) {
+        CommandDecision::Reject(reason) => (Some(reason), None),
+        CommandDecision::Confirm(reason) => (None, Some(reason)),
+        CommandDecision::Run => (None, None),
+    };
+    if let Some(reason) = rejection {
+        record_audit(
+            &state,
+            "task.rejected",
+            Some(&thread_id),
+            None,
+            serde_json::json!({
+                "command": command,
+                "cwd": cwd_string,
+                "shell": shell_name,
+                "permissionMode": thread_permission_mode,
+                "policyAction": evaluation.action,
+                "reason": reason,
+            }),
+        )?;
+        return Err(reason);
     }
 
+    let created_at = now_ms();
+    // Commands that need confirmation wait for `approve_task` instead of failing. There
+    // is no way to confirm up front, so they only ever run through an approval.
+    let approval = confirmation_reason.map(|reason| TaskApproval {
+        reason,
+        status: "pending".to_string(),
+        requested_at: created_at,
+        expires_at: approval_timeout_secs.map(|secs| created_at + (secs as i64).saturating_mul(1000)),
+        decided_by: None,
+        decided_at: None,
+        note: None,
+    });
     let task = TaskRecord {
         id: next_id("task"),
         thread_id: thread_id.clone(),
         command: command.clone(),
         cwd: cwd_string.clone(),
         shell: shell_name.clone(),
-        status: "queued".to_string(),
-        created_at: now_ms(),
+        status: if approval.is_some() { "awaiting_approval" } else { "queued" }.to_string(),
+        created_at,
         started_at: None,
         finished_at: None,
         exit_code: None,
+        exit_signal: None,
+        wall_time_ms: None,
+        cpu_time_ms: None,
+        peak_memory_bytes: None,
+        artifact_patterns: artifact_patterns.clone(),
+        test_results: None,
+        sandboxed: sandbox_root.is_some(),
+        sandbox_violations: Vec::new(),
+        network_disabled,
+        sandbox_root: sandbox_root.map(|root| root.to_string_lossy().to_string()),
+        approval,
     };
 
+    if let Some(approval) = &task.approval {
+        record_audit(
+            &state,
+            "task.approval_requested",
+            Some(&thread_id),
+            Some(&task.id),
+            serde_json::json!({
+                "command": command,
+                "cwd": task.cwd,
+                "shell": task.shell,
+                "permissionMode": thread_permission_mode,
+                "reason": approval.reason,
+                "expiresAt": approval.expires_at,
+            }),
+        )?;
+        if let Ok(mut db) = state.db.lock() {
+            db.tasks.push(task.clone());
+        }
+        update_thread_status(&state, &thread_id, "awaiting_approval");
+        save_db_to_disk(&app, &state)?;
+        emit_task_status(&app, &task.id, &thread_id, "awaiting_approval", None);
+        let _ = app.emit(
+            "task:approval-requested",
+            TaskApprovalRequestEvent {
+                task_id: task.id.clone(),
+                thread_id: thread_id.clone(),
+                command: task.command.clone(),
+                reason: approval.reason.clone(),
+                expires_at: approval.expires_at,
+            },
+        );
+        if let Some(expires_at) = approval.expires_at {
+            schedule_approval_expiry(app, state.inner().clone(), task.id.clone(), expires_at);
+        }
+        return Ok(task);
+    }
+
+    if evaluation.action == PolicyAction::Confirm {
+        // Autonomous mode ran a flagged command without asking.
+        record_audit(
+            &state,
+            "command.auto_approved",
+            Some(&thread_id),
+            Some(&task.id),
+            serde_json::json!({
+                "command": command,
+                "permissionMode": thread_permission_mode,
+                "reason": evaluation.reason(),
+            }),
+        )?;
+    }
+    record_audit(
+        &state,
+        "task.run",
+        Some(&thread_id),
+        Some(&task.id),
+        serde_json::json!({
+            "command": command,
+            "cwd": task.cwd,
+            "shell": task.shell,
+            "permissionMode": thread_permission_mode,
+            "policyAction": evaluation.action,
+            "sandboxed": task.sandboxed,
+            "networkDisabled": task.network_disabled,
+            "artifactPatterns": task.artifact_patterns,
+        }),
+    )?;
+
     if let Ok(mut db) = state.db.lock() {
         db.tasks.push(task.clone());
         if let Some(thread) = db.threads.iter_mut().find(|t| t.id == thread_id) {
@@ -816,13 +1945,7 @@ fn run_task(
     }
 
     if let Ok(mut queue) = state.queue.lock() {
-        queue.push_back(QueuedTask {
-            task_id: task.id.clone(),
-            thread_id: thread_id.clone(),
-            command,
-            cwd: cwd_string,
-            shell: shell_name,
-        });
+        queue.push_back(QueuedTask::from(&task));
     }
 
     save_db_to_disk(&app, &state)?;
@@ -839,11 +1962,18 @@ fn set_thread_permission(
     thread_id: String,
     permission_mode: String,
 ) -> Result<ThreadRecord, String> {
-    validate_permission_mode(permission_mode.as_str())?;
+    PermissionMode::parse(permission_mode.as_str())?;
 
     let mut updated: Option<ThreadRecord> = None;
     if let Ok(mut db) = state.db.lock() {
         if let Some(thread) = db.threads.iter_mut().find(|t| t.id == thread_id) {
+            record_audit(
+                &state,
+                "thread.permission",
+                Some(&thread_id),
+                None,
+                serde_json::json!({ "from": thread.permission_mode, "to": permission_mode }),
+            )?;
             thread.permission_mode = permission_mode;
             thread.updated_at = now_ms();
             updated = Some(thread.clone());
@@ -855,6 +1985,65 @@ fn set_thread_permission(
     Ok(thread)
 }
 
+#[tauri::command]
+fn set_thread_sandbox(app: AppHandle, state: State<AppState>, thread_id: String, enabled: bool) -> Result<ThreadRecord, String> {
+    if enabled {
+        sandbox::check_supported()?;
+    }
+
+    let mut updated: Option<ThreadRecord> = None;
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(thread) = db.threads.iter_mut().find(|t| t.id == thread_id) {
+            record_audit(
+                &state,
+                "thread.sandbox",
+                Some(&thread_id),
+                None,
+                serde_json::json!({ "from": thread.sandbox_enabled, "to": enabled }),
+            )?;
+            thread.sandbox_enabled = enabled;
+            thread.updated_at = now_ms();
+            updated = Some(thread.clone());
+        }
+    }
+
+    let thread = updated.ok_or_else(|| "Thread not found".to_string())?;
+    save_db_to_disk(&app, &state)?;
+    Ok(thread)
+}
+
+#[tauri::command]
+fn set_thread_network(
+    app: AppHandle,
+    state: State<AppState>,
+    thread_id: String,
+    disabled: bool,
+) -> Result<ThreadRecord, String> {
+    if disabled {
+        sandbox::check_network_isolation_supported()?;
+    }
+
+    let mut updated: Option<ThreadRecord> = None;
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(thread) = db.threads.iter_mut().find(|t| t.id == thread_id) {
+            record_audit(
+                &state,
+                "thread.network",
+                Some(&thread_id),
+                None,
+                serde_json::json!({ "from": thread.network_disabled, "to": disabled }),
+            )?;
+            thread.network_disabled = disabled;
+            thread.updated_at = now_ms();
+            updated = Some(thread.clone());
+        }
+    }
+
+    let thread = updated.ok_or_else(|| "Thread not found".to_string())?;
+    save_db_to_disk(&app, &state)?;
+    Ok(thread)
+}
+
 #[tauri::command]
 fn cancel_task(app: AppHandle, state: State<AppState>, task_id: String) -> Result<(), String> {
     let mut cancelled_thread_id: Option<String> = None;
@@ -881,6 +2070,9 @@ fn cancel_task(app: AppHandle, state: State<AppState>, task_id: String) -> Resul
         if let Some(task) = db.tasks.iter_mut().find(|item| item.id == task_id) {
             task.status = "cancelled".to_string();
             task.finished_at = Some(now_ms());
+            if let Some(approval) = task.approval.as_mut().filter(|approval| approval.status == "pending") {
+                approval.status = "cancelled".to_string();
+            }
             cancelled_thread_id = Some(task.thread_id.clone());
         }
     }
@@ -895,65 +2087,537 @@ fn cancel_task(app: AppHandle, state: State<AppState>, task_id: String) -> Resul
     Ok(())
 }
 
-#[tauri::command]
-fn list_tasks(state: State<AppState>, thread_id: String) -> Result<Vec<TaskRecord>, String> {
-    let mut tasks: Vec<TaskRecord> = state
-        .db
-        .lock()
-        .map_err(|_| "Database lock poisoned".to_string())?
-        .tasks
-        .iter()
-        .filter(|task| task.thread_id == thread_id)
-        .cloned()
-        .collect();
-    tasks.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-    Ok(tasks)
-}
+/// Moves an unanswered approval request to "expired" once its deadline passes.
+fn expire_task_approval(app: &AppHandle, state: &AppState, task_id: &str) {
+    let mut expired: Option<TaskRecord> = None;
+    if let Ok(mut db) = state.db.lock() {
+        if let Some(task) = db.tasks.iter_mut().find(|task| task.id == task_id) {
+            let now = now_ms();
+            if let Some(approval) = task.approval.as_mut().filter(|approval| {
+                approval.status == "pending" && approval.expires_at.is_some_and(|expires_at| expires_at <= now)
+            }) {
+                approval.status = "expired".to_string();
+                approval.decided_at = Some(now);
+                task.status = "expired".to_string();
+                task.finished_at = Some(now);
+                expired = Some(task.clone());
+            }
+        }
+    }
 
-#[tauri::command]
-fn list_task_logs(state: State<AppState>, task_id: String) -> Result<Vec<TaskLogRecord>, String> {
-    let mut logs: Vec<TaskLogRecord> = state
-        .db
-        .lock()
-        .map_err(|_| "Database lock poisoned".to_string())?
-        .task_logs
-        .iter()
-        .filter(|log| log.task_id == task_id)
-        .cloned()
-        .collect();
-    logs.sort_by(|a, b| a.created_at.cmp(&b.created_at));
-    Ok(logs)
+    let Some(task) = expired else {
+        return;
+    };
+    let _ = record_audit(
+        state,
+        "task.approval_expired",
+        Some(&task.thread_id),
+        Some(&task.id),
+        serde_json::json!({ "command": task.command }),
+    );
+    update_thread_status(state, &task.thread_id, "expired");
+    let _ = save_db_to_disk(app, state);
+    emit_task_status(app, &task.id, &task.thread_id, "expired", None);
 }
 
-#[tauri::command]
-fn set_max_parallel_tasks(app: AppHandle, state: State<AppState>, value: usize) -> Result<(), String> {
-    if value == 0 {
-        return Err("max_parallel_tasks must be >= 1".to_string());
-    }
+fn schedule_approval_expiry(app: AppHandle, state: AppState, task_id: String, expires_at: i64) {
+    thread::spawn(move || {
+        let remaining = expires_at.saturating_sub(now_ms()).max(0) as u64;
+        thread::sleep(Duration::from_millis(remaining));
+        expire_task_approval(&app, &state, &task_id);
+    });
+}
 
-    if let Ok(mut db) = state.db.lock() {
-        db.settings.max_parallel_tasks = value;
+/// Re-arms expiry timers for approval requests that were pending when the app closed.
+fn resume_pending_approvals(app: &AppHandle, state: &AppState) {
+    let pending: Vec<(String, i64)> = match state.db.lock() {
+        Ok(db) => db
+            .tasks
+            .iter()
+            .filter(|task| task.status == "awaiting_approval")
+            .filter_map(|task| Some((task.id.clone(), task.approval.as_ref()?.expires_at?)))
+            .collect(),
+        Err(_) => return,
+    };
+    for (task_id, expires_at) in pending {
+        schedule_approval_expiry(app.clone(), state.clone(), task_id, expires_at);
     }
-
-    save_db_to_disk(&app, &state)?;
-    schedule_tasks(app, state.inner().clone());
-    Ok(())
 }
 
-#[tauri::command]
-fn get_app_settings(state: State<AppState>) -> Result<AppSettings, String> {
-    let db = state
-        .db
-        .lock()
-        .map_err(|_| "Database lock poisoned".to_string())?;
-    Ok(db.settings.clone())
+fn decided_by(approver: Option<String>) -> String {
+    approver
+        .map(|approver| approver.trim().to_string())
+        .filter(|approver| !approver.is_empty())
+        .unwrap_or_else(audit_log::current_actor)
 }
 
 #[tauri::command]
-fn update_app_settings(app: AppHandle, state: State<AppState>, settings: AppSettings) -> Result<(), String> {
-    if settings.max_parallel_tasks == 0 {
-        return Err("max_parallel_tasks must be >= 1".to_string());
-    }
+fn approve_task(
+    app: AppHandle,
+    state: State<AppState>,
+    task_id: String,
+    approver: Option<String>,
+    note: Option<String>,
+) -> Result<TaskRecord, String> {
+    expire_task_approval(&app, &state, &task_id);
+    let approver = decided_by(approver);
+
+    let approved = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let thread_id = db
+            .tasks
+            .iter()
+            .find(|task| task.id == task_id)
+            .map(|task| task.thread_id.clone())
+            .ok_or_else(|| "Task not found".to_string())?;
+        let project = db
+            .threads
+            .iter()
+            .find(|thread| thread.id == thread_id)
+            .and_then(|thread| db.projects.iter().find(|project| project.id == thread.project_id));
+        require_trusted_project(project)?;
+
+        let task = db
+            .tasks
+            .iter()
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| "Task not found".to_string())?;
+        if task.status != "awaiting_approval" {
+            return Err(format!("Task is not awaiting approval (status: {}).", task.status));
+        }
+
+        // The thread's mode, rules and isolation may have changed while the task waited;
+        // the approval only stands in for a confirmation the thread would still ask for.
+        let cwd = PathBuf::from(&task.cwd);
+        let mut context = execution_context(&db, Some(&thread_id), &cwd)?;
+        context.network_disabled |= task.network_disabled;
+        let evaluation = evaluate_task_command(&db, &task.command, &task.shell, &context.policy);
+        let permission_mode = PermissionMode::from_stored(&context.permission_mode);
+        let refusal = match check_execution_isolation(&context, &cwd) {
+            Err(reason) => Some(reason),
+            Ok(()) => match permissions::decide_command(permission_mode, &evaluation) {
+                CommandDecision::Reject(reason) => Some(reason),
+                CommandDecision::Confirm(_) | CommandDecision::Run => None,
+            },
+        };
+
+        let task = db
+            .tasks
+            .iter_mut()
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| "Task not found".to_string())?;
+        let now = now_ms();
+        if let Some(reason) = refusal {
+            record_audit(
+                &state,
+                "task.rejected",
+                Some(&task.thread_id),
+                Some(&task.id),
+                serde_json::json!({
+                    "command": task.command,
+                    "cwd": task.cwd,
+                    "shell": task.shell,
+                    "permissionMode": context.permission_mode,
+                    "policyAction": evaluation.action,
+                    "approver": approver,
+                    "reason": reason,
+                }),
+            )?;
+            if let Some(approval) = task.approval.as_mut() {
+                approval.status = "rejected".to_string();
+                approval.decided_by = Some(approver);
+                approval.decided_at = Some(now);
+                approval.note = Some(reason.clone());
+            }
+            task.status = "rejected".to_string();
+            task.finished_at = Some(now);
+            Err((task.clone(), reason))
+        } else {
+            record_audit(
+                &state,
+                "task.approved",
+                Some(&task.thread_id),
+                Some(&task.id),
+                serde_json::json!({
+                    "command": task.command,
+                    "cwd": task.cwd,
+                    "shell": task.shell,
+                    "permissionMode": context.permission_mode,
+                    "policyAction": evaluation.action,
+                    "approver": approver,
+                    "note": note,
+                    "sandboxed": context.sandbox_root.is_some(),
+                    "networkDisabled": context.network_disabled,
+                }),
+            )?;
+            if let Some(approval) = task.approval.as_mut() {
+                approval.status = "approved".to_string();
+                approval.decided_by = Some(approver);
+                approval.decided_at = Some(now);
+                approval.note = note;
+            }
+            task.sandboxed = context.sandbox_root.is_some();
+            task.sandbox_root = context.sandbox_root.map(|root| root.to_string_lossy().to_string());
+            task.network_disabled = context.network_disabled;
+            task.status = "queued".to_string();
+            Ok(task.clone())
+        }
+    };
+
+    let approved = match approved {
+        Ok(approved) => approved,
+        Err((rejected, reason)) => {
+            update_thread_status(&state, &rejected.thread_id, "rejected");
+            save_db_to_disk(&app, &state)?;
+            emit_task_status(&app, &rejected.id, &rejected.thread_id, "rejected", None);
+            return Err(reason);
+        }
+    };
+
+    if let Ok(mut queue) = state.queue.lock() {
+        queue.push_back(QueuedTask::from(&approved));
+    }
+    update_thread_status(&state, &approved.thread_id, "queued");
+    save_db_to_disk(&app, &state)?;
+    emit_task_status(&app, &approved.id, &approved.thread_id, "queued", None);
+    schedule_tasks(app, state.inner().clone());
+    Ok(approved)
+}
+
+#[tauri::command]
+fn reject_task(
+    app: AppHandle,
+    state: State<AppState>,
+    task_id: String,
+    approver: Option<String>,
+    reason: Option<String>,
+) -> Result<TaskRecord, String> {
+    expire_task_approval(&app, &state, &task_id);
+    let approver = decided_by(approver);
+
+    let rejected = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let task = db
+            .tasks
+            .iter_mut()
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| "Task not found".to_string())?;
+        if task.status != "awaiting_approval" {
+            return Err(format!("Task is not awaiting approval (status: {}).", task.status));
+        }
+
+        record_audit(
+            &state,
+            "task.approval_rejected",
+            Some(&task.thread_id),
+            Some(&task.id),
+            serde_json::json!({ "command": task.command, "approver": approver, "reason": reason }),
+        )?;
+        let now = now_ms();
+        if let Some(approval) = task.approval.as_mut() {
+            approval.status = "rejected".to_string();
+            approval.decided_by = Some(approver);
+            approval.decided_at = Some(now);
+            approval.note = reason;
+        }
+        task.status = "rejected".to_string();
+        task.finished_at = Some(now);
+        task.clone()
+    };
+
+    update_thread_status(&state, &rejected.thread_id, "rejected");
+    save_db_to_disk(&app, &state)?;
+    emit_task_status(&app, &rejected.id, &rejected.thread_id, "rejected", None);
+    Ok(rejected)
+}
+
+#[tauri::command]
+fn list_pending_approvals(state: State<AppState>) -> Result<Vec<TaskRecord>, String> {
+    let mut tasks: Vec<TaskRecord> = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?
+        .tasks
+        .iter()
+        .filter(|task| task.status == "awaiting_approval")
+        .cloned()
+        .collect();
+    tasks.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(tasks)
+}
+
+#[tauri::command]
+fn list_tasks(state: State<AppState>, thread_id: String) -> Result<Vec<TaskRecord>, String> {
+    let mut tasks: Vec<TaskRecord> = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?
+        .tasks
+        .iter()
+        .filter(|task| task.thread_id == thread_id)
+        .cloned()
+        .collect();
+    tasks.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(tasks)
+}
+
+#[tauri::command]
+fn list_task_logs(state: State<AppState>, task_id: String) -> Result<Vec<TaskLogRecord>, String> {
+    let mut logs: Vec<TaskLogRecord> = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?
+        .task_logs
+        .iter()
+        .filter(|log| log.task_id == task_id)
+        .cloned()
+        .collect();
+    logs.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(logs)
+}
+
+#[tauri::command]
+fn list_task_artifacts(state: State<AppState>, task_id: String) -> Result<Vec<TaskArtifactRecord>, String> {
+    let mut artifacts: Vec<TaskArtifactRecord> = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?
+        .task_artifacts
+        .iter()
+        .filter(|artifact| artifact.task_id == task_id)
+        .cloned()
+        .collect();
+    artifacts.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+    Ok(artifacts)
+}
+
+#[tauri::command]
+fn open_task_artifact(app: AppHandle, state: State<AppState>, artifact_id: String) -> Result<(), String> {
+    let stored_path = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        db.task_artifacts
+            .iter()
+            .find(|artifact| artifact.id == artifact_id)
+            .map(|artifact| artifact.stored_path.clone())
+            .ok_or_else(|| "Artifact not found".to_string())?
+    };
+
+    let path = Path::new(&stored_path);
+    if !path.is_file() {
+        return Err("Artifact file no longer exists.".to_string());
+    }
+
+    // Agent-produced executables and scripts are never handed to the default handler.
+    if artifacts::is_inert_artifact(path) {
+        app.opener()
+            .open_path(stored_path, None::<&str>)
+            .map_err(|err| format!("Failed to open artifact: {err}"))
+    } else {
+        app.opener()
+            .reveal_item_in_dir(path)
+            .map_err(|err| format!("Failed to reveal artifact: {err}"))
+    }
+}
+
+#[tauri::command]
+fn get_task_test_results(state: State<AppState>, task_id: String) -> Result<Option<TestResults>, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    db.tasks
+        .iter()
+        .find(|task| task.id == task_id)
+        .map(|task| task.test_results.clone())
+        .ok_or_else(|| "Task not found".to_string())
+}
+
+#[tauri::command]
+fn get_task_stats(state: State<AppState>, task_id: String) -> Result<TaskStats, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    let task = db
+        .tasks
+        .iter()
+        .find(|task| task.id == task_id)
+        .ok_or_else(|| "Task not found".to_string())?;
+
+    let mut previous: Vec<&TaskRecord> = db
+        .tasks
+        .iter()
+        .filter(|item| {
+            item.id != task.id
+                && item.thread_id == task.thread_id
+                && item.command == task.command
+                && item.finished_at.is_some()
+        })
+        .collect();
+    previous.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    Ok(TaskStats {
+        command: task.command.clone(),
+        current: TaskRunStats::from(task),
+        previous_runs: previous
+            .into_iter()
+            .take(TASK_STATS_HISTORY_LIMIT)
+            .map(TaskRunStats::from)
+            .collect(),
+    })
+}
+
+#[tauri::command]
+fn list_command_policy_rules(state: State<AppState>) -> Result<Vec<CommandPolicyRule>, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    let mut rules = command_policy::builtin_rules();
+    let mut stored = db.command_rules.clone();
+    stored.sort_by(|a, b| a.scope.cmp(&b.scope).then(a.created_at.cmp(&b.created_at)));
+    rules.extend(stored);
+    Ok(rules)
+}
+
+#[tauri::command]
+fn create_command_policy_rule(
+    app: AppHandle,
+    state: State<AppState>,
+    rule: CommandPolicyRuleInput,
+) -> Result<CommandPolicyRule, String> {
+    let rule = rule.into_rule(next_id("rule"), now_ms());
+    {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        validate_rule_scope(&db, &rule)?;
+        db.command_rules.push(rule.clone());
+    }
+    save_db_to_disk(&app, &state)?;
+    Ok(rule)
+}
+
+#[tauri::command]
+fn update_command_policy_rule(
+    app: AppHandle,
+    state: State<AppState>,
+    rule_id: String,
+    rule: CommandPolicyRuleInput,
+) -> Result<CommandPolicyRule, String> {
+    let updated = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let existing = db
+            .command_rules
+            .iter()
+            .find(|item| item.id == rule_id)
+            .ok_or_else(|| "Command rule not found".to_string())?;
+        let updated = rule.into_rule(existing.id.clone(), existing.created_at);
+        validate_rule_scope(&db, &updated)?;
+        if let Some(item) = db.command_rules.iter_mut().find(|item| item.id == rule_id) {
+            *item = updated.clone();
+        }
+        updated
+    };
+    save_db_to_disk(&app, &state)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+fn delete_command_policy_rule(app: AppHandle, state: State<AppState>, rule_id: String) -> Result<(), String> {
+    if let Ok(mut db) = state.db.lock() {
+        db.command_rules.retain(|rule| rule.id != rule_id);
+    }
+    save_db_to_disk(&app, &state)
+}
+
+/// Dry run of the policy check `run_task` performs, without queueing anything.
+#[tauri::command]
+fn evaluate_command_policy(
+    state: State<AppState>,
+    command: String,
+    shell: Option<String>,
+    thread_id: Option<String>,
+    project_id: Option<String>,
+) -> Result<PolicyEvaluation, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    let shell_name = shell
+        .filter(|shell| !shell.trim().is_empty())
+        .unwrap_or_else(|| db.settings.default_shell.clone());
+    let context = match thread_id {
+        Some(thread_id) => {
+            if !db.threads.iter().any(|t| t.id == thread_id) {
+                return Err("Thread not found".to_string());
+            }
+            policy_context_for_thread(&db, &thread_id)
+        }
+        None => PolicyContext {
+            project_id,
+            thread_id: None,
+        },
+    };
+    Ok(evaluate_task_command(&db, &command, &shell_name, &context))
+}
+
+#[tauri::command]
+fn set_max_parallel_tasks(app: AppHandle, state: State<AppState>, value: usize) -> Result<(), String> {
+    if value == 0 {
+        return Err("max_parallel_tasks must be >= 1".to_string());
+    }
+
+    if let Ok(mut db) = state.db.lock() {
+        db.settings.max_parallel_tasks = value;
+    }
+
+    save_db_to_disk(&app, &state)?;
+    schedule_tasks(app, state.inner().clone());
+    Ok(())
+}
+
+#[tauri::command]
+fn get_app_settings(state: State<AppState>) -> Result<AppSettings, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    Ok(db.settings.clone())
+}
+
+#[tauri::command]
+fn update_app_settings(app: AppHandle, state: State<AppState>, mut settings: AppSettings) -> Result<(), String> {
+    if settings.max_parallel_tasks == 0 {
+        return Err("max_parallel_tasks must be >= 1".to_string());
+    }
+    PermissionMode::parse(&settings.default_permission_mode)?;
+    for root in &settings.allowed_external_roots {
+        workspace_paths::validate_allowed_root(root)?;
+    }
+    settings.api_base_url = settings
+        .api_base_url
+        .filter(|url| !url.trim().is_empty())
+        .map(|url| credential_profiles::validate_base_url(&url))
+        .transpose()?;
+    state
+        .redactor
+        .lock()
+        .map_err(|_| "Redactor lock poisoned".to_string())?
+        .set_custom_patterns(&settings.redaction_patterns)?;
 
     if let Ok(mut db) = state.db.lock() {
         db.settings = settings;
@@ -1046,14 +2710,44 @@ fn delete_skill(app: AppHandle, state: State<AppState>, skill_id: String) -> Res
     save_db_to_disk(&app, &state)
 }
 
+/// `git -C path` for the app's own git calls. In projects that are not trusted,
+/// repository-defined hooks, fsmonitor commands and diff drivers are switched off so
+/// that opening a repository never runs code it ships.
+fn git_command(path: &str) -> Command {
+    let mut command = Command::new("git");
+    command.args(["-C", path]);
+    command
+}
+
+/// Reading status or a diff runs the repository's filter, textconv and diff drivers
+/// and its fsmonitor hook, all set by its own config and attributes, so git only runs
+/// in trusted projects.
+fn require_trusted_repository(state: &State<AppState>, path: &Path) -> Result<(), String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    require_trusted_project(project_for_path(&db, path))
+        .map_err(|_| "Git status and diffs are only shown for trusted projects.".to_string())
+}
+
 #[tauri::command]
 fn create_worktree(
+    state: State<AppState>,
     project_path: String,
     branch_name: String,
     worktree_path: String,
 ) -> Result<WorktreeResult, String> {
     let canonical_project = canonical_workspace(&project_path)?;
     let project = canonical_project.to_string_lossy().to_string();
+    {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        // Checking out a branch runs the repository's post-checkout hook.
+        require_trusted_project(project_for_path(&db, &canonical_project))?;
+    }
 
     if branch_name.trim().is_empty() {
         return Err("branch_name is required".to_string());
@@ -1106,12 +2800,13 @@ fn attach_thread_worktree(
 }
 
 #[tauri::command]
-fn git_status(path: String) -> Result<GitStatusResult, String> {
+fn git_status(state: State<AppState>, path: String) -> Result<GitStatusResult, String> {
     let canonical = canonical_workspace(&path)?;
     let path_str = canonical.to_string_lossy().to_string();
+    require_trusted_repository(&state, &canonical)?;
 
-    let repo_check = Command::new("git")
-        .args(["-C", path_str.as_str(), "rev-parse", "--is-inside-work-tree"])
+    let repo_check = git_command(&path_str)
+        .args(["rev-parse", "--is-inside-work-tree"])
         .output()
         .map_err(|err| format!("Failed to execute git: {err}"))?;
 
@@ -1123,8 +2818,8 @@ fn git_status(path: String) -> Result<GitStatusResult, String> {
         });
     }
 
-    let branch = Command::new("git")
-        .args(["-C", path_str.as_str(), "rev-parse", "--abbrev-ref", "HEAD"])
+    let branch = git_command(&path_str)
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
         .output()
         .ok()
         .and_then(|output| {
@@ -1135,8 +2830,8 @@ fn git_status(path: String) -> Result<GitStatusResult, String> {
             }
         });
 
-    let status_output = Command::new("git")
-        .args(["-C", path_str.as_str(), "status", "--porcelain"])
+    let status_output = git_command(&path_str)
+        .args(["status", "--porcelain"])
         .output()
         .map_err(|err| format!("Failed to execute git status: {err}"))?;
 
@@ -1159,11 +2854,12 @@ fn git_status(path: String) -> Result<GitStatusResult, String> {
 }
 
 #[tauri::command]
-fn git_diff(path: String, file: Option<String>) -> Result<String, String> {
+fn git_diff(state: State<AppState>, path: String, file: Option<String>) -> Result<String, String> {
     let canonical = canonical_workspace(&path)?;
     let path_str = canonical.to_string_lossy().to_string();
+    require_trusted_repository(&state, &canonical)?;
 
-    let mut args = vec!["-C".to_string(), path_str, "diff".to_string()];
+    let mut args = vec!["diff".to_string()];
     if let Some(file) = file {
         if !file.trim().is_empty() {
             args.push("--".to_string());
@@ -1171,7 +2867,7 @@ fn git_diff(path: String, file: Option<String>) -> Result<String, String> {
         }
     }
 
-    let output = Command::new("git")
+    let output = git_command(&path_str)
         .args(args)
         .output()
         .map_err(|err| format!("Failed to execute git diff: {err}"))?;
@@ -1180,22 +2876,113 @@ fn git_diff(path: String, file: Option<String>) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn run_terminal_command(workspace_path: String, command: String) -> Result<CommandResult, String> {
+fn run_terminal_command(
+    app: AppHandle,
+    state: State<AppState>,
+    workspace_path: String,
+    command: String,
+    confirm: Option<bool>,
+    thread_id: Option<String>,
+    approver: Option<String>,
+) -> Result<CommandResult, String> {
     let workspace = canonical_workspace(&workspace_path)?;
+    let shell = if cfg!(target_os = "windows") { "cmd" } else { "sh" };
+    let (context, evaluation) = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let context = execution_context(&db, thread_id.as_deref(), &workspace)?;
+        if !context.trusted {
+            require_trusted_project(project_for_path(&db, &workspace))?;
+        }
+        let evaluation = evaluate_task_command(&db, &command, shell, &context.policy);
+        (context, evaluation)
+    };
+    check_execution_isolation(&context, &workspace)?;
+    let mode_name = context.permission_mode.clone();
+
+    let confirmed = confirm.unwrap_or(false);
+    let decision = permissions::decide_command(PermissionMode::from_stored(&mode_name), &evaluation);
+    let (rejection, confirmation) = match decision {
+        CommandDecision::Reject(reason) => (Some(reason), None),
+        CommandDecision::Confirm(reason) if !confirmed => {
+            (Some(format!("Command requires explicit confirmation ({reason}).")), None)
+        }
+        CommandDecision::Confirm(reason) => (None, Some(reason)),
+        CommandDecision::Run => (None, None),
+    };
+    if let Some(reason) = rejection {
+        record_audit(
+            &state,
+            "terminal.rejected",
+            thread_id.as_deref(),
+            None,
+            serde_json::json!({
+                "command": command,
+                "cwd": workspace.to_string_lossy(),
+                "permissionMode": mode_name,
+                "policyAction": evaluation.action,
+                "reason": reason,
+            }),
+        )?;
+        return Err(reason);
+    }
+    // Recorded like a task approval, so every confirmed command names who confirmed it.
+    if let Some(reason) = confirmation {
+        record_audit(
+            &state,
+            "terminal.approved",
+            thread_id.as_deref(),
+            None,
+            serde_json::json!({
+                "command": command,
+                "cwd": workspace.to_string_lossy(),
+                "shell": shell,
+                "permissionMode": mode_name,
+                "policyAction": evaluation.action,
+                "approver": decided_by(approver),
+                "reason": reason,
+            }),
+        )?;
+    }
+    record_audit(
+        &state,
+        "terminal.run",
+        thread_id.as_deref(),
+        None,
+        serde_json::json!({
+            "command": command,
+            "cwd": workspace.to_string_lossy(),
+            "permissionMode": mode_name,
+            "policyAction": evaluation.action,
+            "confirmed": confirmed,
+            "sandboxed": context.sandbox_root.is_some(),
+            "networkDisabled": context.network_disabled,
+        }),
+    )?;
     let started = Instant::now();
 
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .args(["/C", command.as_str()])
-            .current_dir(&workspace)
-            .output()
-    } else {
-        Command::new("sh")
-            .args(["-lc", command.as_str()])
-            .current_dir(&workspace)
-            .output()
+    let sandbox_policy = match &context.sandbox_root {
+        Some(root) => Some(SandboxPolicy::new(root, sandbox_temp_dir(&app, &next_id("terminal"))?, &workspace)),
+        None => None,
+    };
+    let output = run_shell_command(
+        shell,
+        &command,
+        &workspace.to_string_lossy(),
+        sandbox_policy.as_ref(),
+        context.network_disabled,
+    )
+    .and_then(|(child, _containment)| {
+        child
+            .wait_with_output()
+            .map_err(|err| format!("Failed to execute command: {err}"))
+    });
+    if let Some(policy) = &sandbox_policy {
+        let _ = fs::remove_dir_all(&policy.temp_dir);
     }
-    .map_err(|err| format!("Failed to execute command: {err}"))?;
+    let output = output?;
 
     Ok(CommandResult {
         exit_code: output.status.code().unwrap_or(-1),
@@ -1207,12 +2994,13 @@ fn run_terminal_command(workspace_path: String, command: String) -> Result<Comma
 
 #[tauri::command]
 fn list_workspace_entries(
+    state: State<AppState>,
     workspace_path: String,
     relative_path: Option<String>,
 ) -> Result<Vec<WorkspaceEntry>, String> {
     let workspace = canonical_workspace(&workspace_path)?;
     let rel = relative_path.unwrap_or_default();
-    let target = resolve_workspace_target(&workspace, &rel)?;
+    let target = resolve_workspace_target(&state, &workspace, &rel)?;
 
     if !target.exists() {
         return Err("Directory does not exist.".to_string());
@@ -1226,11 +3014,10 @@ fn list_workspace_entries(
         .map_err(|err| format!("Failed reading directory: {err}"))?
         .filter_map(Result::ok)
         .filter_map(|entry| {
-            let path = entry.path();
             let meta = entry.metadata().ok()?;
-            let relative = path
-                .strip_prefix(&workspace)
-                .ok()?
+            // `target` may sit behind a link, so build the path from what was asked for.
+            let relative = Path::new(&rel)
+                .join(entry.file_name())
                 .to_string_lossy()
                 .replace('\\', "/");
             Some(WorkspaceEntry {
@@ -1252,9 +3039,9 @@ fn list_workspace_entries(
 }
 
 #[tauri::command]
-fn read_workspace_file(workspace_path: String, relative_path: String) -> Result<String, String> {
+fn read_workspace_file(state: State<AppState>, workspace_path: String, relative_path: String) -> Result<String, String> {
     let workspace = canonical_workspace(&workspace_path)?;
-    let target = resolve_workspace_target(&workspace, &relative_path)?;
+    let target = resolve_workspace_target(&state, &workspace, &relative_path)?;
 
     if !target.exists() {
         return Err("File does not exist.".to_string());
@@ -1268,13 +3055,19 @@ fn read_workspace_file(workspace_path: String, relative_path: String) -> Result<
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn write_workspace_file(
+    app: AppHandle,
+    state: State<AppState>,
     workspace_path: String,
     relative_path: String,
     content: String,
+    confirm: Option<bool>,
+    thread_id: Option<String>,
+    approver: Option<String>,
 ) -> Result<(), String> {
     let workspace = canonical_workspace(&workspace_path)?;
-    let target = resolve_workspace_target(&workspace, &relative_path)?;
+    let target = resolve_workspace_target(&state, &workspace, &relative_path)?;
 
     if !target.exists() {
         return Err("File does not exist.".to_string());
@@ -1284,120 +3077,1386 @@ fn write_workspace_file(
         return Err("Target is not a file.".to_string());
     }
 
-    let mut file = fs::File::create(&target).map_err(|err| format!("Failed to open file: {err}"))?;
-    file.write_all(content.as_bytes())
-        .map_err(|err| format!("Failed to write file: {err}"))
+    let context = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        execution_context(&db, thread_id.as_deref(), &target)?
+    };
+    check_execution_isolation(&context, &target)?;
+    let mode_name = context.permission_mode;
+    let mode = PermissionMode::from_stored(&mode_name);
+    permissions::check_file_write(mode, confirm.unwrap_or(false))?;
+
+    let previous = fs::read(&target).map_err(|err| format!("Failed to read file: {err}"))?;
+    let previous_sha256 = audit_log::sha256_hex(&previous);
+    keep_overwritten_file(&app, &state, &previous, &previous_sha256)?;
+    // Only danger-confirm asks before writing; the write passed, so it was confirmed.
+    if mode == PermissionMode::DangerConfirm {
+        record_audit(
+            &state,
+            "file.write_approved",
+            thread_id.as_deref(),
+            None,
+            serde_json::json!({
+                "path": target.to_string_lossy(),
+                "permissionMode": mode_name,
+                "approver": decided_by(approver),
+            }),
+        )?;
+    }
+    record_audit(
+        &state,
+        "file.write",
+        thread_id.as_deref(),
+        None,
+        serde_json::json!({
+            "path": target.to_string_lossy(),
+            "previousSha256": previous_sha256,
+            "previousSize": previous.len(),
+            "sha256": audit_log::sha256_hex(content.as_bytes()),
+            "size": content.len(),
+            "permissionMode": mode_name,
+        }),
+    )?;
+
+    let mut file = fs::File::create(&target).map_err(|err| format!("Failed to open file: {err}"))?;
+    file.write_all(content.as_bytes())
+        .map_err(|err| format!("Failed to write file: {err}"))
 }
 
 #[tauri::command]
-fn wait_for_oauth_callback(port: u16, timeout_secs: Option<u64>) -> Result<String, String> {
-    let listener = TcpListener::bind(("127.0.0.1", port))
-        .map_err(|err| format!("Failed to start callback server: {err}"))?;
-    listener
-        .set_nonblocking(true)
-        .map_err(|err| format!("Failed to set callback server nonblocking: {err}"))?;
+fn verify_audit_log(app: AppHandle, state: State<AppState>) -> Result<AuditVerification, String> {
+    // Read before taking the writer lock, which appends hold while they update it. An
+    // append in between only makes the anchor older than the log, which is fine.
+    let anchor = load_audit_anchor(&state);
+    // Holding the writer lock keeps appends from racing the read.
+    let _audit = state
+        .audit
+        .lock()
+        .map_err(|_| "Audit log lock poisoned".to_string())?;
+    audit_log::verify(&audit_log_path(&app)?, anchor.as_ref())
+}
 
-    let started = Instant::now();
+/// Copies the audit log to `destination_path` as JSONL and returns the verification of
+/// what was exported.
+#[tauri::command]
+fn export_audit_log(app: AppHandle, state: State<AppState>, destination_path: String) -> Result<AuditVerification, String> {
+    let destination = PathBuf::from(destination_path.trim());
+    if !destination.is_absolute() {
+        return Err("Export path must be absolute.".to_string());
+    }
+
+    let anchor = load_audit_anchor(&state);
+    let _audit = state
+        .audit
+        .lock()
+        .map_err(|_| "Audit log lock poisoned".to_string())?;
+    let source = audit_log_path(&app)?;
+    if source.exists() {
+        fs::copy(&source, &destination).map_err(|err| format!("Failed to export audit log: {err}"))?;
+    } else {
+        fs::write(&destination, "").map_err(|err| format!("Failed to export audit log: {err}"))?;
+    }
+    audit_log::verify(&destination, anchor.as_ref())
+}
+
+/// Signs in with the authorization code flow. The refresh token goes to the secret
+/// store; the access token stays in process memory and is not returned to the UI.
+#[tauri::command(async)]
+fn start_oauth_login(
+    app: AppHandle,
+    state: State<AppState>,
+    config: OAuthConfig,
+    timeout_secs: Option<u64>,
+) -> Result<AuthSessionInfo, String> {
     let timeout = Duration::from_secs(timeout_secs.unwrap_or(120));
+    sign_in(&app, &state, config, "oauth", |config, login_id| {
+        let server = CallbackServer::bind(&config.redirect_uri)?;
+        set_pending_login(&state, login_id, PendingLogin::Loopback(server.cancel_handle()))?;
+        oauth::login_with_loopback(config, &server, timeout, |url| {
+            app.opener()
+                .open_url(url, None::<&str>)
+                .map_err(|err| format!("Failed to open browser: {err}"))
+        })
+    })
+}
+
+/// Signs in with the device authorization grant, for machines where the loopback
+/// callback cannot work. The user code and verification URL arrive as an
+/// `auth:device-code` event while the token endpoint is polled.
+#[tauri::command(async)]
+fn start_device_login(app: AppHandle, state: State<AppState>, config: OAuthConfig) -> Result<AuthSessionInfo, String> {
+    sign_in(&app, &state, config, "device_code", |config, login_id| {
+        let device = oauth_device::request_device_code(config)?;
+        let cancel = DeviceCancel::for_current_thread();
+        set_pending_login(&state, login_id, PendingLogin::Device(cancel.clone()))?;
+        let _ = app.emit("auth:device-code", device.prompt(now_ms()));
+        oauth_device::poll_device_token(config, &device, &cancel)
+    })
+}
+
+/// Registers the login in progress. A new login replaces one still waiting.
+fn set_pending_login(state: &AppState, login_id: &str, pending: PendingLogin) -> Result<(), String> {
+    if let Some((_, previous)) = state
+        .oauth_login
+        .lock()
+        .map_err(|_| "OAuth login lock poisoned".to_string())?
+        .replace((login_id.to_string(), pending))
+    {
+        previous.cancel();
+    }
+    Ok(())
+}
 
+/// Runs one login flow and turns its tokens into the active session: the refresh
+/// token goes to the secret store, the access token to the session manager.
+/// `login` gets the id to register its cancel handle under.
+fn sign_in(
+    app: &AppHandle,
+    state: &AppState,
+    config: OAuthConfig,
+    method: &str,
+    login: impl FnOnce(&OAuthConfig, &str) -> Result<TokenResponse, String>,
+) -> Result<AuthSessionInfo, String> {
+    let previous = {
+        let mut auth = state
+            .auth
+            .lock()
+            .map_err(|_| "Auth session lock poisoned".to_string())?;
+        let previous = auth.state();
+        auth.set_state(AuthState::SigningIn, None);
+        previous
+    };
+    emit_auth_state(app, state);
+
+    let login_id = next_id("login");
+    let result = login(&config, &login_id);
+    // A newer login may have replaced this one's handle; that one stays cancellable.
+    if let Ok(mut pending) = state.oauth_login.lock() {
+        if pending.as_ref().is_some_and(|(id, _)| *id == login_id) {
+            pending.take();
+        }
+    }
+    let tokens = match result.and_then(|tokens| store_login_tokens(state, &config, method, tokens)) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            if let Ok(mut auth) = state.auth.lock() {
+                if auth.state() == AuthState::SigningIn {
+                    auth.set_state(previous, Some(err.clone()));
+                }
+            }
+            emit_auth_state(app, state);
+            return Err(err);
+        }
+    };
+
+    let session = AuthSession::from_tokens(&tokens, now_ms());
+    let info = AuthSessionInfo::from(&session);
+    let generation = state
+        .auth
+        .lock()
+        .map_err(|_| "Auth session lock poisoned".to_string())?
+        .activate(session, RefreshEndpoint::from(config));
+    emit_auth_state(app, state);
+    spawn_session_refresh(app.clone(), state.clone(), generation);
+    Ok(info)
+}
+
+fn store_login_tokens(state: &AppState, config: &OAuthConfig, method: &str, tokens: TokenResponse) -> Result<TokenResponse, String> {
+    set_known_secret(state, ACCESS_TOKEN_SECRET_LABEL, Some(&tokens.access_token));
+    if let Some(refresh_token) = &tokens.refresh_token {
+        save_secret(state, TOKEN_ACCOUNT, refresh_token)?;
+        set_known_secret(state, REFRESH_TOKEN_SECRET_LABEL, Some(refresh_token));
+    }
+    record_audit(
+        state,
+        "auth.login",
+        None,
+        None,
+        serde_json::json!({
+            "method": method,
+            "clientId": config.client_id,
+            "tokenUrl": config.token_url,
+            "scope": tokens.scope,
+            "refreshToken": tokens.refresh_token.is_some(),
+        }),
+    )?;
+    Ok(tokens)
+}
+
+/// Restores the session from the stored refresh token at startup. The refresh runs in
+/// the background; progress arrives as `auth:state` events.
+#[tauri::command]
+fn restore_oauth_session(app: AppHandle, state: State<AppState>, config: OAuthConfig) -> Result<AuthStateEvent, String> {
+    let has_refresh_token = load_secret(&state, TOKEN_ACCOUNT)?.is_some();
+    let generation = {
+        let mut auth = state
+            .auth
+            .lock()
+            .map_err(|_| "Auth session lock poisoned".to_string())?;
+        if !has_refresh_token {
+            auth.sign_out();
+            return Ok(auth.snapshot());
+        }
+        auth.restore(RefreshEndpoint::from(config))
+    };
+    emit_auth_state(&app, &state);
+    spawn_session_refresh(app.clone(), state.inner().clone(), generation);
+    get_auth_state(state)
+}
+
+#[tauri::command]
+fn get_auth_state(state: State<AppState>) -> Result<AuthStateEvent, String> {
+    Ok(state
+        .auth
+        .lock()
+        .map_err(|_| "Auth session lock poisoned".to_string())?
+        .snapshot())
+}
+
+fn emit_auth_state(app: &AppHandle, state: &AppState) {
+    let snapshot = match state.auth.lock() {
+        Ok(auth) => auth.snapshot(),
+        Err(_) => return,
+    };
+    let _ = app.emit("auth:state", snapshot);
+}
+
+/// Keeps the session of `generation` fresh: refreshes shortly before the access token
+/// expires and exits once the generation is retired or the session is lost.
+fn spawn_session_refresh(app: AppHandle, state: AppState, generation: u64) {
+    thread::spawn(move || {
+        let registered = state
+            .auth
+            .lock()
+            .is_ok_and(|mut auth| auth.set_worker(generation, thread::current()));
+        if !registered {
+            return;
+        }
+        loop {
+            let due = match state.auth.lock() {
+                Ok(auth) if auth.is_current(generation) => auth.refresh_due_at(),
+                _ => return,
+            };
+            let Some(due) = due else {
+                return;
+            };
+            if !park_session_worker(&state, generation, due) || !refresh_session(&app, &state, generation) {
+                return;
+            }
+        }
+    });
+}
+
+/// Sleeps until `until_ms`. Returns false as soon as `generation` is retired, which
+/// also unparks the worker.
+fn park_session_worker(state: &AppState, generation: u64, until_ms: i64) -> bool {
     loop {
-        if started.elapsed() > timeout {
-            return Err("OAuth callback timeout.".to_string());
-        }
-
-        match listener.accept() {
-            Ok((mut stream, _)) => {
-                let mut buffer = [0u8; 8192];
-                let read = stream
-                    .read(&mut buffer)
-                    .map_err(|err| format!("Failed to read callback request: {err}"))?;
-                let request = String::from_utf8_lossy(&buffer[..read]);
-                let first_line = request.lines().next().unwrap_or("");
-                let callback_target = first_line
-                    .split_whitespace()
-                    .nth(1)
-                    .ok_or_else(|| "Invalid callback request.".to_string())?;
-
-                let response = "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n<!doctype html><html><body><h2>Login concluido</h2><p>Voce pode fechar esta aba e voltar para o app.</p></body></html>";
-
-                stream
-                    .write_all(response.as_bytes())
-                    .map_err(|err| format!("Failed to write callback response: {err}"))?;
-
-                return Ok(format!("http://127.0.0.1:{port}{callback_target}"));
+        match state.auth.lock() {
+            Ok(auth) if auth.is_current(generation) => {}
+            _ => return false,
+        }
+        let now = now_ms();
+        if now >= until_ms {
+            return true;
+        }
+        thread::park_timeout(Duration::from_millis((until_ms - now) as u64));
+    }
+}
+
+/// Redeems the refresh token, retrying network failures with backoff. Returns whether
+/// the session is still active afterwards.
+fn refresh_session(app: &AppHandle, state: &AppState, generation: u64) -> bool {
+    let endpoint = match state.auth.lock() {
+        Ok(mut auth) if auth.is_current(generation) => {
+            auth.set_state(AuthState::Refreshing, None);
+            auth.endpoint()
+        }
+        _ => return false,
+    };
+    emit_auth_state(app, state);
+    let Some(endpoint) = endpoint else {
+        return false;
+    };
+
+    let mut attempt = 0;
+    loop {
+        let refresh_token = match load_secret(state, TOKEN_ACCOUNT) {
+            Ok(Some(token)) => token,
+            Ok(None) => return expire_session(app, state, generation, "No refresh token available.".to_string()),
+            Err(err) => return expire_session(app, state, generation, err),
+        };
+
+        match oauth::refresh_tokens(&endpoint.token_url, &endpoint.client_id, &refresh_token) {
+            Ok(tokens) => {
+                set_known_secret(state, ACCESS_TOKEN_SECRET_LABEL, Some(&tokens.access_token));
+                // The generation is checked and the token saved under the same lock, so a
+                // logout or login that happened during the request keeps its own token.
+                let Ok(mut auth) = state.auth.lock() else {
+                    return false;
+                };
+                if !auth.is_current(generation) {
+                    return false;
+                }
+                // Providers that rotate refresh tokens invalidate the old one.
+                if let Some(rotated) = &tokens.refresh_token {
+                    if let Err(err) = save_secret(state, TOKEN_ACCOUNT, rotated) {
+                        drop(auth);
+                        return expire_session(app, state, generation, err);
+                    }
+                    set_known_secret(state, REFRESH_TOKEN_SECRET_LABEL, Some(rotated));
+                }
+                auth.refreshed(AuthSession::from_tokens(&tokens, now_ms()));
+                drop(auth);
+                emit_auth_state(app, state);
+                return true;
+            }
+            Err(TokenFailure::Rejected { error, message, .. }) => {
+                if error == "invalid_grant" {
+                    match state.auth.lock() {
+                        Ok(auth) if auth.is_current(generation) => {
+                            let _ = clear_secret(state, TOKEN_ACCOUNT);
+                            set_known_secret(state, REFRESH_TOKEN_SECRET_LABEL, None);
+                        }
+                        _ => return false,
+                    }
+                }
+                return expire_session(app, state, generation, message);
+            }
+            Err(TokenFailure::Unavailable(message)) => {
+                attempt += 1;
+                if attempt >= auth_session::MAX_REFRESH_ATTEMPTS {
+                    return expire_session(app, state, generation, message);
+                }
+                match state.auth.lock() {
+                    Ok(mut auth) if auth.is_current(generation) => auth.set_state(AuthState::Refreshing, Some(message)),
+                    _ => return false,
+                }
+                emit_auth_state(app, state);
+                let retry_at = now_ms() + auth_session::backoff_delay(attempt).as_millis() as i64;
+                if !park_session_worker(state, generation, retry_at) {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+fn expire_session(app: &AppHandle, state: &AppState, generation: u64, error: String) -> bool {
+    match state.auth.lock() {
+        Ok(mut auth) if auth.is_current(generation) => auth.expire(Some(error)),
+        _ => return false,
+    }
+    emit_auth_state(app, state);
+    false
+}
+
+/// A step of `logout` that did not complete. The other steps still ran.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LogoutFailure {
+    step: String,
+    error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LogoutReport {
+    /// Token kinds the provider confirmed revoked.
+    revoked: Vec<String>,
+    /// Whether a revocation endpoint was known.
+    revocation_configured: bool,
+    /// Credential profiles whose refresh token was deleted. Their API keys and
+    /// settings are kept; they are configuration, not part of the session.
+    cleared_profiles: Vec<String>,
+    failures: Vec<LogoutFailure>,
+}
+
+/// Signs out completely: stops a pending login, revokes the tokens when a revocation
+/// endpoint is configured, then forgets the session and deletes the refresh token,
+/// including the ones saved in credential profiles.
+/// Local cleanup runs even when revocation fails; failed steps are reported.
+#[tauri::command(async)]
+fn logout(app: AppHandle, state: State<AppState>, config: Option<OAuthConfig>) -> Result<LogoutReport, String> {
+    let mut failures = Vec::new();
+    let mut fail = |step: &str, error: String| {
+        failures.push(LogoutFailure {
+            step: step.to_string(),
+            error,
+        })
+    };
+
+    if let Some((_, pending)) = state
+        .oauth_login
+        .lock()
+        .map_err(|_| "OAuth login lock poisoned".to_string())?
+        .take()
+    {
+        pending.cancel();
+    }
+
+    let (access_token, session_endpoint) = state
+        .auth
+        .lock()
+        .map_err(|_| "Auth session lock poisoned".to_string())?
+        .sign_out();
+    emit_auth_state(&app, &state);
+    let endpoint = config.map(RefreshEndpoint::from).or(session_endpoint);
+
+    let refresh_token = match load_secret(&state, TOKEN_ACCOUNT) {
+        Ok(token) => token,
+        Err(err) => {
+            fail("load_refresh_token", err);
+            None
+        }
+    };
+
+    let mut revoked = Vec::new();
+    let revocation = endpoint
+        .as_ref()
+        .and_then(|endpoint| endpoint.revocation_url.as_deref().map(|url| (url, endpoint.client_id.as_str())));
+    if let Some((revocation_url, client_id)) = revocation {
+        // Revoking the refresh token first also ends the access tokens issued from it
+        // at most providers; the access token is revoked too for the others.
+        for (hint, token) in [("refresh_token", &refresh_token), ("access_token", &access_token)] {
+            let Some(token) = token else {
+                continue;
+            };
+            match oauth::revoke_token(revocation_url, client_id, token, hint) {
+                Ok(()) => revoked.push(hint.to_string()),
+                Err(err) => fail(&format!("revoke_{hint}"), err),
+            }
+        }
+    }
+
+    if let Err(err) = clear_secret(&state, TOKEN_ACCOUNT) {
+        fail("delete_refresh_token", err);
+    }
+    set_known_secret(&state, REFRESH_TOKEN_SECRET_LABEL, None);
+    set_known_secret(&state, ACCESS_TOKEN_SECRET_LABEL, None);
+
+    let profile_ids: Vec<String> = match state.db.lock() {
+        Ok(db) => db.credential_profiles.iter().map(|profile| profile.id.clone()).collect(),
+        Err(_) => {
+            fail("delete_profile_refresh_tokens", "Database lock poisoned".to_string());
+            Vec::new()
+        }
+    };
+    let mut cleared_profiles = Vec::new();
+    for profile_id in profile_ids {
+        let cleared = credential_profiles::secret_account(&profile_id, "refresh_token").and_then(|account| {
+            let saved = load_secret(&state, &account)?.is_some();
+            clear_secret(&state, &account)?;
+            set_known_secret(&state, &account, None);
+            Ok(saved)
+        });
+        match cleared {
+            Ok(true) => cleared_profiles.push(profile_id),
+            Ok(false) => {}
+            Err(err) => fail(&format!("delete_profile_refresh_token:{profile_id}"), err),
+        }
+    }
+
+    let mut report = LogoutReport {
+        revoked,
+        revocation_configured: revocation.is_some(),
+        cleared_profiles,
+        failures,
+    };
+    if let Err(err) = record_audit(
+        &state,
+        "auth.logout",
+        None,
+        None,
+        serde_json::json!({
+            "revoked": report.revoked,
+            "revocationConfigured": report.revocation_configured,
+            "clearedProfiles": report.cleared_profiles,
+            "failures": report.failures.iter().map(|failure| &failure.step).collect::<Vec<_>>(),
+        }),
+    ) {
+        report.failures.push(LogoutFailure {
+            step: "audit".to_string(),
+            error: err,
+        });
+    }
+    Ok(report)
+}
+
+/// Stops a login that is waiting for its browser callback. Returns whether one was.
+#[tauri::command]
+fn cancel_oauth_login(state: State<AppState>) -> Result<bool, String> {
+    let pending = state
+        .oauth_login
+        .lock()
+        .map_err(|_| "OAuth login lock poisoned".to_string())?
+        .take();
+    if let Some((_, cancel)) = &pending {
+        cancel.cancel();
+    }
+    Ok(pending.is_some())
+}
+
+/// App-wide secrets. Credential profiles add their own, see `secret_accounts`.
+const SECRET_ACCOUNTS: &[&str] = &[TOKEN_ACCOUNT, API_KEY_ACCOUNT, DATA_STORE_KEY_ACCOUNT, AUDIT_ANCHOR_ACCOUNT];
+
+fn secret_store_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("Unable to resolve app data dir: {err}"))?;
+    fs::create_dir_all(&dir).map_err(|err| format!("Unable to create app data dir: {err}"))?;
+    Ok(dir.join("secrets.json"))
+}
+
+/// Picks the secret backend before anything reads a credential. The choice is not an
+/// `AppSettings` field because the settings may themselves be encrypted with a key
+/// held by the backend; an existing secret file selects the file backend.
+fn init_secret_store(app: &AppHandle, state: &AppState) -> Result<(), String> {
+    if let Some(file) = EncryptedSecretFile::open(secret_store_path(app)?)? {
+        let mut secrets = state
+            .secrets
+            .lock()
+            .map_err(|_| "Secret store lock poisoned".to_string())?;
+        *secrets = SecretBackend::File(file);
+    }
+    Ok(())
+}
+
+fn load_secret(state: &AppState, account: &str) -> Result<Option<String>, String> {
+    state
+        .secrets
+        .lock()
+        .map_err(|_| "Secret store lock poisoned".to_string())?
+        .load(account)
+}
+
+fn save_secret(state: &AppState, account: &str, value: &str) -> Result<(), String> {
+    state
+        .secrets
+        .lock()
+        .map_err(|_| "Secret store lock poisoned".to_string())?
+        .save(account, value)
+}
+
+fn clear_secret(state: &AppState, account: &str) -> Result<(), String> {
+    state
+        .secrets
+        .lock()
+        .map_err(|_| "Secret store lock poisoned".to_string())?
+        .clear(account)
+}
+
+#[tauri::command]
+fn save_refresh_token(state: State<AppState>, refresh_token: String) -> Result<(), String> {
+    save_secret(&state, TOKEN_ACCOUNT, &refresh_token)?;
+    set_known_secret(&state, REFRESH_TOKEN_SECRET_LABEL, Some(&refresh_token));
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_refresh_token(state: State<AppState>) -> Result<(), String> {
+    clear_secret(&state, TOKEN_ACCOUNT)?;
+    set_known_secret(&state, REFRESH_TOKEN_SECRET_LABEL, None);
+    Ok(())
+}
+
+#[tauri::command]
+fn save_api_key(state: State<AppState>, api_key: String) -> Result<(), String> {
+    save_secret(&state, API_KEY_ACCOUNT, &api_key)?;
+    set_known_secret(&state, API_KEY_SECRET_LABEL, Some(&api_key));
+    Ok(())
+}
+
+/// Whether an API key is saved. Stored secrets never go back to the UI.
+#[tauri::command]
+fn has_api_key(state: State<AppState>) -> Result<bool, String> {
+    Ok(load_secret(&state, API_KEY_ACCOUNT)?.is_some())
+}
+
+#[tauri::command]
+fn clear_api_key(state: State<AppState>) -> Result<(), String> {
+    clear_secret(&state, API_KEY_ACCOUNT)?;
+    set_known_secret(&state, API_KEY_SECRET_LABEL, None);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretStoreStatus {
+    backend: String,
+    unlocked: bool,
+}
+
+fn secret_store_status(state: &AppState) -> Result<SecretStoreStatus, String> {
+    let secrets = state
+        .secrets
+        .lock()
+        .map_err(|_| "Secret store lock poisoned".to_string())?;
+    Ok(SecretStoreStatus {
+        backend: secrets.name().to_string(),
+        unlocked: match &*secrets {
+            SecretBackend::Keyring { .. } => true,
+            SecretBackend::File(file) => file.is_unlocked(),
+        },
+    })
+}
+
+#[tauri::command]
+fn get_secret_store_status(state: State<AppState>) -> Result<SecretStoreStatus, String> {
+    secret_store_status(&state)
+}
+
+/// Unlocks the file backend and finishes loading anything that waited for it.
+#[tauri::command]
+fn unlock_secret_store(app: AppHandle, state: State<AppState>, passphrase: String) -> Result<SecretStoreStatus, String> {
+    {
+        let mut secrets = state
+            .secrets
+            .lock()
+            .map_err(|_| "Secret store lock poisoned".to_string())?;
+        match &mut *secrets {
+            SecretBackend::File(file) => file.unlock(&passphrase)?,
+            SecretBackend::Keyring { .. } => return Err("Secrets are stored in the OS keyring.".to_string()),
+        }
+    }
+
+    let was_locked = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?
+        .locked
+        .take()
+        .is_some();
+    if was_locked {
+        load_db_from_disk(&app, &state)?;
+        resume_pending_approvals(&app, &state);
+    }
+    init_redactor(&state)?;
+    secret_store_status(&state)
+}
+
+/// Moves every stored secret to `backend` ("keyring" or "file") and removes it from
+/// the old one. Switching to the file backend needs a passphrase for the new file.
+#[tauri::command]
+fn set_secret_backend(
+    app: AppHandle,
+    state: State<AppState>,
+    backend: String,
+    passphrase: Option<String>,
+) -> Result<SecretStoreStatus, String> {
+    let accounts = secret_accounts(&state)?;
+    let mut secrets = state
+        .secrets
+        .lock()
+        .map_err(|_| "Secret store lock poisoned".to_string())?;
+    if secrets.name() == backend {
+        return Err(format!("Secrets are already stored in the {backend} backend."));
+    }
+
+    let mut migrated: Vec<String> = Vec::new();
+    let mut unreadable: Vec<String> = Vec::new();
+    let next = match backend.as_str() {
+        "file" => {
+            let passphrase = passphrase
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| "A passphrase is required for the encrypted file backend.".to_string())?;
+            let mut file = EncryptedSecretFile::create(secret_store_path(&app)?, &passphrase)?;
+            for account in &accounts {
+                // A keyring that cannot be read has nothing to migrate; that is the
+                // usual reason to switch in the first place.
+                match secrets.load(account) {
+                    Ok(Some(value)) => {
+                        file.set(account, &value)?;
+                        migrated.push(account.clone());
+                    }
+                    Ok(None) => {}
+                    Err(_) => unreadable.push(account.clone()),
+                }
+            }
+            file.save()?;
+            SecretBackend::File(file)
+        }
+        "keyring" => {
+            let accounts: Vec<String> = match &*secrets {
+                SecretBackend::File(file) => file.accounts().cloned().collect(),
+                SecretBackend::Keyring { .. } => Vec::new(),
+            };
+            let mut keyring = SecretBackend::Keyring { service: TOKEN_SERVICE };
+            for account in &accounts {
+                if let Some(value) = secrets.load(account)? {
+                    keyring.save(account, &value)?;
+                    migrated.push(account.clone());
+                }
             }
-            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
-                thread::sleep(Duration::from_millis(50));
+            keyring
+        }
+        _ => return Err("Invalid secret backend. Use keyring or file".to_string()),
+    };
+
+    // The old copies are only removed once the new backend holds them.
+    let from = secrets.name();
+    let removed = match std::mem::replace(&mut *secrets, next) {
+        SecretBackend::File(file) => fs::remove_file(file.path())
+            .map_err(|err| format!("Secrets migrated, but failed to remove the old secret file: {err}")),
+        mut keyring => {
+            for account in &migrated {
+                let _ = keyring.clear(account);
             }
-            Err(err) => return Err(format!("Callback listener failed: {err}")),
+            Ok(())
+        }
+    };
+    let to = secrets.name();
+    // Recorded once the store is unlocked: the audit log goes through the redactor,
+    // whose initialization loads secrets.
+    drop(secrets);
+    record_audit(
+        &state,
+        "secrets.backend",
+        None,
+        None,
+        serde_json::json!({
+            "from": from,
+            "to": to,
+            "migrated": migrated,
+            "unreadable": unreadable,
+        }),
+    )?;
+    removed?;
+    secret_store_status(&state)
+}
+
+/// Every account the secret store may hold: the single app-wide credentials plus the
+/// secrets of each credential profile.
+fn secret_accounts(state: &AppState) -> Result<Vec<String>, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    let mut accounts: Vec<String> = SECRET_ACCOUNTS.iter().map(|account| account.to_string()).collect();
+    for profile in &db.credential_profiles {
+        for (kind, _) in credential_profiles::PROFILE_SECRET_KINDS {
+            accounts.push(credential_profiles::secret_account(&profile.id, kind)?);
         }
     }
+    Ok(accounts)
 }
 
-fn token_entry() -> Result<Entry, String> {
-    Entry::new(TOKEN_SERVICE, TOKEN_ACCOUNT).map_err(|err| format!("Token store unavailable: {err}"))
+#[tauri::command]
+fn list_credential_profiles(state: State<AppState>) -> Result<Vec<CredentialProfile>, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    let mut profiles = db.credential_profiles.clone();
+    profiles.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    Ok(profiles)
 }
 
-fn api_key_entry() -> Result<Entry, String> {
-    Entry::new(TOKEN_SERVICE, API_KEY_ACCOUNT).map_err(|err| format!("Token store unavailable: {err}"))
+fn ensure_unique_profile_name(db: &AppDb, profile: &CredentialProfile) -> Result<(), String> {
+    if db
+        .credential_profiles
+        .iter()
+        .any(|item| item.id != profile.id && item.name.eq_ignore_ascii_case(&profile.name))
+    {
+        return Err(format!("A credential profile named {} already exists.", profile.name));
+    }
+    Ok(())
 }
 
 #[tauri::command]
-fn save_refresh_token(refresh_token: String) -> Result<(), String> {
-    let entry = token_entry()?;
-    entry
-        .set_password(&refresh_token)
-        .map_err(|err| format!("Failed to save refresh token: {err}"))
+fn create_credential_profile(
+    app: AppHandle,
+    state: State<AppState>,
+    profile: CredentialProfileInput,
+) -> Result<CredentialProfile, String> {
+    let now = now_ms();
+    let profile = profile.into_profile(next_id("profile"), now, now)?;
+    {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        ensure_unique_profile_name(&db, &profile)?;
+        db.credential_profiles.push(profile.clone());
+    }
+    save_db_to_disk(&app, &state)?;
+    Ok(profile)
 }
 
 #[tauri::command]
-fn load_refresh_token() -> Result<Option<String>, String> {
-    let entry = token_entry()?;
+fn update_credential_profile(
+    app: AppHandle,
+    state: State<AppState>,
+    profile_id: String,
+    profile: CredentialProfileInput,
+) -> Result<CredentialProfile, String> {
+    let updated = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let existing = db
+            .credential_profiles
+            .iter()
+            .find(|item| item.id == profile_id)
+            .ok_or_else(|| "Credential profile not found".to_string())?;
+        let updated = profile.into_profile(existing.id.clone(), existing.created_at, now_ms())?;
+        ensure_unique_profile_name(&db, &updated)?;
+        if let Some(item) = db.credential_profiles.iter_mut().find(|item| item.id == profile_id) {
+            *item = updated.clone();
+        }
+        updated
+    };
+    save_db_to_disk(&app, &state)?;
+    Ok(updated)
+}
 
-    match entry.get_password() {
-        Ok(token) => Ok(Some(token)),
-        Err(KeyringError::NoEntry) => Ok(None),
-        Err(err) => Err(format!("Failed to read refresh token: {err}")),
+/// Deletes a profile together with its secrets. Projects that used it fall back to
+/// the app-wide credentials.
+#[tauri::command]
+fn delete_credential_profile(app: AppHandle, state: State<AppState>, profile_id: String) -> Result<(), String> {
+    for (kind, _) in credential_profiles::PROFILE_SECRET_KINDS {
+        let account = credential_profiles::secret_account(&profile_id, kind)?;
+        clear_secret(&state, &account)?;
+        set_known_secret(&state, &account, None);
+    }
+    if let Ok(mut db) = state.db.lock() {
+        db.credential_profiles.retain(|profile| profile.id != profile_id);
+        for project in db
+            .projects
+            .iter_mut()
+            .filter(|project| project.credential_profile_id.as_deref() == Some(profile_id.as_str()))
+        {
+            project.credential_profile_id = None;
+        }
     }
+    save_db_to_disk(&app, &state)
+}
+
+fn profile_secret_account(state: &AppState, profile_id: &str, kind: &str) -> Result<String, String> {
+    let db = state
+        .db
+        .lock()
+        .map_err(|_| "Database lock poisoned".to_string())?;
+    if !db.credential_profiles.iter().any(|profile| profile.id == profile_id) {
+        return Err("Credential profile not found".to_string());
+    }
+    credential_profiles::secret_account(profile_id, kind)
+}
+
+#[tauri::command]
+fn save_profile_secret(state: State<AppState>, profile_id: String, kind: String, value: String) -> Result<(), String> {
+    let account = profile_secret_account(&state, &profile_id, &kind)?;
+    save_secret(&state, &account, &value)?;
+    set_known_secret(&state, &account, Some(&value));
+    Ok(())
 }
 
 #[tauri::command]
-fn clear_refresh_token() -> Result<(), String> {
-    let entry = token_entry()?;
+fn clear_profile_secret(state: State<AppState>, profile_id: String, kind: String) -> Result<(), String> {
+    let account = profile_secret_account(&state, &profile_id, &kind)?;
+    clear_secret(&state, &account)?;
+    set_known_secret(&state, &account, None);
+    Ok(())
+}
 
-    match entry.delete_credential() {
-        Ok(_) | Err(KeyringError::NoEntry) => Ok(()),
-        Err(err) => Err(format!("Failed to clear refresh token: {err}")),
+/// Sets the profile threads in this project use by default. `None` goes back to the
+/// app-wide credentials.
+#[tauri::command]
+fn set_project_credential_profile(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    profile_id: Option<String>,
+) -> Result<ProjectRecord, String> {
+    let profile_id = profile_id.filter(|id| !id.trim().is_empty());
+    let updated = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        if let Some(profile_id) = &profile_id {
+            if !db.credential_profiles.iter().any(|profile| &profile.id == profile_id) {
+                return Err("Credential profile not found".to_string());
+            }
+        }
+        let project = db
+            .projects
+            .iter_mut()
+            .find(|project| project.id == project_id)
+            .ok_or_else(|| "Project not found".to_string())?;
+        project.credential_profile_id = profile_id;
+        project.clone()
+    };
+    save_db_to_disk(&app, &state)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+fn set_project_api_base_url(
+    app: AppHandle,
+    state: State<AppState>,
+    project_id: String,
+    api_base_url: Option<String>,
+) -> Result<ProjectRecord, String> {
+    let api_base_url = api_base_url
+        .filter(|url| !url.trim().is_empty())
+        .map(|url| credential_profiles::validate_base_url(&url))
+        .transpose()?;
+    let updated = {
+        let mut db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let project = db
+            .projects
+            .iter_mut()
+            .find(|project| project.id == project_id)
+            .ok_or_else(|| "Project not found".to_string())?;
+        project.api_base_url = api_base_url;
+        project.clone()
+    };
+    save_db_to_disk(&app, &state)?;
+    Ok(updated)
+}
+
+/// How a thread reaches the model. Fields left empty fall back to the app-wide
+/// connection settings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelConnection {
+    project_id: String,
+    profile_id: Option<String>,
+    profile_name: Option<String>,
+    auth_method: Option<String>,
+    base_url: Option<String>,
+    organization: Option<String>,
+    model: Option<String>,
+    /// API key of the project's credential profile. Never serialized: only the model
+    /// client reads it.
+    #[serde(skip)]
+    api_key: Option<String>,
+    /// Bearer token of the signed-in OAuth session, if any. Never serialized.
+    #[serde(skip)]
+    access_token: Option<String>,
+}
+
+/// Resolves the connection for a thread from its project: the project's base URL
+/// wins, then its credential profile, then the app-wide settings.
+fn resolve_model_connection(state: &AppState, thread_id: &str) -> Result<ModelConnection, String> {
+    let (project, profile) = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let thread = db
+            .threads
+            .iter()
+            .find(|thread| thread.id == thread_id)
+            .ok_or_else(|| "Thread not found".to_string())?;
+        let project = db
+            .projects
+            .iter()
+            .find(|project| project.id == thread.project_id)
+            .cloned()
+            .ok_or_else(|| "Project not found".to_string())?;
+        let profile = match &project.credential_profile_id {
+            Some(profile_id) => Some(
+                db.credential_profiles
+                    .iter()
+                    .find(|profile| &profile.id == profile_id)
+                    .cloned()
+                    .ok_or_else(|| format!("Credential profile of project {} no longer exists.", project.name))?,
+            ),
+            None => None,
+        };
+        (project, profile)
+    };
+
+    let api_key = match &profile {
+        Some(profile) if profile.auth_method == "api_key" => {
+            load_secret(state, &credential_profiles::secret_account(&profile.id, "api_key")?)?
+        }
+        _ => None,
+    };
+    let access_token = state
+        .auth
+        .lock()
+        .map_err(|_| "Auth session lock poisoned".to_string())?
+        .access_token(now_ms())
+        .map(str::to_string);
+    Ok(ModelConnection {
+        project_id: project.id,
+        profile_id: profile.as_ref().map(|profile| profile.id.clone()),
+        profile_name: profile.as_ref().map(|profile| profile.name.clone()),
+        auth_method: profile.as_ref().map(|profile| profile.auth_method.clone()),
+        base_url: project
+            .api_base_url
+            .or_else(|| profile.as_ref().map(|profile| profile.base_url.clone())),
+        organization: profile.as_ref().and_then(|profile| profile.organization.clone()),
+        model: profile.as_ref().and_then(|profile| profile.default_model.clone()),
+        api_key,
+        access_token,
+    })
+}
+
+#[tauri::command]
+fn resolve_thread_connection(state: State<AppState>, thread_id: String) -> Result<ModelConnection, String> {
+    resolve_model_connection(&state, &thread_id)
+}
+
+/// App-wide connection settings kept by the UI, used where the thread's project
+/// and credential profile leave a field empty. The base URL is not one of them: it
+/// only comes from stored projects, profiles and settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ChatDefaults {
+    auth_method: Option<String>,
+    model: Option<String>,
+}
+
+/// Payload of `chat:delta`: the next chunk of the assistant reply of a thread.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChatDeltaEvent {
+    thread_id: String,
+    delta: String,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+/// Sends the thread's conversation to the Responses API, streams the reply as
+/// `chat:delta` events and stores it as an assistant message. Credentials are read
+/// from the secret store and the session here, so they never reach the webview.
+#[tauri::command(async)]
+fn send_chat_message(
+    app: AppHandle,
+    state: State<AppState>,
+    thread_id: String,
+    defaults: Option<ChatDefaults>,
+) -> Result<ThreadMessage, String> {
+    let defaults = defaults.unwrap_or_default();
+    let connection = resolve_model_connection(&state, &thread_id)?;
+    let auth_method = non_empty(connection.auth_method)
+        .or(non_empty(defaults.auth_method))
+        .unwrap_or_else(|| "api_key".to_string());
+    let bearer = if auth_method == "oauth" {
+        connection
+            .access_token
+            .ok_or_else(|| "OAuth session is required. Connect your account in Add Key.".to_string())?
+    } else {
+        let api_key = match connection.profile_id {
+            Some(_) => connection.api_key,
+            None => load_secret(&state, API_KEY_ACCOUNT)?,
+        };
+        non_empty(api_key).ok_or_else(|| "API key is required for API mode.".to_string())?
+    };
+
+    let (mut history, settings_base_url) = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        let history: Vec<ThreadMessage> = db
+            .messages
+            .iter()
+            // System messages here are the app's own notes, not instructions for the model.
+            .filter(|message| {
+                message.thread_id == thread_id && (message.role == "user" || message.role == "assistant")
+            })
+            .cloned()
+            .collect();
+        (history, db.settings.api_base_url.clone())
+    };
+    history.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    // The bearer token goes to this URL, so it is checked again even though it was
+    // validated when stored: records may predate validation or be edited by hand.
+    let base_url = credential_profiles::validate_base_url(
+        &non_empty(connection.base_url)
+            .or(non_empty(settings_base_url))
+            .unwrap_or_else(|| model_client::DEFAULT_BASE_URL.to_string()),
+    )?;
+
+    let request = ResponsesRequest {
+        base_url,
+        bearer,
+        organization: connection.organization,
+        model: non_empty(connection.model)
+            .or(non_empty(defaults.model))
+            .unwrap_or_else(|| model_client::DEFAULT_MODEL.to_string()),
+        input: history
+            .into_iter()
+            .map(|message| InputMessage {
+                role: message.role,
+                content: message.content,
+            })
+            .collect(),
+    };
+    let emit_delta = |delta: String| {
+        if !delta.is_empty() {
+            let _ = app.emit(
+                "chat:delta",
+                ChatDeltaEvent {
+                    thread_id: thread_id.clone(),
+                    delta,
+                },
+            );
+        }
+    };
+    // Nothing is shown while the redactor is unavailable; the stored reply is
+    // redacted as a whole anyway.
+    let mut stream = StreamRedaction::default();
+    let reply = model_client::stream_response(&request, |delta| {
+        if let Ok(redactor) = state.redactor.lock() {
+            emit_delta(stream.push(&redactor, delta));
+        }
+    })?;
+    if let Ok(redactor) = state.redactor.lock() {
+        emit_delta(stream.finish(&redactor));
+    }
+    let reply = if reply.trim().is_empty() {
+        "No text output returned from the model.".to_string()
+    } else {
+        reply
+    };
+    append_thread_message(&app, &state, &thread_id, "assistant", &reply)
+}
+
+fn load_data_store_key(state: &AppState) -> Result<Option<StoreKey>, String> {
+    load_secret(state, DATA_STORE_KEY_ACCOUNT)?
+        .map(|value| StoreKey::from_keyring_value(&value))
+        .transpose()
+}
+
+fn save_data_store_key(state: &AppState, key: &StoreKey) -> Result<(), String> {
+    save_secret(state, DATA_STORE_KEY_ACCOUNT, &key.to_keyring_value())
+}
+
+fn data_store_status(data_store: &DataStore) -> DataStoreStatus {
+    match &data_store.locked {
+        Some(locked) => DataStoreStatus {
+            encrypted: true,
+            locked: true,
+            key_id: Some(locked.envelope.key_id.clone()),
+            lock_reason: Some(locked.reason.clone()),
+        },
+        None => DataStoreStatus {
+            encrypted: data_store.key.is_some(),
+            locked: false,
+            key_id: data_store.key.as_ref().map(|key| key.id.clone()),
+            lock_reason: None,
+        },
+    }
+}
+
+#[tauri::command]
+fn get_data_store_status(state: State<AppState>) -> Result<DataStoreStatus, String> {
+    let data_store = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?;
+    Ok(data_store_status(&data_store))
+}
+
+/// Encrypts the store with a new keyring key, or re-encrypts it with a fresh one when
+/// it is already encrypted. Either way a new recovery key is issued and the previous one
+/// stops working.
+fn install_data_store_key(app: &AppHandle, state: &AppState, action: &str) -> Result<DataStoreRecovery, String> {
+    let key = StoreKey::generate();
+    let (recovery, recovery_key) = key.wrap_for_recovery()?;
+    let key_id = key.id.clone();
+    let previous_key_id = {
+        let data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        if data_store.locked.is_some() {
+            return Err("Data store is locked. Recover it with the recovery key first.".to_string());
+        }
+        data_store.key.as_ref().map(|key| key.id.clone())
+    };
+    record_audit(
+        state,
+        action,
+        None,
+        None,
+        serde_json::json!({ "keyId": key_id, "previousKeyId": previous_key_id }),
+    )?;
+
+    let path = db_file_path(app)?;
+    // Held until the new file is in place so no other save interleaves.
+    let mut data_store = state
+        .data_store
+        .lock()
+        .map_err(|_| "Data store lock poisoned".to_string())?;
+    let snapshot = {
+        let db = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        serde_json::to_string_pretty(&*db).map_err(|err| format!("Failed serializing db: {err}"))?
+    };
+    let contents = store_crypto::encrypt(&key, &recovery, snapshot.as_bytes())?;
+
+    // The new file is written aside first, then the keyring takes the new key, then the
+    // file is renamed into place. If the rename fails the previous key goes back, so the
+    // keyring always matches the file on disk.
+    let previous_key = load_secret(state, DATA_STORE_KEY_ACCOUNT)?;
+    let tmp = stage_db_file(&path, &contents)?;
+    if let Err(err) = save_data_store_key(state, &key) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
     }
+    if let Err(err) = fs::rename(&tmp, &path) {
+        let _ = fs::remove_file(&tmp);
+        let restored = match &previous_key {
+            Some(value) => save_secret(state, DATA_STORE_KEY_ACCOUNT, value),
+            None => clear_secret(state, DATA_STORE_KEY_ACCOUNT),
+        };
+        return Err(match restored {
+            Ok(()) => format!("Failed writing db file: {err}"),
+            Err(restore_err) => {
+                format!("Failed writing db file: {err}. Restoring the previous data store key also failed: {restore_err}")
+            }
+        });
+    }
+    let previous = data_store.key.replace(key);
+    data_store.recovery = Some(recovery);
+    // Blobs kept from now on use the new key, so the rest follow it under the same lock.
+    let blobs = audit_blobs_dir(app)
+        .map(|dir| rewrite_audit_blobs(&dir, previous.as_ref(), data_store.key.as_ref()))
+        .unwrap_or_default();
+    drop(data_store);
+    record_audit_blob_rewrite(state, Some(&key_id), blobs);
+    Ok(DataStoreRecovery { key_id, recovery_key })
 }
 
 #[tauri::command]
-fn save_api_key(api_key: String) -> Result<(), String> {
-    let entry = api_key_entry()?;
-    entry
-        .set_password(&api_key)
-        .map_err(|err| format!("Failed to save API key: {err}"))
+fn enable_data_store_encryption(app: AppHandle, state: State<AppState>) -> Result<DataStoreRecovery, String> {
+    {
+        let data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        if data_store.key.is_some() || data_store.locked.is_some() {
+            return Err("Data store is already encrypted.".to_string());
+        }
+    }
+    install_data_store_key(&app, &state, "datastore.encrypt")
 }
 
 #[tauri::command]
-fn load_api_key() -> Result<Option<String>, String> {
-    let entry = api_key_entry()?;
+fn rotate_data_store_key(app: AppHandle, state: State<AppState>) -> Result<DataStoreRecovery, String> {
+    {
+        let data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        if data_store.key.is_none() && data_store.locked.is_none() {
+            return Err("Data store is not encrypted.".to_string());
+        }
+    }
+    install_data_store_key(&app, &state, "datastore.rotate")
+}
 
-    match entry.get_password() {
-        Ok(token) => Ok(Some(token)),
-        Err(KeyringError::NoEntry) => Ok(None),
-        Err(err) => Err(format!("Failed to read API key: {err}")),
+#[tauri::command]
+fn disable_data_store_encryption(app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let key_id = {
+        let data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        if data_store.locked.is_some() {
+            return Err("Data store is locked. Recover it with the recovery key first.".to_string());
+        }
+        data_store
+            .key
+            .as_ref()
+            .map(|key| key.id.clone())
+            .ok_or_else(|| "Data store is not encrypted.".to_string())?
+    };
+    record_audit(&state, "datastore.decrypt", None, None, serde_json::json!({ "keyId": key_id }))?;
+
+    let (key, recovery) = {
+        let mut data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        (data_store.key.take(), data_store.recovery.take())
+    };
+    if let Err(err) = save_db_to_disk(&app, &state) {
+        if let Ok(mut data_store) = state.data_store.lock() {
+            data_store.key = key;
+            data_store.recovery = recovery;
+        }
+        return Err(err);
     }
+    let blobs = {
+        let _data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        audit_blobs_dir(&app)
+            .map(|dir| rewrite_audit_blobs(&dir, key.as_ref(), None))
+            .unwrap_or_default()
+    };
+    record_audit_blob_rewrite(&state, None, blobs);
+
+    clear_secret(&state, DATA_STORE_KEY_ACCOUNT)
+        .map_err(|err| format!("Data store decrypted, but failed to remove its key: {err}"))
 }
 
+/// Opens a locked store with its recovery key and puts the data key back in the keyring.
 #[tauri::command]
-fn clear_api_key() -> Result<(), String> {
-    let entry = api_key_entry()?;
+fn recover_data_store(app: AppHandle, state: State<AppState>, recovery_key: String) -> Result<DataStoreStatus, String> {
+    let (key, db) = {
+        let data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        let locked = data_store
+            .locked
+            .as_ref()
+            .ok_or_else(|| "Data store is not locked.".to_string())?;
+        let key = store_crypto::recover_key(&locked.envelope, &recovery_key)?;
+        let plaintext = store_crypto::decrypt(&locked.envelope, &key)?;
+        let db: AppDb = serde_json::from_slice(&plaintext).map_err(|err| format!("Invalid db json: {err}"))?;
+        (key, db)
+    };
+    save_data_store_key(&state, &key)?;
 
-    match entry.delete_credential() {
-        Ok(_) | Err(KeyringError::NoEntry) => Ok(()),
-        Err(err) => Err(format!("Failed to clear API key: {err}")),
+    let status = {
+        let mut data_store = state
+            .data_store
+            .lock()
+            .map_err(|_| "Data store lock poisoned".to_string())?;
+        let locked = data_store.locked.take();
+        data_store.recovery = locked.map(|locked| locked.envelope.recovery);
+        data_store.key = Some(key);
+        data_store_status(&data_store)
+    };
+    {
+        let mut current = state
+            .db
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+        *current = db;
     }
+    record_audit(
+        &state,
+        "datastore.recover",
+        None,
+        None,
+        serde_json::json!({ "keyId": status.key_id }),
+    )?;
+    init_redactor(&state)?;
+    resume_pending_approvals(&app, &state);
+    Ok(status)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -1409,23 +4468,62 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
             let state = app.state::<AppState>();
+            init_secret_store(&app.handle().clone(), state.inner())
+                .map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
             load_db_from_disk(&app.handle().clone(), state.inner())
                 .map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
+            open_audit_log(&app.handle().clone(), state.inner())
+                .map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
+            init_redactor(state.inner()).map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
+            resume_pending_approvals(&app.handle().clone(), state.inner());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             create_project,
             list_projects,
             touch_project,
+            set_project_trust,
+            get_data_store_status,
+            enable_data_store_encryption,
+            rotate_data_store_key,
+            disable_data_store_encryption,
+            recover_data_store,
+            get_secret_store_status,
+            unlock_secret_store,
+            set_secret_backend,
+            list_credential_profiles,
+            create_credential_profile,
+            update_credential_profile,
+            delete_credential_profile,
+            save_profile_secret,
+            clear_profile_secret,
+            set_project_credential_profile,
+            set_project_api_base_url,
+            resolve_thread_connection,
+            send_chat_message,
             create_thread,
             list_threads,
             add_thread_message,
             list_thread_messages,
             run_task,
             set_thread_permission,
+            set_thread_sandbox,
+            set_thread_network,
             cancel_task,
+            approve_task,
+            reject_task,
+            list_pending_approvals,
             list_tasks,
             list_task_logs,
+            list_task_artifacts,
+            open_task_artifact,
+            get_task_test_results,
+            get_task_stats,
+            list_command_policy_rules,
+            create_command_policy_rule,
+            update_command_policy_rule,
+            delete_command_policy_rule,
+            evaluate_command_policy,
             set_max_parallel_tasks,
             get_app_settings,
             update_app_settings,
@@ -1441,12 +4539,18 @@ pub fn run() {
             list_workspace_entries,
             read_workspace_file,
             write_workspace_file,
-            wait_for_oauth_callback,
+            verify_audit_log,
+            export_audit_log,
+            start_oauth_login,
+            start_device_login,
+            cancel_oauth_login,
+            restore_oauth_session,
+            get_auth_state,
+            logout,
             save_refresh_token,
-            load_refresh_token,
             clear_refresh_token,
             save_api_key,
-            load_api_key,
+            has_api_key,
             clear_api_key
         ])
         .run(tauri::generate_context!())
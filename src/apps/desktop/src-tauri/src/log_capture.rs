@@ -0,0 +1,172 @@
+use std::io::Read;
+
+/// A piece of process output after carriage-return handling.
+pub(crate) enum LogSegment {
+    /// Intermediate state of a line that is being rewritten with `\r`.
+    Progress(String),
+    /// A finished line, ready to be persisted.
+    Line(String),
+}
+
+/// Splits raw output bytes into lines, folding `\r` rewrites (progress bars from
+/// cargo, npm, pip...) into progress updates so that only the final state of the
+/// line ends up as a log record. `\r\n` is treated as a plain line ending.
+#[derive(Default)]
+pub(crate) struct LineAssembler {
+    current: Vec<u8>,
+    pending_cr: bool,
+    last_progress: Option<String>,
+}
+
+impl LineAssembler {
+    pub(crate) fn push(&mut self, chunk: &[u8], out: &mut Vec<LogSegment>) {
+        for &byte in chunk {
+            match byte {
+                b'\n' => {
+                    self.pending_cr = false;
+                    out.push(self.take_line());
+                }
+                b'\r' => {
+                    if self.pending_cr {
+                        self.flush_progress(out);
+                    }
+                    self.pending_cr = true;
+                }
+                _ => {
+                    if self.pending_cr {
+                        self.pending_cr = false;
+                        self.flush_progress(out);
+                    }
+                    self.current.push(byte);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> Option<LogSegment> {
+        self.pending_cr = false;
+        if self.current.is_empty() && self.last_progress.is_none() {
+            return None;
+        }
+        Some(self.take_line())
+    }
+
+    fn flush_progress(&mut self, out: &mut Vec<LogSegment>) {
+        if self.current.is_empty() {
+            return;
+        }
+
+        let text = String::from_utf8_lossy(&self.current).to_string();
+        self.current.clear();
+        if !text.trim().is_empty() {
+            self.last_progress = Some(text.clone());
+        }
+        out.push(LogSegment::Progress(text));
+    }
+
+    fn take_line(&mut self) -> LogSegment {
+        let mut line = String::from_utf8_lossy(&self.current).to_string();
+        self.current.clear();
+        // A progress bar that was cleared right before the newline keeps its last visible state.
+        if let Some(progress) = self.last_progress.take() {
+            if line.trim().is_empty() {
+                line = progress;
+            }
+        }
+        LogSegment::Line(line)
+    }
+}
+
+/// Reads `reader` until EOF, handing every segment to `on_segment`.
+pub(crate) fn read_segments<R: Read>(mut reader: R, mut on_segment: impl FnMut(LogSegment)) {
+    let mut assembler = LineAssembler::default();
+    let mut buffer = [0u8; 8192];
+    let mut segments = Vec::new();
+
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+
+        assembler.push(&buffer[..read], &mut segments);
+        for segment in segments.drain(..) {
+            on_segment(segment);
+        }
+    }
+
+    if let Some(segment) = assembler.finish() {
+        on_segment(segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reader that hands out its input in the given chunks, one per `read` call.
+    struct Chunked(Vec<Vec<u8>>);
+
+    impl Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn segments(chunks: &[&[u8]]) -> Vec<String> {
+        let mut out = Vec::new();
+        read_segments(Chunked(chunks.iter().map(|chunk| chunk.to_vec()).collect()), |segment| {
+            out.push(match segment {
+                LogSegment::Progress(text) => format!("progress:{text}"),
+                LogSegment::Line(text) => format!("line:{text}"),
+            })
+        });
+        out
+    }
+
+    #[test]
+    fn folds_carriage_return_progress() {
+        assert_eq!(
+            segments(&[b"10%\r20%\r100%\r"]),
+            ["progress:10%", "progress:20%", "line:100%"]
+        );
+        // A bar cleared with spaces before the newline keeps its last visible state.
+        assert_eq!(
+            segments(&[b"50%\r100%\r    \n"]),
+            ["progress:50%", "progress:100%", "line:100%"]
+        );
+    }
+
+    #[test]
+    fn treats_crlf_as_a_line_ending() {
+        assert_eq!(segments(&[b"one\r\ntwo\r", b"\nthree\r\n"]), ["line:one", "line:two", "line:three"]);
+    }
+
+    #[test]
+    fn keeps_a_trailing_partial_line() {
+        assert_eq!(segments(&[b"done\npartial"]), ["line:done", "line:partial"]);
+        assert_eq!(segments(&[b"done\n"]), ["line:done"]);
+    }
+
+    #[test]
+    fn progress_followed_by_a_normal_line() {
+        assert_eq!(
+            segments(&[b"Compiling 1/2\rCompiling 2/2\r", b"Finished\nnext\n"]),
+            ["progress:Compiling 1/2", "progress:Compiling 2/2", "line:Finished", "line:next"]
+        );
+    }
+
+    #[test]
+    fn joins_utf8_split_across_reads() {
+        let text = "héllo ✓\n".as_bytes();
+        // Split inside both the two-byte "é" and the three-byte "✓".
+        assert_eq!(segments(&[&text[..2], &text[2..8], &text[8..]]), ["line:héllo ✓"]);
+    }
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+pub(crate) const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub(crate) const DEFAULT_MODEL: &str = "gpt-4.1-mini";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Longest silence tolerated between two chunks of a streamed response.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct InputMessage {
+    pub(crate) role: String,
+    pub(crate) content: String,
+}
+
+/// A streamed request to the Responses API. `bearer` is an API key or an OAuth
+/// access token and never leaves the backend.
+pub(crate) struct ResponsesRequest {
+    pub(crate) base_url: String,
+    pub(crate) bearer: String,
+    pub(crate) organization: Option<String>,
+    pub(crate) model: String,
+    pub(crate) input: Vec<InputMessage>,
+}
+
+#[derive(Serialize)]
+struct RequestBody<'a> {
+    model: &'a str,
+    input: &'a [InputMessage],
+    stream: bool,
+}
+
+/// Error body the API returns with a failed status.
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    error: ApiError,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+/// Sends `request` and calls `on_delta` with every chunk of output text as it
+/// arrives. Returns the full text once the response completes.
+pub(crate) fn stream_response(request: &ResponsesRequest, on_delta: impl FnMut(&str)) -> Result<String, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(STREAM_IDLE_TIMEOUT)
+        .build();
+    let url = format!("{}/responses", request.base_url.trim().trim_end_matches('/'));
+    let mut call = agent
+        .post(&url)
+        .set("Authorization", &format!("Bearer {}", request.bearer))
+        .set("Accept", "text/event-stream");
+    if let Some(organization) = request.organization.as_deref().filter(|value| !value.trim().is_empty()) {
+        call = call.set("OpenAI-Organization", organization);
+    }
+    let body = RequestBody {
+        model: &request.model,
+        input: &request.input,
+        stream: true,
+    };
+    match call.send_json(&body) {
+        Ok(response) => read_events(BufReader::new(response.into_reader()), on_delta),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(match serde_json::from_str::<ApiErrorBody>(&body) {
+                Ok(ApiErrorBody { error }) => format!("Model request failed with status {status}: {}", error.message),
+                Err(_) => format!("Model request failed with status {status}."),
+            })
+        }
+        Err(err) => Err(format!("Model request failed: {err}")),
+    }
+}
+
+/// Reads server-sent events until the response completes or fails.
+fn read_events(reader: impl BufRead, mut on_delta: impl FnMut(&str)) -> Result<String, String> {
+    let mut text = String::new();
+    let mut data: Vec<String> = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|err| format!("Model stream interrupted: {err}"))?;
+        if line.is_empty() {
+            if data.is_empty() {
+                continue;
+            }
+            let payload = data.join("\n");
+            data.clear();
+            if handle_event(&payload, &mut text, &mut on_delta)? {
+                return Ok(text);
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+        // `event:`, `id:` and `:` comment lines carry nothing the payload lacks.
+    }
+    if !data.is_empty() && handle_event(&data.join("\n"), &mut text, &mut on_delta)? {
+        return Ok(text);
+    }
+    Err("Model stream ended before the response completed.".to_string())
+}
+
+/// Applies one event to `text`. Returns true once the response is finished.
+fn handle_event(payload: &str, text: &mut String, on_delta: &mut impl FnMut(&str)) -> Result<bool, String> {
+    if payload == "[DONE]" {
+        return Ok(true);
+    }
+    let event: Value = serde_json::from_str(payload).map_err(|err| format!("Invalid model stream event: {err}"))?;
+    match event["type"].as_str().unwrap_or_default() {
+        "response.output_text.delta" => {
+            if let Some(delta) = event["delta"].as_str() {
+                text.push_str(delta);
+                on_delta(delta);
+            }
+            Ok(false)
+        }
+        "response.completed" | "response.incomplete" => Ok(true),
+        "response.failed" => Err(event_error(&event["response"]["error"], "The model response failed.")),
+        "error" => Err(event_error(&event, "The model stream reported an error.")),
+        _ => Ok(false),
+    }
+}
+
+fn event_error(error: &Value, fallback: &str) -> String {
+    error["message"]
+        .as_str()
+        .or_else(|| error["error"]["message"].as_str())
+        .map(|message| format!("Model request failed: {message}"))
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    struct Captured {
+        headers: HashMap<String, String>,
+        body: Value,
+    }
+
+    /// Stand-in API that answers one request with `status` and `body`, and hands the
+    /// request it received back to the test.
+    fn stand_in(status: &'static str, content_type: &'static str, body: String) -> (String, mpsc::Receiver<Captured>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/v1/", listener.local_addr().unwrap().port());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let request = test_support::read_request(&stream);
+            assert!(request.request_line.starts_with("POST /v1/responses "), "{}", request.request_line);
+            test_support::respond(&stream, status, content_type, &body);
+            sender
+                .send(Captured {
+                    headers: request.headers,
+                    body: serde_json::from_slice(&request.body).unwrap(),
+                })
+                .unwrap();
+        });
+        (url, receiver)
+    }
+
+    fn request(base_url: String) -> ResponsesRequest {
+        ResponsesRequest {
+            base_url,
+            bearer: "sk-test".to_string(),
+            organization: Some("org-1".to_string()),
+            model: "gpt-test".to_string(),
+            input: vec![InputMessage {
+                role: "user".to_string(),
+                content: "Hello".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn streams_output_text_deltas() {
+        let body = concat!(
+            ": keep-alive\n\n",
+            "event: response.created\ndata: {\"type\":\"response.created\",\"response\":{\"id\":\"resp_1\"}}\n\n",
+            "event: response.output_text.delta\ndata: {\"type\":\"response.output_text.delta\",\"delta\":\"Hel\"}\n\n",
+            "event: response.output_text.delta\ndata: {\"type\":\"response.output_text.delta\",\"delta\":\"lo!\"}\n\n",
+            "event: response.completed\ndata: {\"type\":\"response.completed\",\"response\":{\"id\":\"resp_1\"}}\n\n",
+        )
+        .to_string();
+        let (url, captured) = stand_in("200 OK", "text/event-stream", body);
+
+        let mut deltas = Vec::new();
+        let text = stream_response(&request(url), |delta| deltas.push(delta.to_string())).unwrap();
+        assert_eq!(text, "Hello!");
+        assert_eq!(deltas, ["Hel", "lo!"]);
+
+        let captured = captured.recv().unwrap();
+        assert_eq!(captured.headers["authorization"], "Bearer sk-test");
+        assert_eq!(captured.headers["openai-organization"], "org-1");
+        assert_eq!(captured.body["model"], "gpt-test");
+        assert_eq!(captured.body["stream"], true);
+        assert_eq!(captured.body["input"][0]["content"], "Hello");
+    }
+
+    #[test]
+    fn reports_status_and_stream_errors() {
+        let (url, _captured) = stand_in(
+            "401 Unauthorized",
+            "application/json",
+            r#"{"error":{"message":"Incorrect API key provided."}}"#.to_string(),
+        );
+        let err = stream_response(&request(url), |_| {}).unwrap_err();
+        assert!(err.contains("401") && err.contains("Incorrect API key"), "{err}");
+
+        let body = concat!(
+            "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\n",
+            "data: {\"type\":\"response.failed\",\"response\":{\"error\":{\"message\":\"server overloaded\"}}}\n\n",
+        );
+        let (url, _captured) = stand_in("200 OK", "text/event-stream", body.to_string());
+        let err = stream_response(&request(url), |_| {}).unwrap_err();
+        assert!(err.contains("server overloaded"), "{err}");
+    }
+
+    #[test]
+    fn rejects_a_stream_cut_short() {
+        let events = "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\n".as_bytes();
+        assert!(read_events(events, |_| {}).unwrap_err().contains("ended"));
+        let done = "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\ndata: [DONE]\n".as_bytes();
+        assert_eq!(read_events(done, |_| {}).unwrap(), "Hi");
+    }
+}
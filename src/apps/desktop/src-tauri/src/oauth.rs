@@ -0,0 +1,736 @@
+use crate::store_crypto::random_bytes;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+pub(crate) const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OAuthConfig {
+    pub(crate) client_id: String,
+    pub(crate) authorize_url: String,
+    pub(crate) token_url: String,
+    pub(crate) redirect_uri: String,
+    #[serde(default)]
+    pub(crate) scope: String,
+    /// Token revocation endpoint (RFC 7009). Logout skips revocation without one.
+    #[serde(default)]
+    pub(crate) revocation_url: Option<String>,
+    /// Device authorization endpoint (RFC 8628), for the device code login.
+    #[serde(default)]
+    pub(crate) device_authorization_url: Option<String>,
+}
+
+impl OAuthConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.client_id.trim().is_empty() || self.authorize_url.trim().is_empty() || self.token_url.trim().is_empty() {
+            return Err("OAuth config missing. Client id, authorize URL and token URL are required.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// PKCE verifier and its S256 challenge (RFC 7636).
+pub(crate) struct Pkce {
+    pub(crate) verifier: String,
+    pub(crate) challenge: String,
+}
+
+/// 32 random bytes, base64url encoded: 43 characters, as RFC 7636 recommends.
+pub(crate) fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(random_bytes::<32>())
+}
+
+pub(crate) fn s256_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub(crate) fn generate_pkce() -> Pkce {
+    let verifier = random_token();
+    Pkce {
+        challenge: s256_challenge(&verifier),
+        verifier,
+    }
+}
+
+pub(crate) fn authorization_url(config: &OAuthConfig, pkce: &Pkce, state: &str, nonce: &str) -> Result<String, String> {
+    let mut url = Url::parse(&config.authorize_url).map_err(|err| format!("Invalid OAuth authorize URL: {err}"))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &config.client_id)
+        .append_pair("redirect_uri", &config.redirect_uri)
+        .append_pair("scope", &config.scope)
+        .append_pair("code_challenge", &pkce.challenge)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("state", state)
+        .append_pair("nonce", nonce);
+    Ok(url.to_string())
+}
+
+/// The authorization code from a callback request target such as
+/// `/callback?code=...&state=...`. `state` is checked before anything else, so an
+/// error response that does not carry it is not trusted either.
+pub(crate) fn callback_code(target: &str, expected_state: &str) -> Result<String, String> {
+    let url = Url::parse("http://127.0.0.1")
+        .and_then(|base| base.join(target))
+        .map_err(|_| "Invalid callback request.".to_string())?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    if param("state").as_deref() != Some(expected_state) {
+        return Err("Invalid OAuth state. Login attempt aborted.".to_string());
+    }
+    if let Some(error) = param("error") {
+        return Err(match param("error_description") {
+            Some(description) => format!("OAuth provider returned error: {error} ({description})"),
+            None => format!("OAuth provider returned error: {error}"),
+        });
+    }
+    param("code")
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| "Missing code on callback URL.".to_string())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct TokenResponse {
+    pub(crate) access_token: String,
+    #[serde(default = "default_token_type")]
+    pub(crate) token_type: String,
+    #[serde(default)]
+    pub(crate) expires_in: Option<u64>,
+    #[serde(default)]
+    pub(crate) refresh_token: Option<String>,
+    #[serde(default)]
+    pub(crate) scope: Option<String>,
+}
+
+fn default_token_type() -> String {
+    "Bearer".to_string()
+}
+
+/// Error body of a failed token request (RFC 6749 section 5.2).
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenErrorBody {
+    pub(crate) error: String,
+    #[serde(default)]
+    pub(crate) error_description: Option<String>,
+}
+
+/// Why a token request failed. Refreshing retries `Unavailable` and gives up on
+/// `Rejected`.
+#[derive(Debug)]
+pub(crate) enum TokenFailure {
+    /// The server answered with an OAuth error such as `invalid_grant`.
+    Rejected { error: String, message: String },
+    /// Network errors, 5xx and 429 responses: worth trying again later.
+    Unavailable(String),
+}
+
+impl std::fmt::Display for TokenFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rejected { message, .. } | Self::Unavailable(message) => f.write_str(message),
+        }
+    }
+}
+
+/// POSTs a form to the token endpoint and decodes the token response.
+pub(crate) fn post_token_request(token_url: &str, form: &[(&str, &str)]) -> Result<TokenResponse, TokenFailure> {
+    let agent = ureq::AgentBuilder::new().timeout(TOKEN_REQUEST_TIMEOUT).build();
+    let response = agent
+        .post(token_url)
+        .set("Accept", "application/json")
+        .send_form(form);
+    match response {
+        Ok(response) => response
+            .into_json::<TokenResponse>()
+            .map_err(|err| TokenFailure::Unavailable(format!("Invalid token response: {err}"))),
+        Err(ureq::Error::Status(status, _)) if status >= 500 || status == 429 => Err(TokenFailure::Unavailable(
+            format!("Token request failed with status {status}."),
+        )),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            let (error, message) = match serde_json::from_str::<TokenErrorBody>(&body) {
+                Ok(TokenErrorBody {
+                    error,
+                    error_description: Some(description),
+                }) => {
+                    let message = format!("Token request failed with status {status}: {error} ({description})");
+                    (error, message)
+                }
+                Ok(TokenErrorBody { error, .. }) => {
+                    let message = format!("Token request failed with status {status}: {error}");
+                    (error, message)
+                }
+                Err(_) => (String::new(), format!("Token request failed with status {status}.")),
+            };
+            Err(TokenFailure::Rejected { error, message })
+        }
+        Err(err) => Err(TokenFailure::Unavailable(format!("Token request failed: {err}"))),
+    }
+}
+
+pub(crate) fn exchange_code(config: &OAuthConfig, code: &str, verifier: &str) -> Result<TokenResponse, String> {
+    post_token_request(
+        &config.token_url,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &config.redirect_uri),
+            ("code_verifier", verifier),
+        ],
+    )
+    .map_err(|failure| failure.to_string())
+}
+
+/// Revokes `token` (RFC 7009). The server answers 200 even for tokens it no longer
+/// knows, so any other answer is a failure.
+pub(crate) fn revoke_token(revocation_url: &str, client_id: &str, token: &str, hint: &str) -> Result<(), String> {
+    let agent = ureq::AgentBuilder::new().timeout(TOKEN_REQUEST_TIMEOUT).build();
+    let response = agent.post(revocation_url).send_form(&[
+        ("token", token),
+        ("token_type_hint", hint),
+        ("client_id", client_id),
+    ]);
+    match response {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(match serde_json::from_str::<TokenErrorBody>(&body) {
+                Ok(TokenErrorBody { error, .. }) => format!("Revocation failed with status {status}: {error}"),
+                Err(_) => format!("Revocation failed with status {status}."),
+            })
+        }
+        Err(err) => Err(format!("Revocation failed: {err}")),
+    }
+}
+
+pub(crate) fn refresh_tokens(token_url: &str, client_id: &str, refresh_token: &str) -> Result<TokenResponse, TokenFailure> {
+    post_token_request(
+        token_url,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+        ],
+    )
+}
+
+/// Largest request head the callback server reads before giving up on a connection.
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+/// How long one connection may take to send its request head, so a stalled client
+/// cannot hold up the login.
+const CONNECTION_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const WAITING: u8 = 0;
+const CANCELLED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+/// Stops a pending `CallbackServer::wait`. The server blocks in `accept`, so stopping
+/// sets the reason and then connects once to wake it.
+#[derive(Clone)]
+pub(crate) struct CallbackCancel {
+    addr: SocketAddr,
+    state: Arc<AtomicU8>,
+}
+
+impl CallbackCancel {
+    pub(crate) fn cancel(&self) {
+        self.stop(CANCELLED);
+    }
+
+    fn stop(&self, reason: u8) {
+        if self
+            .state
+            .compare_exchange(WAITING, reason, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            let _ = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1));
+        }
+    }
+
+    fn stopped(&self) -> Result<(), String> {
+        match self.state.load(Ordering::SeqCst) {
+            CANCELLED => Err("OAuth login cancelled.".to_string()),
+            TIMED_OUT => Err("OAuth callback timeout.".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Loopback listener for the authorization redirect. Only a GET on the redirect path
+/// that carries `state` and a `code` or `error` ends the wait; anything else (favicon
+/// requests, port scanners, stray POSTs) is answered and ignored.
+pub(crate) struct CallbackServer {
+    listener: TcpListener,
+    path: String,
+    redirect_uri: String,
+    cancel: CallbackCancel,
+}
+
+struct CallbackRequest {
+    method: String,
+    target: String,
+}
+
+impl CallbackServer {
+    /// Binds the port of `redirect_uri`, which must be a loopback URI. Port 0 picks a
+    /// free port; `redirect_uri()` then reports the one actually bound.
+    pub(crate) fn bind(redirect_uri: &str) -> Result<Self, String> {
+        let mut redirect = Url::parse(redirect_uri).map_err(|err| format!("Invalid OAuth redirect URI: {err}"))?;
+        if !matches!(redirect.host_str(), Some("127.0.0.1") | Some("localhost")) {
+            return Err("OAuth redirect URI must use localhost/127.0.0.1 for loopback flow.".to_string());
+        }
+        let port = redirect
+            .port()
+            .ok_or_else(|| "OAuth redirect URI requires an explicit port (0 picks a free one).".to_string())?;
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|err| format!("Failed to start callback server: {err}"))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|err| format!("Failed to start callback server: {err}"))?;
+        redirect
+            .set_port(Some(addr.port()))
+            .map_err(|_| "Invalid OAuth redirect URI.".to_string())?;
+        Ok(Self {
+            listener,
+            path: redirect.path().to_string(),
+            redirect_uri: redirect.to_string(),
+            cancel: CallbackCancel {
+                addr,
+                state: Arc::new(AtomicU8::new(WAITING)),
+            },
+        })
+    }
+
+    pub(crate) fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    pub(crate) fn cancel_handle(&self) -> CallbackCancel {
+        self.cancel.clone()
+    }
+
+    /// Waits for the redirect carrying `expected_state` and returns its authorization
+    /// code. The browser gets a success or an error page either way.
+    pub(crate) fn wait(&self, expected_state: &str, timeout: Duration) -> Result<String, String> {
+        let (finished, finished_rx) = mpsc::channel::<()>();
+        let timer = self.cancel.clone();
+        thread::spawn(move || {
+            if finished_rx.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                timer.stop(TIMED_OUT);
+            }
+        });
+        let result = self.accept_callback(expected_state);
+        drop(finished);
+        result
+    }
+
+    fn accept_callback(&self, expected_state: &str) -> Result<String, String> {
+        loop {
+            let (mut stream, _) = self
+                .listener
+                .accept()
+                .map_err(|err| format!("Callback listener failed: {err}"))?;
+            self.cancel.stopped()?;
+
+            let Some(request) = read_request(&mut stream) else {
+                continue;
+            };
+            let Ok(url) = Url::parse("http://127.0.0.1").and_then(|base| base.join(&request.target)) else {
+                respond(&mut stream, "400 Bad Request", &error_page("Invalid request."));
+                continue;
+            };
+            if url.path() != self.path {
+                respond(&mut stream, "404 Not Found", &error_page("Not found."));
+                continue;
+            }
+            if request.method != "GET" {
+                respond(&mut stream, "405 Method Not Allowed", &error_page("Method not allowed."));
+                continue;
+            }
+            let has = |name: &str| url.query_pairs().any(|(key, _)| key == name);
+            if !has("error") && !has("code") {
+                respond(&mut stream, "400 Bad Request", &error_page("This is not an authorization response."));
+                continue;
+            }
+            // A response for another attempt, or a forged one, must not end this login.
+            if !url.query_pairs().any(|(key, value)| key == "state" && value == expected_state) {
+                respond(&mut stream, "400 Bad Request", &error_page("This response is for a different login attempt."));
+                continue;
+            }
+
+            let result = callback_code(&request.target, expected_state);
+            match &result {
+                Ok(_) => respond(&mut stream, "200 OK", SUCCESS_PAGE),
+                Err(err) => respond(&mut stream, "400 Bad Request", &error_page(err)),
+            }
+            return result;
+        }
+    }
+}
+
+/// Reads the request head of one connection. `None` for anything that is not an
+/// HTTP/1.x request, which is dropped without a reply.
+fn read_request(stream: &mut TcpStream) -> Option<CallbackRequest> {
+    stream.set_read_timeout(Some(CONNECTION_READ_TIMEOUT)).ok()?;
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return None;
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => head.extend_from_slice(&chunk[..read]),
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.lines().next()?.split(' ');
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !version.starts_with("HTTP/1.") || !target.starts_with('/') {
+        return None;
+    }
+    Some(CallbackRequest {
+        method: method.to_string(),
+        target: target.to_string(),
+    })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+const SUCCESS_PAGE: &str = "<!doctype html><html><body><h2>Login concluido</h2><p>Voce pode fechar esta aba e voltar para o app.</p></body></html>";
+
+fn error_page(message: &str) -> String {
+    let escaped = message
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+    format!("<!doctype html><html><body><h2>Falha no login</h2><p>{escaped}</p><p>Volte para o app e tente novamente.</p></body></html>")
+}
+
+/// Runs the authorization code flow with PKCE on a bound `server`: hands the
+/// authorization URL to `open_browser`, waits for the callback and exchanges the code.
+pub(crate) fn login_with_loopback(
+    config: &OAuthConfig,
+    server: &CallbackServer,
+    timeout: Duration,
+    open_browser: impl FnOnce(&str) -> Result<(), String>,
+) -> Result<TokenResponse, String> {
+    config.validate()?;
+    // With port 0 the redirect URI only becomes known once the server is bound.
+    let config = OAuthConfig {
+        redirect_uri: server.redirect_uri().to_string(),
+        ..config.clone()
+    };
+
+    let pkce = generate_pkce();
+    let state = random_token();
+    let nonce = random_token();
+    open_browser(&authorization_url(&config, &pkce, &state, &nonce)?)?;
+
+    let code = server.wait(&state, timeout)?;
+    exchange_code(&config, &code, &pkce.verifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// What the mock authorization server saw on its token endpoint.
+    #[derive(Default)]
+    struct Seen {
+        challenge: Option<String>,
+        token_form: Option<HashMap<String, String>>,
+    }
+
+    /// Serves one token request. The token endpoint answers like a real server would,
+    /// checking the code and the PKCE verifier against the challenge the browser saw.
+    fn mock_token_server(seen: Arc<Mutex<Seen>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let request = test_support::read_request(&stream);
+            assert!(request.request_line.starts_with("POST /token "), "{}", request.request_line);
+            let form = request.form();
+            let mut seen = seen.lock().unwrap();
+            let verifier_ok = seen.challenge.as_deref() == form.get("code_verifier").map(|v| s256_challenge(v)).as_deref();
+            let code_ok = form.get("code").map(String::as_str) == Some("mock-code");
+            seen.token_form = Some(form);
+            let (status, body) = if verifier_ok && code_ok {
+                (
+                    "200 OK",
+                    r#"{"access_token":"mock-access","token_type":"Bearer","expires_in":3600,"refresh_token":"mock-refresh","scope":"openid"}"#,
+                )
+            } else {
+                ("400 Bad Request", r#"{"error":"invalid_grant","error_description":"bad verifier"}"#)
+            };
+            test_support::respond(&stream, status, "application/json", body);
+        });
+        format!("http://127.0.0.1:{port}/token")
+    }
+
+    fn config(token_url: String) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "desktop-client".to_string(),
+            authorize_url: "https://auth.example.test/authorize".to_string(),
+            token_url,
+            redirect_uri: "http://127.0.0.1:0/callback".to_string(),
+            scope: "openid".to_string(),
+            revocation_url: None,
+            device_authorization_url: None,
+        }
+    }
+
+    /// Sends `request` as-is to the loopback server and returns the raw response.
+    fn send_raw(redirect_uri: &str, request: &str) -> String {
+        let port = Url::parse(redirect_uri).unwrap().port().unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    fn get(redirect_uri: &str, target: &str) -> String {
+        send_raw(redirect_uri, &format!("GET {target} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n"))
+    }
+
+    /// Plays the browser: reads the authorization URL and follows the redirect back to
+    /// the loopback listener with `query`, where `{state}` is the state that was sent.
+    /// Stray requests in `noise` reach the listener first. The join handle yields the
+    /// page the browser was shown.
+    fn browser(
+        seen: Arc<Mutex<Seen>>,
+        noise: Vec<&'static str>,
+        query: &'static str,
+        page: Arc<Mutex<Option<thread::JoinHandle<String>>>>,
+    ) -> impl FnOnce(&str) -> Result<(), String> {
+        move |authorize_url: &str| {
+            let url = Url::parse(authorize_url).unwrap();
+            let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+            assert_eq!(params["code_challenge_method"], "S256");
+            assert_eq!(params["response_type"], "code");
+            seen.lock().unwrap().challenge = Some(params["code_challenge"].clone());
+            let redirect_uri = params["redirect_uri"].clone();
+            let target = format!("/callback?{}", query.replace("{state}", &params["state"]));
+            *page.lock().unwrap() = Some(thread::spawn(move || {
+                for request in noise {
+                    let response = send_raw(&redirect_uri, request);
+                    assert!(!response.starts_with("HTTP/1.1 200"), "{request}: {response}");
+                }
+                get(&redirect_uri, &target)
+            }));
+            Ok(())
+        }
+    }
+
+    fn login(
+        config: &OAuthConfig,
+        seen: Arc<Mutex<Seen>>,
+        noise: Vec<&'static str>,
+        query: &'static str,
+    ) -> (Result<TokenResponse, String>, String) {
+        let server = CallbackServer::bind(&config.redirect_uri).unwrap();
+        let page = Arc::new(Mutex::new(None));
+        let result = login_with_loopback(
+            config,
+            &server,
+            Duration::from_secs(10),
+            browser(seen, noise, query, page.clone()),
+        );
+        let page = page.lock().unwrap().take().unwrap().join().unwrap();
+        (result, page)
+    }
+
+    #[test]
+    fn completes_login_against_mock_server() {
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let config = config(mock_token_server(seen.clone()));
+        let (result, page) = login(&config, seen.clone(), vec![], "code=mock-code&state={state}");
+        let tokens = result.unwrap();
+        assert_eq!(tokens.access_token, "mock-access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("mock-refresh"));
+        assert_eq!(tokens.expires_in, Some(3600));
+        assert!(page.starts_with("HTTP/1.1 200") && page.contains("Login concluido"), "{page}");
+
+        let seen = seen.lock().unwrap();
+        let form = seen.token_form.as_ref().unwrap();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["client_id"], "desktop-client");
+        // Port 0 was replaced by the port actually bound.
+        let redirect = Url::parse(&form["redirect_uri"]).unwrap();
+        assert_ne!(redirect.port(), Some(0));
+        assert_eq!(redirect.path(), "/callback");
+    }
+
+    #[test]
+    fn ignores_unrelated_requests() {
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let config = config(mock_token_server(seen.clone()));
+        let noise = vec![
+            "GET /favicon.ico HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+            "POST /callback?code=mock-code HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+            "GET /callback HTTP/1.1\r\n\r\n",
+            "\x16\x03\x01 not http\r\n\r\n",
+        ];
+        let (result, page) = login(&config, seen, noise, "code=mock-code&state={state}");
+        assert_eq!(result.unwrap().access_token, "mock-access");
+        assert!(page.starts_with("HTTP/1.1 200"), "{page}");
+    }
+
+    #[test]
+    fn keeps_waiting_past_responses_with_the_wrong_state() {
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let config = config(mock_token_server(seen.clone()));
+        let noise = vec![
+            "GET /callback?code=mock-code&state=forged HTTP/1.1\r\n\r\n",
+            "GET /callback?code=mock-code HTTP/1.1\r\n\r\n",
+            "GET /callback?error=access_denied&state=forged HTTP/1.1\r\n\r\n",
+            "GET /callback?error=access_denied HTTP/1.1\r\n\r\n",
+        ];
+        let (result, page) = login(&config, seen, noise, "code=mock-code&state={state}");
+        assert_eq!(result.unwrap().access_token, "mock-access");
+        assert!(page.starts_with("HTTP/1.1 200"), "{page}");
+    }
+
+    #[test]
+    fn callback_code_checks_state_before_anything_else() {
+        let err = callback_code("/callback?error=access_denied&state=forged", "expected").unwrap_err();
+        assert!(err.contains("Invalid OAuth state"), "{err}");
+        let err = callback_code("/callback?error=access_denied", "expected").unwrap_err();
+        assert!(err.contains("Invalid OAuth state"), "{err}");
+        let err = callback_code("/callback?error=access_denied&state=expected", "expected").unwrap_err();
+        assert!(err.contains("access_denied"), "{err}");
+        assert!(callback_code("/callback?code=abc&state=forged", "expected").is_err());
+        assert_eq!(callback_code("/callback?code=abc&state=expected", "expected").unwrap(), "abc");
+    }
+
+    #[test]
+    fn reports_provider_errors() {
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let config = config("http://127.0.0.1:9/token".to_string());
+        let (result, page) = login(&config, seen, vec![], "error=access_denied&error_description=<denied>&state={state}");
+        let err = result.unwrap_err();
+        assert!(err.contains("access_denied"), "{err}");
+        assert!(page.contains("Falha no login") && page.contains("&lt;denied&gt;"), "{page}");
+    }
+
+    #[test]
+    fn surfaces_token_endpoint_errors() {
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let config = config(mock_token_server(seen.clone()));
+        let (result, _) = login(&config, seen, vec![], "code=wrong-code&state={state}");
+        let err = result.unwrap_err();
+        assert!(err.contains("invalid_grant"), "{err}");
+    }
+
+    #[test]
+    fn cancels_and_times_out() {
+        let server = CallbackServer::bind("http://127.0.0.1:0/callback").unwrap();
+        let cancel = server.cancel_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        });
+        let err = server.wait("state", Duration::from_secs(10)).unwrap_err();
+        assert!(err.contains("cancelled"), "{err}");
+
+        let server = CallbackServer::bind("http://localhost:0/callback").unwrap();
+        let err = server.wait("state", Duration::from_millis(100)).unwrap_err();
+        assert!(err.contains("timeout"), "{err}");
+    }
+
+    /// Answers every request with `status` and `body`.
+    fn fixed_response_server(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                test_support::read_request(&stream);
+                test_support::respond(&stream, status, "application/json", body);
+            }
+        });
+        format!("http://127.0.0.1:{port}/token")
+    }
+
+    #[test]
+    fn classifies_refresh_failures() {
+        let rejected = fixed_response_server("400 Bad Request", r#"{"error":"invalid_grant"}"#);
+        match refresh_tokens(&rejected, "desktop-client", "stale") {
+            Err(TokenFailure::Rejected { error, .. }) => assert_eq!(error, "invalid_grant"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let unavailable = fixed_response_server("503 Service Unavailable", "");
+        assert!(matches!(
+            refresh_tokens(&unavailable, "desktop-client", "token"),
+            Err(TokenFailure::Unavailable(_))
+        ));
+
+        let refused = format!("http://127.0.0.1:{}/token", {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        });
+        assert!(matches!(
+            refresh_tokens(&refused, "desktop-client", "token"),
+            Err(TokenFailure::Unavailable(_))
+        ));
+
+        let ok = fixed_response_server("200 OK", r#"{"access_token":"fresh","expires_in":60}"#);
+        let tokens = refresh_tokens(&ok, "desktop-client", "token").unwrap();
+        assert_eq!(tokens.access_token, "fresh");
+        assert_eq!(tokens.token_type, "Bearer");
+    }
+
+    #[test]
+    fn reports_revocation_failures() {
+        let ok = fixed_response_server("200 OK", "");
+        assert!(revoke_token(&ok, "desktop-client", "token", "refresh_token").is_ok());
+
+        let unsupported = fixed_response_server("400 Bad Request", r#"{"error":"unsupported_token_type"}"#);
+        let err = revoke_token(&unsupported, "desktop-client", "token", "access_token").unwrap_err();
+        assert!(err.contains("unsupported_token_type"), "{err}");
+    }
+
+    #[test]
+    fn pkce_challenge_is_unpadded_base64url_sha256() {
+        // SHA-256("abc") = ba7816bf...f20015ad.
+        assert_eq!(s256_challenge("abc"), "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+        let pkce = generate_pkce();
+        assert_eq!(pkce.verifier.len(), 43);
+        assert_eq!(pkce.challenge, s256_challenge(&pkce.verifier));
+    }
+
+    #[test]
+    fn requires_loopback_redirect_with_port() {
+        assert!(CallbackServer::bind("https://example.test/callback").is_err());
+        assert!(CallbackServer::bind("http://127.0.0.1/callback").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::oauth::{self, OAuthConfig, TokenErrorBody, TokenFailure, TokenResponse, TOKEN_REQUEST_TIMEOUT};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// Poll interval when the server does not name one (RFC 8628 section 3.2).
+const DEFAULT_INTERVAL: u64 = 5;
+/// Added to the interval on every `slow_down` (RFC 8628 section 3.5).
+const SLOW_DOWN_STEP: u64 = 5;
+
+/// Response of the device authorization endpoint (RFC 8628 section 3.2).
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct DeviceAuthorization {
+    pub(crate) device_code: String,
+    pub(crate) user_code: String,
+    pub(crate) verification_uri: String,
+    #[serde(default)]
+    pub(crate) verification_uri_complete: Option<String>,
+    pub(crate) expires_in: u64,
+    #[serde(default)]
+    pub(crate) interval: Option<u64>,
+}
+
+/// What the user needs to approve the login on any device. Emitted as
+/// `auth:device-code`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeviceLoginPrompt {
+    pub(crate) user_code: String,
+    pub(crate) verification_uri: String,
+    pub(crate) verification_uri_complete: Option<String>,
+    pub(crate) expires_at: i64,
+}
+
+impl DeviceAuthorization {
+    pub(crate) fn prompt(&self, now_ms: i64) -> DeviceLoginPrompt {
+        DeviceLoginPrompt {
+            user_code: self.user_code.clone(),
+            verification_uri: self.verification_uri.clone(),
+            verification_uri_complete: self.verification_uri_complete.clone(),
+            expires_at: now_ms + self.expires_in as i64 * 1000,
+        }
+    }
+}
+
+/// Stops a device login that is polling. Polling sleeps with `park_timeout`, so
+/// cancelling also unparks the polling thread.
+#[derive(Clone)]
+pub(crate) struct DeviceCancel {
+    cancelled: Arc<AtomicBool>,
+    poller: Thread,
+}
+
+impl DeviceCancel {
+    /// A handle for a poll that will run on the current thread.
+    pub(crate) fn for_current_thread() -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            poller: thread::current(),
+        }
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.poller.unpark();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps for `duration` unless cancelled first. Returns false when cancelled.
+    fn sleep(&self, duration: Duration) -> bool {
+        let until = Instant::now() + duration;
+        while !self.is_cancelled() {
+            let now = Instant::now();
+            if now >= until {
+                return true;
+            }
+            thread::park_timeout(until - now);
+        }
+        false
+    }
+}
+
+fn device_authorization_url(config: &OAuthConfig) -> Result<&str, String> {
+    let url = config.device_authorization_url.as_deref().map(str::trim).unwrap_or_default();
+    if config.client_id.trim().is_empty() || config.token_url.trim().is_empty() || url.is_empty() {
+        return Err(
+            "Device login needs a client id, a token URL and a device authorization URL.".to_string(),
+        );
+    }
+    Ok(url)
+}
+
+pub(crate) fn request_device_code(config: &OAuthConfig) -> Result<DeviceAuthorization, String> {
+    let agent = ureq::AgentBuilder::new().timeout(TOKEN_REQUEST_TIMEOUT).build();
+    let response = agent
+        .post(device_authorization_url(config)?)
+        .set("Accept", "application/json")
+        .send_form(&[("client_id", &config.client_id), ("scope", &config.scope)]);
+    match response {
+        Ok(response) => response
+            .into_json::<DeviceAuthorization>()
+            .map_err(|err| format!("Invalid device authorization response: {err}")),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(match serde_json::from_str::<TokenErrorBody>(&body) {
+                Ok(TokenErrorBody { error, .. }) => format!("Device authorization failed with status {status}: {error}"),
+                Err(_) => format!("Device authorization failed with status {status}."),
+            })
+        }
+        Err(err) => Err(format!("Device authorization failed: {err}")),
+    }
+}
+
+/// Polls the token endpoint until the user approves or denies the login, the device
+/// code expires or `cancel` fires.
+pub(crate) fn poll_device_token(
+    config: &OAuthConfig,
+    device: &DeviceAuthorization,
+    cancel: &DeviceCancel,
+) -> Result<TokenResponse, String> {
+    poll_with_tick(config, device, cancel, Duration::from_secs(1))
+}
+
+/// `poll_device_token` with every server-given number of seconds scaled to `tick`, so
+/// tests can run the interval and `slow_down` rules in milliseconds.
+fn poll_with_tick(
+    config: &OAuthConfig,
+    device: &DeviceAuthorization,
+    cancel: &DeviceCancel,
+    tick: Duration,
+) -> Result<TokenResponse, String> {
+    let deadline = Instant::now() + tick * device.expires_in as u32;
+    let mut interval = device.interval.unwrap_or(DEFAULT_INTERVAL);
+    loop {
+        if !cancel.sleep(tick * interval as u32) {
+            return Err("OAuth login cancelled.".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err("The device code expired before the login was approved.".to_string());
+        }
+
+        let result = oauth::post_token_request(
+            &config.token_url,
+            &[
+                ("grant_type", DEVICE_CODE_GRANT),
+                ("device_code", &device.device_code),
+                ("client_id", &config.client_id),
+            ],
+        );
+        match result {
+            Ok(tokens) => return Ok(tokens),
+            Err(TokenFailure::Rejected { error, message }) => match error.as_str() {
+                "authorization_pending" => {}
+                "slow_down" => interval += SLOW_DOWN_STEP,
+                "access_denied" => return Err("The login was denied.".to_string()),
+                "expired_token" => return Err("The device code expired before the login was approved.".to_string()),
+                _ => return Err(message),
+            },
+            // A dropped connection does not end the login; try again next interval.
+            Err(TokenFailure::Unavailable(_)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::collections::VecDeque;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// Stand-in authorization server: hands out a device code, then answers token
+    /// polls from `replies` in order and records when each poll arrived.
+    struct StandIn {
+        url: String,
+        polls: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    fn stand_in(replies: Vec<(&'static str, &'static str)>) -> StandIn {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let polls = Arc::new(Mutex::new(Vec::new()));
+        let seen = polls.clone();
+        let mut replies: VecDeque<_> = replies.into();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request = test_support::read_request(&stream);
+                let form = request.form();
+                let (status, body) = if request.request_line.starts_with("POST /device ") {
+                    assert_eq!(form["client_id"], "desktop-client");
+                    ("200 OK", r#"{"device_code":"dev-1","user_code":"WDJB-MJHT","verification_uri":"https://auth.example.test/device","expires_in":300,"interval":1}"#)
+                } else {
+                    assert_eq!(form["grant_type"], DEVICE_CODE_GRANT);
+                    assert_eq!(form["device_code"], "dev-1");
+                    seen.lock().unwrap().push(Instant::now());
+                    replies
+                        .pop_front()
+                        .unwrap_or(("400 Bad Request", r#"{"error":"authorization_pending"}"#))
+                };
+                test_support::respond(&stream, status, "application/json", body);
+            }
+        });
+        StandIn { url, polls }
+    }
+
+    fn config(server: &StandIn) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "desktop-client".to_string(),
+            authorize_url: String::new(),
+            token_url: format!("{}/token", server.url),
+            redirect_uri: String::new(),
+            scope: "openid offline_access".to_string(),
+            revocation_url: None,
+            device_authorization_url: Some(format!("{}/device", server.url)),
+        }
+    }
+
+    #[test]
+    fn polls_with_interval_and_slow_down_until_approved() {
+        let server = stand_in(vec![
+            ("400 Bad Request", r#"{"error":"authorization_pending"}"#),
+            ("400 Bad Request", r#"{"error":"slow_down"}"#),
+            ("200 OK", r#"{"access_token":"device-access","refresh_token":"device-refresh","expires_in":3600}"#),
+        ]);
+        let config = config(&server);
+        let device = request_device_code(&config).unwrap();
+        assert_eq!(device.user_code, "WDJB-MJHT");
+        assert_eq!(device.interval, Some(1));
+
+        let tokens = poll_with_tick(&config, &device, &DeviceCancel::for_current_thread(), TICK).unwrap();
+        assert_eq!(tokens.access_token, "device-access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("device-refresh"));
+
+        let polls = server.polls.lock().unwrap();
+        assert_eq!(polls.len(), 3);
+        // After slow_down the interval grows from 1 to 1 + 5 ticks.
+        assert!(polls[2] - polls[1] >= TICK * 6, "{:?}", polls[2] - polls[1]);
+    }
+
+    #[test]
+    fn stops_on_denial_expiry_and_cancel() {
+        let server = stand_in(vec![("400 Bad Request", r#"{"error":"access_denied"}"#)]);
+        let config = config(&server);
+        let device = request_device_code(&config).unwrap();
+        let err = poll_with_tick(&config, &device, &DeviceCancel::for_current_thread(), TICK).unwrap_err();
+        assert!(err.contains("denied"), "{err}");
+
+        let expiring = DeviceAuthorization {
+            expires_in: 3,
+            ..device.clone()
+        };
+        let err = poll_with_tick(&config, &expiring, &DeviceCancel::for_current_thread(), TICK).unwrap_err();
+        assert!(err.contains("expired"), "{err}");
+
+        let cancel = DeviceCancel::for_current_thread();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let err = poll_with_tick(&config, &device, &cancel, Duration::from_secs(1)).unwrap_err();
+        assert!(err.contains("cancelled"), "{err}");
+    }
+
+    #[test]
+    fn requires_device_endpoint() {
+        let server = stand_in(vec![]);
+        let mut config = config(&server);
+        config.device_authorization_url = None;
+        assert!(request_device_code(&config).is_err());
+    }
+}
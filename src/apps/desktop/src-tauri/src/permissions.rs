@@ -0,0 +1,447 @@
+use crate::command_policy::{InvocationVerdict, PolicyAction, PolicyEvaluation};
+use crate::shell_parse::Redirection;
+
+/// What a session may do without asking:
+///
+/// | mode           | commands                                         | file writes        |
+/// |----------------|--------------------------------------------------|--------------------|
+/// | safe           | read-only allow list and `allow` rules only      | denied             |
+/// | normal         | confirmation for commands flagged by the policy  | allowed            |
+/// | danger-confirm | confirmation for every command                   | need confirmation  |
+/// | autonomous     | flagged commands run without confirmation        | allowed            |
+///
+/// Commands denied by the policy never run, whatever the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PermissionMode {
+    Safe,
+    Normal,
+    DangerConfirm,
+    Autonomous,
+}
+
+impl PermissionMode {
+    pub(crate) fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "safe" => Ok(Self::Safe),
+            "normal" => Ok(Self::Normal),
+            "danger-confirm" => Ok(Self::DangerConfirm),
+            "autonomous" => Ok(Self::Autonomous),
+            _ => Err("Invalid permission mode. Use safe, normal, danger-confirm, or autonomous".to_string()),
+        }
+    }
+
+    /// Mode stored on a record. Values are validated when saved, so an unknown one
+    /// means the file was edited by hand and is treated as the most restrictive mode.
+    pub(crate) fn from_stored(mode: &str) -> Self {
+        Self::parse(mode).unwrap_or(Self::Safe)
+    }
+}
+
+pub(crate) enum CommandDecision {
+    Run,
+    Confirm(String),
+    Reject(String),
+}
+
+/// Programs safe mode runs without an explicit `allow` rule. They only read files,
+/// as long as none of `WRITING_OPTIONS` is passed and their positional arguments pass
+/// `positional_args_read_only`.
+const READ_ONLY_PROGRAMS: &[&str] = &[
+    "basename", "cat", "cmp", "cut", "date", "df", "diff", "dirname", "du", "echo", "egrep", "false",
+    "fgrep", "file", "find", "grep", "head", "hostname", "id", "less", "ls", "more", "printf", "pwd",
+    "readlink", "realpath", "rg", "stat", "tail", "test", "tr", "tree", "true", "uname", "wc", "which",
+    "whoami",
+    // cmd
+    "dir", "findstr", "type", "ver", "where",
+    // PowerShell
+    "compare-object", "foreach-object", "format-list", "format-table", "get-childitem", "get-command",
+    "get-content", "get-date", "get-filehash", "get-item", "get-location", "measure-object", "out-string",
+    "resolve-path", "select-object", "select-string", "sort-object", "test-path", "where-object",
+    "write-host", "write-output",
+];
+
+/// Options that make an otherwise read-only program write or prompt.
+const WRITING_OPTIONS: &[&str] = &["-delete", "-fls", "-fprint", "-fprint0", "-fprintf", "-ok", "-okdir", "--output"];
+
+/// Options that let a read-only program run other commands or change system state.
+/// Two-character options also match when bundled (`-ao`) or given a glued value
+/// (`-ofile`).
+const DENIED_PROGRAM_OPTIONS: &[(&str, &[&str])] = &[
+    ("date", &["-s", "--set"]),
+    ("find", &["-exec", "-execdir"]),
+    ("hostname", &["-b", "-F", "--boot", "--file"]),
+    ("less", &["-o", "-O", "--log-file", "--LOG-FILE"]),
+    ("rg", &["--pre", "--pre-glob"]),
+    ("tree", &["-o"]),
+];
+
+const DENIED_GIT_GREP_OPTIONS: &[&str] = &["-O", "--open-files-in-pager"];
+
+const READ_ONLY_GIT_COMMANDS: &[&str] = &[
+    "blame", "cat-file", "describe", "diff", "grep", "log", "ls-files", "ls-tree", "rev-parse", "shortlog",
+    "show", "status",
+];
+
+const READ_ONLY_GIT_BRANCH_OPTIONS: &[&str] =
+    &["-a", "-r", "-v", "-vv", "--all", "--list", "--remotes", "--show-current", "--verbose"];
+
+/// Redirection targets that discard output instead of writing a file.
+const DISCARD_TARGETS: &[&str] = &["/dev/null", "nul", "$null"];
+
+/// Options of `date` whose value is the next argument.
+const DATE_VALUE_OPTIONS: &[&str] = &["-d", "-f", "-r", "--date", "--file", "--reference"];
+
+/// PowerShell parameters of `ForEach-Object` that take a script block.
+const FOREACH_BLOCK_PARAMETERS: &[&str] = &["-begin", "-process", "-end"];
+
+fn opens_file(redirection: &Redirection) -> bool {
+    !redirection.duplicates_descriptor() && !DISCARD_TARGETS.contains(&redirection.target.to_lowercase().as_str())
+}
+
+/// Positional arguments that would make a read-only program change state: a new
+/// clock for `date`, a new name for `hostname`, and a member to invoke on each object
+/// for `ForEach-Object`. Its script blocks are checked as invocations of their own.
+fn positional_args_read_only(program: &str, args: &[String]) -> bool {
+    match program {
+        "date" => {
+            let mut rest = args.iter();
+            while let Some(arg) = rest.next() {
+                if DATE_VALUE_OPTIONS.contains(&arg.as_str()) {
+                    rest.next();
+                } else if !arg.starts_with(['-', '+']) {
+                    return false;
+                }
+            }
+            true
+        }
+        "hostname" => args.iter().all(|arg| arg.starts_with('-')),
+        "foreach-object" => args
+            .iter()
+            .all(|arg| arg.starts_with('{') || FOREACH_BLOCK_PARAMETERS.contains(&arg.to_lowercase().as_str())),
+        _ => true,
+    }
+}
+
+fn matches_option(arg: &str, option: &str) -> bool {
+    if option.starts_with("--") {
+        return arg == option || arg.strip_prefix(option).is_some_and(|rest| rest.starts_with('='));
+    }
+    if option.len() == 2 {
+        let letter = &option[1..];
+        return arg.starts_with('-') && !arg.starts_with("--") && arg[1..].contains(letter);
+    }
+    arg == option
+}
+
+fn passes_any(args: &[String], options: &[&str]) -> bool {
+    args.iter().any(|arg| options.iter().any(|option| matches_option(arg, option)))
+}
+
+fn is_read_only_git(args: &[String]) -> bool {
+    let mut rest = args;
+    loop {
+        match rest.first().map(String::as_str) {
+            Some("-C") => rest = rest.get(2..).unwrap_or_default(),
+            Some("--no-pager") => rest = &rest[1..],
+            _ => break,
+        }
+    }
+    let Some((subcommand, options)) = rest.split_first() else {
+        return false;
+    };
+    match subcommand.as_str() {
+        "branch" => options
+            .iter()
+            .all(|option| READ_ONLY_GIT_BRANCH_OPTIONS.contains(&option.as_str())),
+        "remote" => options.iter().all(|option| option == "-v"),
+        "grep" => !passes_any(options, DENIED_GIT_GREP_OPTIONS),
+        other => READ_ONLY_GIT_COMMANDS.contains(&other),
+    }
+}
+
+pub(crate) fn is_read_only_invocation(verdict: &InvocationVerdict) -> bool {
+    if verdict
+        .args
+        .iter()
+        .any(|arg| WRITING_OPTIONS.iter().any(|option| arg == option || arg.starts_with(&format!("{option}="))))
+    {
+        return false;
+    }
+
+    let program = verdict.program.to_lowercase();
+    if program == "git" {
+        return is_read_only_git(&verdict.args);
+    }
+    let denied = DENIED_PROGRAM_OPTIONS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .is_some_and(|(_, options)| passes_any(&verdict.args, options));
+    !denied && READ_ONLY_PROGRAMS.contains(&program.as_str()) && positional_args_read_only(&program, &verdict.args)
+}
+
+fn is_safe_mode_invocation(verdict: &InvocationVerdict) -> bool {
+    verdict.action == PolicyAction::Allow && (verdict.rule_id.is_some() || is_read_only_invocation(verdict))
+}
+
+pub(crate) fn decide_command(mode: PermissionMode, evaluation: &PolicyEvaluation) -> CommandDecision {
+    if evaluation.action == PolicyAction::Deny {
+        return CommandDecision::Reject(format!("Command denied by policy: {}.", evaluation.reason()));
+    }
+
+    match mode {
+        PermissionMode::Safe => {
+            if !evaluation.fully_analyzed() {
+                return CommandDecision::Reject(format!(
+                    "Command blocked by safe mode: {}. Safe mode only runs commands it can fully parse.",
+                    evaluation
+                        .parse_error
+                        .as_ref()
+                        .or(evaluation.unresolved.first())
+                        .cloned()
+                        .unwrap_or_default()
+                ));
+            }
+            if evaluation.action == PolicyAction::Confirm {
+                return CommandDecision::Reject(format!(
+                    "Command blocked by safe mode ({}). Change session permission to run destructive commands.",
+                    evaluation.reason()
+                ));
+            }
+            // Statements without a program (`> out`, `x=1 >y`) are checked here too.
+            if let Some(redirection) = evaluation.redirections.iter().find(|item| opens_file(item)) {
+                return CommandDecision::Reject(format!(
+                    "Command blocked by safe mode: it redirects to `{}`. Safe mode only redirects to {}.",
+                    redirection.target,
+                    DISCARD_TARGETS.join(", ")
+                ));
+            }
+            if let Some(assignment) = evaluation.assignments.first() {
+                return CommandDecision::Reject(format!(
+                    "Command blocked by safe mode: it assigns `{assignment}`, which can change what later commands run."
+                ));
+            }
+            match evaluation
+                .invocations
+                .iter()
+                .find(|verdict| !is_safe_mode_invocation(verdict))
+            {
+                Some(verdict) => CommandDecision::Reject(format!(
+                    "Command blocked by safe mode: `{}` is not read-only. Add an allow rule or change session permission to run it.",
+                    verdict.invoked_as.as_ref().unwrap_or(&verdict.program)
+                )),
+                None => CommandDecision::Run,
+            }
+        }
+        PermissionMode::Normal if evaluation.action == PolicyAction::Confirm => {
+            CommandDecision::Confirm(evaluation.reason())
+        }
+        PermissionMode::Normal | PermissionMode::Autonomous => CommandDecision::Run,
+        PermissionMode::DangerConfirm if evaluation.action == PolicyAction::Confirm => {
+            CommandDecision::Confirm(evaluation.reason())
+        }
+        PermissionMode::DangerConfirm => {
+            CommandDecision::Confirm("danger-confirm mode asks before every command".to_string())
+        }
+    }
+}
+
+pub(crate) fn check_file_write(mode: PermissionMode, confirmed: bool) -> Result<(), String> {
+    match mode {
+        PermissionMode::Safe => Err("File writes are disabled in safe mode.".to_string()),
+        PermissionMode::DangerConfirm if !confirmed => {
+            Err("File writes require explicit confirmation in danger-confirm mode.".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_policy::{evaluate, CommandPolicyRule, PatternKind, PolicyContext, PolicyScope};
+    use crate::shell_parse::ShellDialect;
+
+    fn evaluation(command: &str, rules: &[CommandPolicyRule]) -> PolicyEvaluation {
+        evaluate(command, ShellDialect::Posix, rules, &PolicyContext::default())
+    }
+
+    fn powershell_read_only(command: &str) -> bool {
+        let evaluation = evaluate(command, ShellDialect::PowerShell, &[], &PolicyContext::default());
+        matches!(decide_command(PermissionMode::Safe, &evaluation), CommandDecision::Run)
+    }
+
+    fn decide(mode: PermissionMode, command: &str) -> CommandDecision {
+        decide_command(mode, &evaluation(command, &[]))
+    }
+
+    fn rule(action: PolicyAction, program: &str) -> CommandPolicyRule {
+        CommandPolicyRule {
+            id: format!("{program}-{action:?}"),
+            scope: PolicyScope::Global,
+            scope_id: None,
+            action,
+            kind: PatternKind::Glob,
+            program: program.to_string(),
+            args: Vec::new(),
+            description: String::new(),
+            enabled: true,
+            created_at: 0,
+        }
+    }
+
+    fn read_only(command: &str) -> bool {
+        matches!(decide(PermissionMode::Safe, command), CommandDecision::Run)
+    }
+
+    #[test]
+    fn read_only_programs_and_git_commands() {
+        for command in [
+            "ls -la",
+            "cat README.md | grep -n todo",
+            "rg -n 'fn main' src",
+            "find . -name '*.rs' -print",
+            "tree -L 2",
+            "date -u +%s",
+            "git status",
+            "git -C sub --no-pager log --oneline",
+            "git grep -n TODO",
+            "git branch -a",
+            "ls > /dev/null 2>&1",
+            "date -d yesterday +%F",
+            "hostname -f",
+        ] {
+            assert!(read_only(command), "{command}");
+        }
+        for command in [
+            "ls > out.txt",
+            "cat a >> b",
+            "find . -delete",
+            "rg --output=x y",
+            "git commit -m x",
+            "git branch -D old",
+            "git remote add origin url",
+            "touch x",
+        ] {
+            assert!(!read_only(command), "{command}");
+        }
+    }
+
+    #[test]
+    fn options_that_run_commands_or_change_state_are_not_read_only() {
+        for command in [
+            "rg --pre ./hook pattern",
+            "rg --pre=./hook pattern",
+            "rg --pre-glob '*.pdf' --pre pdftotext x",
+            "git grep -O TODO",
+            "git grep -Ovim TODO",
+            "git grep --open-files-in-pager=vim TODO",
+            "tree -o listing.txt",
+            "tree -ao listing.txt",
+            "tree -olisting.txt",
+            "date -s '2020-01-01'",
+            "date --set='2020-01-01'",
+            "date -us 10:00",
+            "find . -exec rm {} +",
+            "find . -execdir sh -c x \\;",
+            "less -o log.txt README.md",
+            "less --log-file=log.txt README.md",
+            "date 010101012020",
+            "hostname evil",
+            "hostname -F name.txt",
+            "fc -s",
+        ] {
+            assert!(!read_only(command), "{command}");
+        }
+    }
+
+    #[test]
+    fn foreach_object_runs_only_script_blocks() {
+        assert!(powershell_read_only("Get-ChildItem | ForEach-Object { Get-Item $_ }"));
+        assert!(powershell_read_only("gci | % -Process { Write-Output $_ }"));
+        for command in [
+            "gci out | ForEach-Object Delete",
+            "gci out | % -MemberName Delete",
+            "gci out | % { Remove-Item $_ }",
+            "gci out | % { $_.Delete() }",
+        ] {
+            assert!(!powershell_read_only(command), "{command}");
+        }
+    }
+
+    #[test]
+    fn safe_mode_rejects_redirections_to_files_and_assignments() {
+        for command in [
+            "> important.txt",
+            "echo a;>b",
+            "x=1 >y",
+            "ls 2> err.log",
+            "cat < /etc/passwd",
+            "PATH=. ls",
+            "x=1",
+        ] {
+            assert!(!read_only(command), "{command}");
+        }
+        assert!(read_only("ls 2>&1 >/dev/null; echo a >&2"));
+        assert!(!powershell_read_only("> x"));
+        assert!(!powershell_read_only("Get-ChildItem *> x"));
+        assert!(!powershell_read_only("$env:PATH = '.'; Get-ChildItem"));
+        assert!(powershell_read_only("Get-ChildItem 2>$null"));
+
+        let allowed = evaluation("cargo test > out.txt", &[rule(PolicyAction::Allow, "cargo")]);
+        assert!(matches!(decide_command(PermissionMode::Safe, &allowed), CommandDecision::Reject(_)));
+    }
+
+    #[test]
+    fn safe_mode_runs_only_read_only_or_allowed_commands() {
+        assert!(matches!(decide(PermissionMode::Safe, "ls && git status"), CommandDecision::Run));
+        assert!(matches!(decide(PermissionMode::Safe, "touch x"), CommandDecision::Reject(_)));
+        assert!(matches!(decide(PermissionMode::Safe, "rg --pre sh x"), CommandDecision::Reject(_)));
+        assert!(matches!(decide(PermissionMode::Safe, "find . -exec rm {} +"), CommandDecision::Reject(_)));
+        assert!(matches!(decide(PermissionMode::Safe, "rm -rf out"), CommandDecision::Reject(_)));
+        assert!(matches!(decide(PermissionMode::Safe, "eval \"$CMD\""), CommandDecision::Reject(_)));
+
+        let allowed = evaluation("cargo test", &[rule(PolicyAction::Allow, "cargo")]);
+        assert!(matches!(decide_command(PermissionMode::Safe, &allowed), CommandDecision::Run));
+    }
+
+    #[test]
+    fn other_modes_confirm_or_run_by_policy() {
+        for (mode, plain, flagged) in [
+            (PermissionMode::Normal, "run", "confirm"),
+            (PermissionMode::DangerConfirm, "confirm", "confirm"),
+            (PermissionMode::Autonomous, "run", "run"),
+        ] {
+            for (command, expected) in [("cargo build", plain), ("rm -rf out", flagged)] {
+                let actual = match decide(mode, command) {
+                    CommandDecision::Run => "run",
+                    CommandDecision::Confirm(_) => "confirm",
+                    CommandDecision::Reject(_) => "reject",
+                };
+                assert_eq!(actual, expected, "{mode:?} `{command}`");
+            }
+        }
+    }
+
+    #[test]
+    fn denied_commands_never_run() {
+        let denied = evaluation("curl https://example.com", &[rule(PolicyAction::Deny, "curl")]);
+        for mode in [
+            PermissionMode::Safe,
+            PermissionMode::Normal,
+            PermissionMode::DangerConfirm,
+            PermissionMode::Autonomous,
+        ] {
+            assert!(matches!(decide_command(mode, &denied), CommandDecision::Reject(_)), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn file_writes_follow_the_mode() {
+        assert!(check_file_write(PermissionMode::Safe, true).is_err());
+        assert!(check_file_write(PermissionMode::DangerConfirm, false).is_err());
+        assert!(check_file_write(PermissionMode::DangerConfirm, true).is_ok());
+        assert!(check_file_write(PermissionMode::Normal, false).is_ok());
+        assert!(check_file_write(PermissionMode::Autonomous, false).is_ok());
+        assert_eq!(PermissionMode::from_stored("bogus"), PermissionMode::Safe);
+    }
+}
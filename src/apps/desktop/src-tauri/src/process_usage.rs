@@ -0,0 +1,183 @@
+use std::process::Child;
+
+/// Exit information and resource usage of a finished task process.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProcessExit {
+    pub(crate) code: Option<i32>,
+    pub(crate) signal: Option<i32>,
+    pub(crate) cpu_time_ms: Option<u64>,
+    pub(crate) peak_memory_bytes: Option<u64>,
+}
+
+/// A running task process. On Unix `try_reap` collects the child with `wait4`, behind
+/// `Child`'s back, so once it has been reaped the `Child` is never touched again: its
+/// PID may already belong to another process.
+pub(crate) struct TaskProcess {
+    child: Child,
+    reaped: bool,
+}
+
+impl TaskProcess {
+    pub(crate) fn new(child: Child) -> Self {
+        Self { child, reaped: false }
+    }
+
+    /// Non-blocking reap. Returns `Ok(None)` while the process is still running.
+    ///
+    /// On Unix CPU time and peak RSS include the shell's own (waited-for) children. On
+    /// Windows the figures cover the shell process.
+    pub(crate) fn try_reap(&mut self) -> Result<Option<ProcessExit>, String> {
+        if self.reaped {
+            return Err("Task process was already reaped.".to_string());
+        }
+        let exit = try_reap(&mut self.child)?;
+        self.reaped = exit.is_some();
+        Ok(exit)
+    }
+
+    /// Kills the process unless it has already been reaped.
+    pub(crate) fn kill(&mut self) -> std::io::Result<()> {
+        if self.reaped {
+            return Ok(());
+        }
+        self.child.kill()
+    }
+}
+
+#[cfg(unix)]
+fn try_reap(child: &mut Child) -> Result<Option<ProcessExit>, String> {
+    let pid = child.id() as libc::pid_t;
+    let mut status: libc::c_int = 0;
+    // SAFETY: `rusage` is plain old data and `wait4` fully initializes it on success.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `status` and `usage` are valid, writable locals for the duration of the call.
+    let reaped = unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) };
+
+    if reaped == 0 {
+        return Ok(None);
+    }
+    if reaped < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(None);
+        }
+        return Err(format!("Failed waiting for task process: {err}"));
+    }
+
+    let timeval_ms = |tv: libc::timeval| (tv.tv_sec as u64) * 1000 + (tv.tv_usec as u64) / 1000;
+    // ru_maxrss is reported in kilobytes on Linux and in bytes on macOS.
+    let max_rss = usage.ru_maxrss.max(0) as u64;
+    let peak_memory_bytes = if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    };
+
+    Ok(Some(ProcessExit {
+        code: libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status)),
+        signal: libc::WIFSIGNALED(status).then(|| libc::WTERMSIG(status)),
+        cpu_time_ms: Some(timeval_ms(usage.ru_utime) + timeval_ms(usage.ru_stime)),
+        peak_memory_bytes: Some(peak_memory_bytes),
+    }))
+}
+
+#[cfg(windows)]
+fn try_reap(child: &mut Child) -> Result<Option<ProcessExit>, String> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows_sys::Win32::System::Threading::GetProcessTimes;
+
+    let status = match child
+        .try_wait()
+        .map_err(|err| format!("Failed waiting for task process: {err}"))?
+    {
+        Some(status) => status,
+        None => return Ok(None),
+    };
+
+    let handle = child.as_raw_handle();
+    let filetime_ms = |ft: FILETIME| ((u64::from(ft.dwHighDateTime) << 32) | u64::from(ft.dwLowDateTime)) / 10_000;
+
+    // SAFETY: `handle` stays valid while `child` is alive and the out-pointers reference locals.
+    let cpu_time_ms = unsafe {
+        let mut creation: FILETIME = std::mem::zeroed();
+        let mut exit: FILETIME = std::mem::zeroed();
+        let mut kernel: FILETIME = std::mem::zeroed();
+        let mut user: FILETIME = std::mem::zeroed();
+        (GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) != 0)
+            .then(|| filetime_ms(kernel) + filetime_ms(user))
+    };
+
+    // SAFETY: same as above; `cb` carries the size of the counters struct.
+    let peak_memory_bytes = unsafe {
+        let mut counters: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+        counters.cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        (K32GetProcessMemoryInfo(handle, &mut counters, counters.cb) != 0)
+            .then(|| counters.PeakWorkingSetSize as u64)
+    };
+
+    Ok(Some(ProcessExit {
+        code: status.code(),
+        signal: None,
+        cpu_time_ms,
+        peak_memory_bytes,
+    }))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn try_reap(child: &mut Child) -> Result<Option<ProcessExit>, String> {
+    Ok(child
+        .try_wait()
+        .map_err(|err| format!("Failed waiting for task process: {err}"))?
+        .map(|status| ProcessExit {
+            code: status.code(),
+            ..ProcessExit::default()
+        }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reports_exit_code_and_stops_touching_the_reaped_child() {
+        let started = Instant::now();
+        let child = Command::new("sh").args(["-c", "sleep 0.05; exit 3"]).spawn().unwrap();
+        let mut process = TaskProcess::new(child);
+        let exit = loop {
+            match process.try_reap().unwrap() {
+                Some(exit) => break exit,
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        };
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(exit.code, Some(3));
+        assert_eq!(exit.signal, None);
+        assert!(exit.cpu_time_ms.is_some());
+        assert!(exit.peak_memory_bytes.is_some_and(|bytes| bytes > 0));
+
+        // The PID is free for reuse now: neither kill nor another reap may act on it.
+        assert!(process.kill().is_ok());
+        assert!(process.try_reap().is_err());
+    }
+
+    #[test]
+    fn reports_the_signal_of_a_killed_child() {
+        let child = Command::new("sh").args(["-c", "sleep 5"]).spawn().unwrap();
+        let mut process = TaskProcess::new(child);
+        assert!(process.try_reap().unwrap().is_none());
+        process.kill().unwrap();
+        let exit = loop {
+            if let Some(exit) = process.try_reap().unwrap() {
+                break exit;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(exit.code, None);
+        assert_eq!(exit.signal, Some(libc::SIGKILL));
+    }
+}
@@ -0,0 +1,976 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command};
+
+pub(crate) const MAX_RECORDED_VIOLATIONS: usize = 50;
+
+/// Messages printed by common tools when the kernel refuses a write.
+const DENIAL_MARKERS: &[&str] = &[
+    "Permission denied",
+    "Operation not permitted",
+    "Read-only file system",
+    "EACCES",
+    "EPERM",
+    "Access is denied",
+    "is denied.",
+];
+
+/// Where a sandboxed task may write. Reads and execution are not restricted.
+#[derive(Debug, Clone)]
+pub(crate) struct SandboxPolicy {
+    pub(crate) writable_roots: Vec<PathBuf>,
+    pub(crate) temp_dir: PathBuf,
+    pub(crate) cwd: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SandboxViolation {
+    pub(crate) path: Option<String>,
+    pub(crate) message: String,
+    pub(crate) created_at: i64,
+}
+
+impl SandboxPolicy {
+    /// Workspace root, the git directories a worktree checkout writes to, and a
+    /// private temp dir.
+    pub(crate) fn new(workspace_root: &Path, temp_dir: PathBuf, cwd: &Path) -> Self {
+        let mut writable_roots = vec![workspace_root.to_path_buf()];
+        writable_roots.extend(worktree_git_dirs(workspace_root));
+        writable_roots.push(temp_dir.clone());
+        Self {
+            writable_roots,
+            temp_dir,
+            cwd: cwd.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn allows_write(&self, path: &Path) -> bool {
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.cwd.join(path)
+        };
+        let normalized = normalize_lexically(&absolute);
+        self.writable_roots.iter().any(|root| normalized.starts_with(root))
+    }
+
+    /// Restricts `command` to this policy when it is spawned. The child must then be
+    /// passed to [`contain`] before it runs.
+    pub(crate) fn apply(&self, command: &mut Command) -> Result<(), String> {
+        fs::create_dir_all(&self.temp_dir).map_err(|err| format!("Unable to create sandbox temp dir: {err}"))?;
+        command
+            .env("TMPDIR", &self.temp_dir)
+            .env("TMP", &self.temp_dir)
+            .env("TEMP", &self.temp_dir);
+        platform::apply(self, command)
+    }
+}
+
+/// Restrictions that can only be attached once the process exists. Dropping this ends
+/// them; on Windows it also ends anything the task left running and gives the
+/// writable roots their previous label back.
+pub(crate) struct Containment {
+    _inner: platform::Containment,
+}
+
+/// Finishes confining a child spawned from a command passed to
+/// [`SandboxPolicy::apply`]. The child is killed if that fails, since it would
+/// otherwise be left without its restrictions.
+pub(crate) fn contain(child: &mut Child, policy: &SandboxPolicy) -> Result<Containment, String> {
+    match platform::contain(child, policy) {
+        Ok(inner) => Ok(Containment { _inner: inner }),
+        Err(err) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(err)
+        }
+    }
+}
+
+/// Checks that this system can enforce the filesystem sandbox.
+pub(crate) fn check_supported() -> Result<(), String> {
+    platform::check_supported()
+}
+
+/// Cuts `command` off from the network when it is spawned. Loopback keeps working so
+/// local test servers still run.
+pub(crate) fn disable_network(command: &mut Command) -> Result<(), String> {
+    platform::disable_network(command)
+}
+
+/// Checks that this system can run tasks without network access.
+pub(crate) fn check_network_isolation_supported() -> Result<(), String> {
+    platform::check_network_isolation_supported()
+}
+
+/// A checkout created by `git worktree add` keeps its index and refs under the main
+/// repository's `.git` directory, so git commands need write access there as well.
+fn worktree_git_dirs(root: &Path) -> Vec<PathBuf> {
+    let Ok(contents) = fs::read_to_string(root.join(".git")) else {
+        return Vec::new();
+    };
+    let Some(git_dir) = contents.trim().strip_prefix("gitdir:").map(str::trim) else {
+        return Vec::new();
+    };
+    let Ok(git_dir) = fs::canonicalize(root.join(git_dir)) else {
+        return Vec::new();
+    };
+
+    let mut dirs = vec![git_dir.clone()];
+    if let Ok(common) = fs::read_to_string(git_dir.join("commondir")) {
+        if let Ok(common_dir) = fs::canonicalize(git_dir.join(common.trim())) {
+            dirs.push(common_dir);
+        }
+    }
+    dirs
+}
+
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Best-effort path from an error message such as `touch: cannot touch '/etc/x':
+/// Permission denied` or `PermissionError: [Errno 13] Permission denied: '/etc/x'`.
+fn extract_path(line: &str) -> Option<String> {
+    let mut quoted: Vec<&str> = Vec::new();
+    for (open, close) in [('\'', '\''), ('‘', '’'), ('"', '"'), ('`', '\'')] {
+        let mut rest = line;
+        while let Some(start) = rest.find(open) {
+            let after = &rest[start + open.len_utf8()..];
+            let Some(end) = after.find(close) else {
+                break;
+            };
+            quoted.push(&after[..end]);
+            rest = &after[end + close.len_utf8()..];
+        }
+    }
+
+    quoted
+        .iter()
+        .find(|candidate| candidate.contains('/') || candidate.contains('\\'))
+        .or_else(|| quoted.iter().find(|candidate| !candidate.is_empty() && !candidate.contains(char::is_whitespace)))
+        .map(|candidate| candidate.to_string())
+        .or_else(|| {
+            line.split_whitespace()
+                .map(|token| token.trim_end_matches([':', ',', ';']))
+                .find(|token| token.len() > 1 && token.starts_with('/'))
+                .map(str::to_string)
+        })
+}
+
+/// Turns a stderr line into a violation when it reports a refused write outside the
+/// writable roots. Denials for paths inside the workspace are ordinary permission
+/// errors and are not reported.
+pub(crate) fn detect_violation(line: &str, policy: &SandboxPolicy, created_at: i64) -> Option<SandboxViolation> {
+    if !DENIAL_MARKERS.iter().any(|marker| line.contains(marker)) {
+        return None;
+    }
+
+    let path = extract_path(line);
+    if path.as_deref().is_some_and(|path| policy.allows_write(Path::new(path))) {
+        return None;
+    }
+
+    Some(SandboxViolation {
+        path,
+        message: line.trim().to_string(),
+        created_at,
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::SandboxPolicy;
+    use std::ffi::CString;
+    use std::fs::{self, OpenOptions};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+
+    const CREATE_RULESET_VERSION: u32 = 1;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    /// Device files tasks routinely write to even when sandboxed.
+    const WRITABLE_DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    fn abi_version() -> io::Result<i64> {
+        // SAFETY: querying the ABI version takes no pointers.
+        let version = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        if version < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(version)
+        }
+    }
+
+    pub(super) fn check_supported() -> Result<(), String> {
+        abi_version().map(|_| ()).map_err(|err| {
+            format!("Filesystem sandbox requires Landlock (Linux 5.13 or newer), which is not available: {err}")
+        })
+    }
+
+    fn add_rule(ruleset: &OwnedFd, path: &str, allowed_access: u64) -> io::Result<()> {
+        let target = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)?;
+        let attr = PathBeneathAttr {
+            allowed_access,
+            parent_fd: target.as_raw_fd(),
+        };
+        // SAFETY: `attr` matches `struct landlock_path_beneath_attr` and outlives the call.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Builds the ruleset in the parent so the forked child only has to enforce it.
+    fn build_ruleset(policy: &SandboxPolicy) -> Result<OwnedFd, String> {
+        let abi = abi_version().map_err(|err| format!("Landlock is not available: {err}"))?;
+        let file_access = ACCESS_FS_WRITE_FILE | if abi >= 3 { ACCESS_FS_TRUNCATE } else { 0 };
+        let mut handled = file_access
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: `attr` matches the leading field of `struct landlock_ruleset_attr`.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(format!("Failed to create sandbox ruleset: {}", io::Error::last_os_error()));
+        }
+        // SAFETY: the syscall returned a new file descriptor that nothing else owns.
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        for root in &policy.writable_roots {
+            let path = root.to_string_lossy();
+            add_rule(&ruleset, &path, handled)
+                .map_err(|err| format!("Failed to allow writes under {path}: {err}"))?;
+        }
+        for device in WRITABLE_DEVICES {
+            // Missing devices (no controlling tty, minimal containers) are simply not allowed.
+            let _ = add_rule(&ruleset, device, file_access);
+        }
+        Ok(ruleset)
+    }
+
+    pub(super) fn apply(policy: &SandboxPolicy, command: &mut Command) -> Result<(), String> {
+        let ruleset = build_ruleset(policy)?;
+        // SAFETY: the closure runs between fork and exec and only makes async-signal-safe
+        // syscalls on a descriptor it owns.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Landlock is already in force from `pre_exec`.
+    pub(super) struct Containment;
+
+    pub(super) fn contain(_child: &Child, _policy: &SandboxPolicy) -> Result<Containment, String> {
+        Ok(Containment)
+    }
+
+    pub(super) fn check_network_isolation_supported() -> Result<(), String> {
+        let disabled = |path: &str| fs::read_to_string(path).is_ok_and(|value| value.trim() == "0");
+        if disabled("/proc/sys/user/max_user_namespaces") || disabled("/proc/sys/kernel/unprivileged_userns_clone") {
+            return Err("Network-off mode requires user namespaces, which are disabled on this system.".to_string());
+        }
+        if !std::path::Path::new("/proc/self/ns/net").exists() {
+            return Err("Network-off mode requires network namespaces, which this kernel does not provide.".to_string());
+        }
+        Ok(())
+    }
+
+    /// Writes `contents` to `path` using only async-signal-safe calls.
+    fn write_proc_file(path: &CString, contents: &[u8]) -> io::Result<()> {
+        // SAFETY: `path` is NUL-terminated and `contents` is a valid buffer for its length.
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+            libc::close(fd);
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// A fresh network namespace only has a loopback device, and it starts down.
+    fn bring_loopback_up() {
+        // SAFETY: plain socket and ioctl calls on a zeroed `ifreq` naming "lo".
+        unsafe {
+            let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+            if socket < 0 {
+                return;
+            }
+            let mut request: libc::ifreq = std::mem::zeroed();
+            for (slot, byte) in request.ifr_name.iter_mut().zip(b"lo") {
+                *slot = *byte as libc::c_char;
+            }
+            request.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &request);
+            libc::close(socket);
+        }
+    }
+
+    /// Moves the child into new user and network namespaces. The user namespace maps
+    /// the caller's own uid and gid, so file ownership and permissions look unchanged.
+    pub(super) fn disable_network(command: &mut Command) -> Result<(), String> {
+        check_network_isolation_supported()?;
+        // SAFETY: getuid and getgid cannot fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let uid_map = format!("{uid} {uid} 1\n").into_bytes();
+        let gid_map = format!("{gid} {gid} 1\n").into_bytes();
+        let proc_path = |name: &str| CString::new(format!("/proc/self/{name}")).map_err(|err| err.to_string());
+        let setgroups_path = proc_path("setgroups")?;
+        let uid_map_path = proc_path("uid_map")?;
+        let gid_map_path = proc_path("gid_map")?;
+
+        // SAFETY: everything the closure touches was allocated before the fork, and it
+        // only makes async-signal-safe syscalls.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                write_proc_file(&setgroups_path, b"deny")?;
+                write_proc_file(&uid_map_path, &uid_map)?;
+                write_proc_file(&gid_map_path, &gid_map)?;
+                bring_loopback_up();
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Windows runs sandboxed tasks at low integrity inside a kill-on-close job. A
+/// low-integrity process cannot write to anything labelled above it, which is every
+/// file a user creates, so the writable roots get a low label while a task runs and
+/// their previous label back once the last task using them ends. The std spawn API
+/// cannot hand the child a prepared token, so the child starts suspended and its own
+/// token is lowered before it runs.
+#[cfg(windows)]
+mod platform {
+    use super::SandboxPolicy;
+    use std::ffi::{c_void, OsStr};
+    use std::io;
+    use std::mem::size_of;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::os::windows::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, Command};
+    use std::ptr;
+    use std::sync::Mutex;
+    use windows_sys::Win32::Foundation::{CloseHandle, LocalFree, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, ConvertStringSidToSidW, GetNamedSecurityInfoW,
+        SetNamedSecurityInfoW, SE_FILE_OBJECT,
+    };
+    use windows_sys::Win32::Security::{
+        GetAce, GetLengthSid, GetSecurityDescriptorSacl, InitializeAcl, SetTokenInformation, TokenIntegrityLevel,
+        ACE_HEADER, ACL, ACL_REVISION, INHERITED_ACE, LABEL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+        SID_AND_ATTRIBUTES, SYSTEM_MANDATORY_LABEL_ACE, TOKEN_ADJUST_DEFAULT, TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcessToken, OpenThread, ResumeThread, CREATE_SUSPENDED, THREAD_SUSPEND_RESUME,
+    };
+
+    const LOW_INTEGRITY_SID: &str = "S-1-16-4096";
+    /// `S-1-16-4096` in binary form, to recognise a low label left behind by a run of
+    /// the app that did not get to restore it.
+    const LOW_INTEGRITY_SID_BYTES: [u8; 12] = [1, 1, 0, 0, 0, 0, 0, 16, 0, 16, 0, 0];
+    /// Low mandatory label, inherited by files and directories, refusing writes from
+    /// anything below it.
+    const LOW_LABEL_SDDL: &str = "S:(ML;OICI;NW;;;LW)";
+    const SDDL_REVISION_1: u32 = 1;
+    const SE_GROUP_INTEGRITY: u32 = 0x20;
+
+    /// A root labelled low for the tasks running under it.
+    struct Labelled {
+        root: PathBuf,
+        /// The root's own label before the first task, as ACL words. `None` when it only
+        /// had the inherited or implicit medium label.
+        original: Option<Vec<u32>>,
+        tasks: usize,
+    }
+
+    /// Labelling walks the whole tree, so concurrent tasks share one label per root.
+    static LABELLED: Mutex<Vec<Labelled>> = Mutex::new(Vec::new());
+
+    fn wide(value: &OsStr) -> Vec<u16> {
+        value.encode_wide().chain(Some(0)).collect()
+    }
+
+    pub(super) fn check_supported() -> Result<(), String> {
+        Ok(())
+    }
+
+    pub(super) fn apply(_policy: &SandboxPolicy, command: &mut Command) -> Result<(), String> {
+        command.creation_flags(CREATE_SUSPENDED);
+        Ok(())
+    }
+
+    /// Low labels held by one task. Dropping it gives each root its previous label back
+    /// once no other task uses it.
+    struct LowLabels(Vec<PathBuf>);
+
+    impl LowLabels {
+        fn acquire(roots: &[PathBuf]) -> Result<Self, String> {
+            let mut held = LowLabels(Vec::new());
+            let mut labelled = LABELLED.lock().map_err(|err| err.to_string())?;
+            for root in roots {
+                match labelled.iter_mut().find(|entry| &entry.root == root) {
+                    Some(entry) => entry.tasks += 1,
+                    None => {
+                        let original = explicit_label(root)
+                            .map_err(|err| format!("Failed to read the label of {}: {err}", root.display()))?;
+                        label_low(root)
+                            .map_err(|err| format!("Failed to let the sandbox write under {}: {err}", root.display()))?;
+                        labelled.push(Labelled {
+                            root: root.clone(),
+                            original,
+                            tasks: 1,
+                        });
+                    }
+                }
+                held.0.push(root.clone());
+            }
+            Ok(held)
+        }
+    }
+
+    impl Drop for LowLabels {
+        fn drop(&mut self) {
+            let Ok(mut labelled) = LABELLED.lock() else {
+                return;
+            };
+            for root in &self.0 {
+                let Some(index) = labelled.iter().position(|entry| &entry.root == root) else {
+                    continue;
+                };
+                labelled[index].tasks -= 1;
+                if labelled[index].tasks == 0 {
+                    let entry = labelled.remove(index);
+                    // Best effort: a root that cannot be restored keeps the low label,
+                    // which the next task on it recognises and removes.
+                    let _ = restore_label(&entry.root, entry.original.as_deref());
+                }
+            }
+        }
+    }
+
+    /// The label set directly on `root`, if any. A low label is treated as left over
+    /// from an earlier run and ignored, so it is removed rather than restored.
+    fn explicit_label(root: &Path) -> io::Result<Option<Vec<u32>>> {
+        let path = wide(root.as_os_str());
+        let mut sacl: *mut ACL = ptr::null_mut();
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        // SAFETY: `path` is NUL-terminated; `sacl` points into `descriptor`, which is
+        // read before it is freed below.
+        unsafe {
+            let code = GetNamedSecurityInfoW(
+                path.as_ptr(),
+                SE_FILE_OBJECT,
+                LABEL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut sacl,
+                &mut descriptor,
+            );
+            if code != 0 {
+                return Err(io::Error::from_raw_os_error(code as i32));
+            }
+            let mut original = None;
+            let mut ace: *mut c_void = ptr::null_mut();
+            if !sacl.is_null() && (*sacl).AceCount > 0 && GetAce(sacl, 0, &mut ace) != 0 {
+                let header = &*(ace as *const ACE_HEADER);
+                let label = &*(ace as *const SYSTEM_MANDATORY_LABEL_ACE);
+                let sid = std::slice::from_raw_parts(
+                    &label.SidStart as *const u32 as *const u8,
+                    LOW_INTEGRITY_SID_BYTES.len(),
+                );
+                let inherited = u32::from(header.AceFlags) & INHERITED_ACE != 0;
+                if !inherited && sid != LOW_INTEGRITY_SID_BYTES {
+                    let size = usize::from((*sacl).AclSize);
+                    let mut words = vec![0u32; size.div_ceil(4)];
+                    ptr::copy_nonoverlapping(sacl as *const u8, words.as_mut_ptr() as *mut u8, size);
+                    original = Some(words);
+                }
+            }
+            LocalFree(descriptor);
+            Ok(original)
+        }
+    }
+
+    /// Lets low-integrity processes write under `root`. It does not grant access the
+    /// user's own ACLs refuse.
+    fn label_low(root: &Path) -> io::Result<()> {
+        let sddl = wide(OsStr::new(LOW_LABEL_SDDL));
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        // SAFETY: `sddl` is NUL-terminated and `descriptor` is a valid out-pointer.
+        let converted = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                ptr::null_mut(),
+            )
+        };
+        if converted == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut present = 0;
+        let mut defaulted = 0;
+        let mut sacl: *mut ACL = ptr::null_mut();
+        // SAFETY: `descriptor` is valid until it is freed below, and `sacl` points into it.
+        let result = unsafe {
+            if GetSecurityDescriptorSacl(descriptor, &mut present, &mut sacl, &mut defaulted) == 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                set_label(root, sacl)
+            }
+        };
+        // SAFETY: the conversion allocated `descriptor` with LocalAlloc.
+        unsafe { LocalFree(descriptor) };
+        result
+    }
+
+    /// Puts back the label `root` had before its first task. Without one, an empty
+    /// label list removes the explicit label and the root inherits again.
+    fn restore_label(root: &Path, original: Option<&[u32]>) -> io::Result<()> {
+        match original {
+            Some(words) => set_label(root, words.as_ptr() as *const ACL),
+            None => {
+                // SAFETY: all zeroes is a valid value for this plain C struct, which
+                // InitializeAcl then fills in as an empty list.
+                let mut empty: ACL = unsafe { std::mem::zeroed() };
+                // SAFETY: `empty` is exactly the size passed.
+                if unsafe { InitializeAcl(&mut empty, size_of::<ACL>() as u32, ACL_REVISION) } == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                set_label(root, &empty)
+            }
+        }
+    }
+
+    fn set_label(root: &Path, sacl: *const ACL) -> io::Result<()> {
+        let path = wide(root.as_os_str());
+        // SAFETY: `path` is NUL-terminated and `sacl` is a valid ACL for the call.
+        match unsafe {
+            SetNamedSecurityInfoW(
+                path.as_ptr(),
+                SE_FILE_OBJECT,
+                LABEL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null(),
+                sacl,
+            )
+        } {
+            0 => Ok(()),
+            code => Err(io::Error::from_raw_os_error(code as i32)),
+        }
+    }
+
+    /// Kill-on-close job holding a sandboxed task and everything it starts.
+    struct Job(HANDLE);
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned by this value.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Fields drop in order: the job is closed, ending anything the task left running,
+    /// before the labels are restored.
+    pub(super) struct Containment {
+        _job: Job,
+        _labels: LowLabels,
+    }
+
+    // SAFETY: a job handle may be used and closed from any thread.
+    unsafe impl Send for Containment {}
+
+    pub(super) fn contain(child: &Child, policy: &SandboxPolicy) -> Result<Containment, String> {
+        let labels = LowLabels::acquire(&policy.writable_roots)?;
+        let process = child.as_raw_handle() as HANDLE;
+        lower_integrity(process).map_err(|err| format!("Failed to lower the task's integrity level: {err}"))?;
+        let job = kill_on_close_job().map_err(|err| format!("Failed to create the task's job object: {err}"))?;
+        // SAFETY: both handles are valid; the job does not take ownership of the process handle.
+        if unsafe { AssignProcessToJobObject(job.0, process) } == 0 {
+            return Err(format!("Failed to place the task in its job object: {}", io::Error::last_os_error()));
+        }
+        resume(child.id()).map_err(|err| format!("Failed to start the sandboxed task: {err}"))?;
+        Ok(Containment {
+            _job: job,
+            _labels: labels,
+        })
+    }
+
+    fn lower_integrity(process: HANDLE) -> io::Result<()> {
+        let mut sid_text = wide(OsStr::new(LOW_INTEGRITY_SID));
+        let mut sid: PSID = ptr::null_mut();
+        // SAFETY: `sid_text` is NUL-terminated and `sid` is a valid out-pointer.
+        if unsafe { ConvertStringSidToSidW(sid_text.as_mut_ptr(), &mut sid) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut token: HANDLE = ptr::null_mut();
+        // SAFETY: `process` is the suspended child, `token` is closed before returning and
+        // `label` only borrows `sid`, which is freed last.
+        let result = unsafe {
+            if OpenProcessToken(process, TOKEN_ADJUST_DEFAULT | TOKEN_QUERY, &mut token) == 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                let label = TOKEN_MANDATORY_LABEL {
+                    Label: SID_AND_ATTRIBUTES {
+                        Sid: sid,
+                        Attributes: SE_GROUP_INTEGRITY,
+                    },
+                };
+                let length = size_of::<TOKEN_MANDATORY_LABEL>() as u32 + GetLengthSid(sid);
+                let set = SetTokenInformation(
+                    token,
+                    TokenIntegrityLevel,
+                    &label as *const TOKEN_MANDATORY_LABEL as *const c_void,
+                    length,
+                );
+                let result = if set == 0 { Err(io::Error::last_os_error()) } else { Ok(()) };
+                CloseHandle(token);
+                result
+            }
+        };
+        // SAFETY: the conversion allocated `sid` with LocalAlloc.
+        unsafe { LocalFree(sid) };
+        result
+    }
+
+    fn kill_on_close_job() -> io::Result<Job> {
+        // SAFETY: an anonymous job with default security.
+        let handle = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let job = Job(handle);
+
+        // SAFETY: all zeroes is a valid value for this plain C struct.
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        // SAFETY: `limits` matches the information class and outlives the call.
+        let set = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const c_void,
+                size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if set == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(job)
+    }
+
+    /// std keeps the main thread's handle to itself, so the suspended thread is found
+    /// again by process id.
+    fn resume(process_id: u32) -> io::Result<()> {
+        // SAFETY: the snapshot and every thread handle opened here are closed before
+        // returning, and `entry` is sized as the API requires.
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            let mut entry: THREADENTRY32 = std::mem::zeroed();
+            entry.dwSize = size_of::<THREADENTRY32>() as u32;
+            let mut resumed = false;
+            let mut more = Thread32First(snapshot, &mut entry) != 0;
+            while more {
+                if entry.th32OwnerProcessID == process_id {
+                    let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                    if !thread.is_null() {
+                        resumed |= ResumeThread(thread) != u32::MAX;
+                        CloseHandle(thread);
+                    }
+                }
+                more = Thread32Next(snapshot, &mut entry) != 0;
+            }
+            CloseHandle(snapshot);
+            if resumed {
+                Ok(())
+            } else {
+                Err(io::Error::other("its main thread was not found"))
+            }
+        }
+    }
+
+    /// Cutting one process tree off the network needs either an AppContainer, which
+    /// also hides the user's toolchains from the task and cannot be requested through
+    /// std's spawn API, or a WFP filter, which only administrators may add.
+    pub(super) fn check_network_isolation_supported() -> Result<(), String> {
+        Err("Network-off mode is not available on Windows: blocking a single task's network access needs \
+             administrator rights or an AppContainer, which this build does not use."
+            .to_string())
+    }
+
+    pub(super) fn disable_network(_command: &mut Command) -> Result<(), String> {
+        check_network_isolation_supported()
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use super::SandboxPolicy;
+    use std::process::{Child, Command};
+
+    pub(super) fn check_supported() -> Result<(), String> {
+        Err("Filesystem sandbox is not available on this platform.".to_string())
+    }
+
+    pub(super) fn apply(_policy: &SandboxPolicy, _command: &mut Command) -> Result<(), String> {
+        check_supported()
+    }
+
+    pub(super) struct Containment;
+
+    pub(super) fn contain(_child: &Child, _policy: &SandboxPolicy) -> Result<Containment, String> {
+        check_supported().map(|()| Containment)
+    }
+
+    pub(super) fn check_network_isolation_supported() -> Result<(), String> {
+        Err("Network-off mode is not available on this platform.".to_string())
+    }
+
+    pub(super) fn disable_network(_command: &mut Command) -> Result<(), String> {
+        check_network_isolation_supported()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_path;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = scratch_path("sandbox", name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    fn policy(root: &Path) -> SandboxPolicy {
+        SandboxPolicy::new(&root.join("workspace"), root.join("tmp"), &root.join("workspace/src"))
+    }
+
+    #[test]
+    fn writable_roots_cover_worktree_git_dirs() {
+        let root = scratch_dir("worktree");
+        let git_dir = root.join("main/.git/worktrees/feature");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("commondir"), "../..\n").unwrap();
+        let checkout = root.join("feature");
+        fs::create_dir_all(&checkout).unwrap();
+        fs::write(checkout.join(".git"), format!("gitdir: {}\n", git_dir.display())).unwrap();
+
+        let policy = SandboxPolicy::new(&checkout, root.join("tmp"), &checkout);
+        assert_eq!(
+            policy.writable_roots,
+            [checkout.clone(), git_dir, root.join("main/.git"), root.join("tmp")]
+        );
+
+        let plain = SandboxPolicy::new(&root.join("main"), root.join("tmp"), &root);
+        assert_eq!(plain.writable_roots, [root.join("main"), root.join("tmp")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn allows_write_resolves_relative_and_parent_paths() {
+        let root = Path::new("/scratch");
+        let policy = policy(root);
+        assert!(policy.allows_write(Path::new("main.rs")));
+        assert!(policy.allows_write(Path::new("../target/debug/app")));
+        assert!(policy.allows_write(Path::new("/scratch/tmp/build.log")));
+        assert!(!policy.allows_write(Path::new("../../outside.txt")));
+        assert!(!policy.allows_write(Path::new("/scratch/workspace/../secrets")));
+        assert!(!policy.allows_write(Path::new("/scratch/workspace-other/file")));
+    }
+
+    #[test]
+    fn extracts_paths_from_common_error_formats() {
+        let cases = [
+            ("touch: cannot touch '/etc/x': Permission denied", Some("/etc/x")),
+            ("mkdir: cannot create directory ‘/opt/cache’: Permission denied", Some("/opt/cache")),
+            ("PermissionError: [Errno 13] Permission denied: '/usr/lib/x.py'", Some("/usr/lib/x.py")),
+            ("Access to the path 'C:\\Windows\\x.txt' is denied.", Some("C:\\Windows\\x.txt")),
+            ("cp: `build' is busy", Some("build")),
+            ("error: /var/log/app.log: Operation not permitted", Some("/var/log/app.log")),
+            ("Permission denied", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(extract_path(line).as_deref(), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn reports_only_denied_writes_outside_the_policy() {
+        let policy = policy(Path::new("/scratch"));
+        let violation = detect_violation("touch: cannot touch '/etc/x': Permission denied", &policy, 7).unwrap();
+        assert_eq!(violation.path.as_deref(), Some("/etc/x"));
+        assert_eq!(violation.created_at, 7);
+
+        let unknown = detect_violation("rm: Operation not permitted", &policy, 7).unwrap();
+        assert_eq!(unknown.path, None);
+        assert!(detect_violation("Access is denied.", &policy, 7).is_some());
+
+        // Denials inside the workspace are the task's own permission problems.
+        assert!(detect_violation("touch: cannot touch 'out/x': Permission denied", &policy, 7).is_none());
+        assert!(detect_violation("open '/scratch/tmp/x': EACCES", &policy, 7).is_none());
+        assert!(detect_violation("wrote '/etc/x'", &policy, 7).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sandboxed_child_can_only_write_inside_its_roots() {
+        if check_supported().is_err() {
+            return;
+        }
+        let root = scratch_dir("enforce");
+        let workspace = root.join("workspace");
+        fs::create_dir_all(&workspace).unwrap();
+        let policy = SandboxPolicy::new(&workspace, root.join("tmp"), &workspace);
+
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "echo in > inside.txt; echo out > ../outside.txt"])
+            .current_dir(&workspace);
+        policy.apply(&mut command).unwrap();
+        let mut child = command.spawn().unwrap();
+        let _containment = contain(&mut child, &policy).unwrap();
+        assert!(!child.wait().unwrap().success());
+
+        assert!(workspace.join("inside.txt").exists());
+        assert!(!root.join("outside.txt").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn network_disabled_child_cannot_reach_host_loopback() {
+        if check_network_isolation_supported().is_err() || !Path::new("/bin/bash").exists() {
+            return;
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect = |disabled: bool| {
+            let mut command = Command::new("/bin/bash");
+            command.args(["-c", &format!("echo hello > /dev/tcp/127.0.0.1/{port}")]);
+            if disabled {
+                disable_network(&mut command).unwrap();
+            }
+            command.status().unwrap().success()
+        };
+
+        assert!(connect(false), "the listener should be reachable without isolation");
+        listener.accept().unwrap();
+        assert!(!connect(true));
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+}
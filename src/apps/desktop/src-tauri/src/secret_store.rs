@@ -0,0 +1,271 @@
+use crate::store_crypto::{self, KEY_LEN};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use keyring::{Entry, Error as KeyringError};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FILE_VERSION: u32 = 1;
+/// PBKDF2-HMAC-SHA256 work factor for new files. Stored per file so it can be raised
+/// later without breaking existing ones.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const MIN_PASSPHRASE_LEN: usize = 8;
+/// Encrypted under the derived key to tell a wrong passphrase from a corrupt entry.
+const PASSPHRASE_CHECK: &[u8] = b"codex-secret-store";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SealedValue {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretFileContents {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    check: SealedValue,
+    /// Keyed by keyring account name. Each value is bound to its account.
+    entries: BTreeMap<String, SealedValue>,
+}
+
+/// Secrets kept in a passphrase-encrypted file for machines without an OS keyring.
+/// The derived key is held only while the store is unlocked.
+pub(crate) struct EncryptedSecretFile {
+    path: PathBuf,
+    contents: SecretFileContents,
+    key: Option<[u8; KEY_LEN]>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+fn seal(key: &[u8; KEY_LEN], value: &[u8], aad: &[u8]) -> Result<SealedValue, String> {
+    let (nonce, ciphertext) = store_crypto::seal(key, value, aad)?;
+    Ok(SealedValue { nonce, ciphertext })
+}
+
+impl EncryptedSecretFile {
+    /// The file at `path`, still locked, or `None` if there is none.
+    pub(crate) fn open(path: PathBuf) -> Result<Option<Self>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&path).map_err(|err| format!("Failed to read secret store: {err}"))?;
+        let contents: SecretFileContents =
+            serde_json::from_str(&raw).map_err(|err| format!("Secret store is corrupt: {err}"))?;
+        if contents.version != FILE_VERSION {
+            return Err(format!("Unsupported secret store version {}.", contents.version));
+        }
+        Ok(Some(Self {
+            path,
+            contents,
+            key: None,
+        }))
+    }
+
+    /// A new, empty, unlocked store. Nothing is written until the first change.
+    pub(crate) fn create(path: PathBuf, passphrase: &str) -> Result<Self, String> {
+        Self::create_with_iterations(path, passphrase, PBKDF2_ITERATIONS)
+    }
+
+    fn create_with_iterations(path: PathBuf, passphrase: &str, iterations: u32) -> Result<Self, String> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(format!("Passphrase must be at least {MIN_PASSPHRASE_LEN} characters."));
+        }
+        let salt = store_crypto::random_bytes::<16>();
+        let key = derive_key(passphrase, &salt, iterations);
+        Ok(Self {
+            path,
+            contents: SecretFileContents {
+                version: FILE_VERSION,
+                iterations,
+                salt: BASE64.encode(salt),
+                check: seal(&key, PASSPHRASE_CHECK, b"check")?,
+                entries: BTreeMap::new(),
+            },
+            key: Some(key),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub(crate) fn unlock(&mut self, passphrase: &str) -> Result<(), String> {
+        let salt = BASE64
+            .decode(&self.contents.salt)
+            .map_err(|_| "Secret store is corrupt: invalid salt.".to_string())?;
+        let key = derive_key(passphrase, &salt, self.contents.iterations);
+        let check = &self.contents.check;
+        if store_crypto::open(&key, &check.nonce, &check.ciphertext, b"check").as_deref() != Some(PASSPHRASE_CHECK) {
+            return Err("Wrong passphrase for the secret store.".to_string());
+        }
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn unlocked_key(&self) -> Result<&[u8; KEY_LEN], String> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| "Secret store is locked. Unlock it with its passphrase.".to_string())
+    }
+
+    pub(crate) fn accounts(&self) -> impl Iterator<Item = &String> {
+        self.contents.entries.keys()
+    }
+
+    pub(crate) fn get(&self, account: &str) -> Result<Option<String>, String> {
+        let key = self.unlocked_key()?;
+        let Some(sealed) = self.contents.entries.get(account) else {
+            return Ok(None);
+        };
+        let value = store_crypto::open(key, &sealed.nonce, &sealed.ciphertext, account.as_bytes())
+            .ok_or_else(|| format!("Secret store entry {account} is corrupt."))?;
+        String::from_utf8(value)
+            .map(Some)
+            .map_err(|_| format!("Secret store entry {account} is corrupt."))
+    }
+
+    pub(crate) fn set(&mut self, account: &str, value: &str) -> Result<(), String> {
+        let sealed = seal(self.unlocked_key()?, value.as_bytes(), account.as_bytes())?;
+        self.contents.entries.insert(account.to_string(), sealed);
+        self.save()
+    }
+
+    pub(crate) fn remove(&mut self, account: &str) -> Result<(), String> {
+        self.unlocked_key()?;
+        if self.contents.entries.remove(account).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the file through a rename so an interrupted write keeps the old one.
+    pub(crate) fn save(&self) -> Result<(), String> {
+        let raw = serde_json::to_string_pretty(&self.contents)
+            .map_err(|err| format!("Failed to encode secret store: {err}"))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, raw)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| format!("Failed to write secret store: {err}"))
+    }
+}
+
+/// Where credentials are kept: the OS keyring, or an encrypted file when there is none.
+pub(crate) enum SecretBackend {
+    Keyring { service: &'static str },
+    File(EncryptedSecretFile),
+}
+
+impl SecretBackend {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Keyring { .. } => "keyring",
+            Self::File(_) => "file",
+        }
+    }
+
+    fn keyring_entry(service: &str, account: &str) -> Result<Entry, String> {
+        Entry::new(service, account).map_err(|err| format!("Token store unavailable: {err}"))
+    }
+
+    pub(crate) fn load(&self, account: &str) -> Result<Option<String>, String> {
+        match self {
+            Self::Keyring { service } => match Self::keyring_entry(service, account)?.get_password() {
+                Ok(value) => Ok(Some(value)),
+                Err(KeyringError::NoEntry) => Ok(None),
+                Err(err) => Err(format!("Failed to read {account}: {err}")),
+            },
+            Self::File(file) => file.get(account),
+        }
+    }
+
+    pub(crate) fn save(&mut self, account: &str, value: &str) -> Result<(), String> {
+        match self {
+            Self::Keyring { service } => Self::keyring_entry(service, account)?
+                .set_password(value)
+                .map_err(|err| format!("Failed to save {account}: {err}")),
+            Self::File(file) => file.set(account, value),
+        }
+    }
+
+    pub(crate) fn clear(&mut self, account: &str) -> Result<(), String> {
+        match self {
+            Self::Keyring { service } => match Self::keyring_entry(service, account)?.delete_credential() {
+                Ok(_) | Err(KeyringError::NoEntry) => Ok(()),
+                Err(err) => Err(format!("Failed to clear {account}: {err}")),
+            },
+            Self::File(file) => file.remove(account),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_path;
+
+    /// Keeps the tests fast; real files use `PBKDF2_ITERATIONS`.
+    const TEST_ITERATIONS: u32 = 1_000;
+
+    fn temp_path(name: &str) -> PathBuf {
+        scratch_path("secret-store", name).with_extension("json")
+    }
+
+    #[test]
+    fn saves_loads_and_clears_across_reopen() {
+        let path = temp_path("roundtrip");
+        let mut file = EncryptedSecretFile::create_with_iterations(path.clone(), "correct horse", TEST_ITERATIONS).unwrap();
+        file.set("openai-api-key", "sk-test-value").unwrap();
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("sk-test-value"));
+
+        let mut reopened = EncryptedSecretFile::open(path.clone()).unwrap().unwrap();
+        assert!(reopened.get("openai-api-key").is_err());
+        reopened.unlock("correct horse").unwrap();
+        assert_eq!(reopened.get("openai-api-key").unwrap().as_deref(), Some("sk-test-value"));
+        assert_eq!(reopened.get("oauth-refresh-token").unwrap(), None);
+
+        reopened.remove("openai-api-key").unwrap();
+        assert_eq!(reopened.get("openai-api-key").unwrap(), None);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase_and_short_passphrases() {
+        let path = temp_path("passphrase");
+        let mut file = EncryptedSecretFile::create_with_iterations(path.clone(), "correct horse", TEST_ITERATIONS).unwrap();
+        file.set("openai-api-key", "sk-test-value").unwrap();
+
+        let mut reopened = EncryptedSecretFile::open(path.clone()).unwrap().unwrap();
+        assert!(reopened.unlock("wrong horse").is_err());
+        assert!(!reopened.is_unlocked());
+        assert!(EncryptedSecretFile::create(temp_path("short"), "short").is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn entries_are_bound_to_their_account() {
+        let path = temp_path("binding");
+        let mut file = EncryptedSecretFile::create_with_iterations(path.clone(), "correct horse", TEST_ITERATIONS).unwrap();
+        file.set("openai-api-key", "sk-test-value").unwrap();
+        let moved = file.contents.entries["openai-api-key"].clone();
+        file.contents.entries.insert("oauth-refresh-token".to_string(), moved);
+        assert!(file.get("oauth-refresh-token").is_err());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,1551 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+const MAX_NESTING_DEPTH: usize = 8;
+const MAX_ALIAS_EXPANSIONS: usize = 16;
+
+const POSIX_RESERVED_WORDS: &[&str] = &["if", "then", "else", "elif", "fi", "do", "done", "while", "until", "!", "{", "}"];
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "ash", "mksh"];
+
+/// Built-in PowerShell aliases that resolve to cmdlets relevant to policy checks.
+const POWERSHELL_ALIASES: &[(&str, &str)] = &[
+    ("ri", "remove-item"),
+    ("rm", "remove-item"),
+    ("rmdir", "remove-item"),
+    ("del", "remove-item"),
+    ("erase", "remove-item"),
+    ("rd", "remove-item"),
+    ("cp", "copy-item"),
+    ("copy", "copy-item"),
+    ("cpi", "copy-item"),
+    ("mv", "move-item"),
+    ("move", "move-item"),
+    ("mi", "move-item"),
+    ("ren", "rename-item"),
+    ("rni", "rename-item"),
+    ("ni", "new-item"),
+    ("iex", "invoke-expression"),
+    ("icm", "invoke-command"),
+    ("saps", "start-process"),
+    ("start", "start-process"),
+    ("kill", "stop-process"),
+    ("spps", "stop-process"),
+    ("sal", "set-alias"),
+    ("nal", "new-alias"),
+    ("%", "foreach-object"),
+    ("foreach", "foreach-object"),
+    ("?", "where-object"),
+    ("where", "where-object"),
+    ("ls", "get-childitem"),
+    ("dir", "get-childitem"),
+    ("gci", "get-childitem"),
+    ("cat", "get-content"),
+    ("gc", "get-content"),
+    ("type", "get-content"),
+    ("echo", "write-output"),
+    ("write", "write-output"),
+    ("cd", "set-location"),
+    ("sl", "set-location"),
+    ("chdir", "set-location"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ShellDialect {
+    Posix,
+    Cmd,
+    PowerShell,
+}
+
+impl ShellDialect {
+    /// The dialect `run_shell_command` actually executes for `shell` on this platform.
+    pub(crate) fn for_task_shell(shell: &str) -> Self {
+        if cfg!(target_os = "windows") {
+            if shell.to_lowercase().contains("cmd") {
+                ShellDialect::Cmd
+            } else {
+                ShellDialect::PowerShell
+            }
+        } else {
+            ShellDialect::Posix
+        }
+    }
+
+    fn escape_char(self) -> char {
+        match self {
+            ShellDialect::Posix => '\\',
+            ShellDialect::Cmd => '^',
+            ShellDialect::PowerShell => '`',
+        }
+    }
+}
+
+/// A single program invocation. `program` is the normalized name after resolving
+/// aliases; `invoked_as` keeps the alias the command actually used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Invocation {
+    pub(crate) program: String,
+    pub(crate) invoked_as: Option<String>,
+    pub(crate) args: Vec<String>,
+    pub(crate) dialect: ShellDialect,
+}
+
+impl Invocation {
+    /// Argument tokens used for rule matching: the original arguments plus their
+    /// expansions (`-rf` -> `-r -f` for POSIX, `/s/q` -> `/s /q` for cmd and
+    /// `-Recurse:$true` -> `-Recurse` for PowerShell).
+    pub(crate) fn match_candidates(&self) -> Vec<String> {
+        let mut candidates = self.args.clone();
+        for arg in &self.args {
+            match self.dialect {
+                ShellDialect::Posix => {
+                    if let Some(flags) = arg.strip_prefix('-') {
+                        if flags.len() > 1 && flags.chars().all(|c| c.is_ascii_alphabetic()) {
+                            candidates.extend(flags.chars().map(|c| format!("-{c}")));
+                        }
+                    }
+                }
+                ShellDialect::Cmd => {
+                    if arg.starts_with('/') && arg[1..].contains('/') {
+                        candidates.extend(
+                            arg.split('/')
+                                .filter(|part| !part.is_empty())
+                                .map(|part| format!("/{part}")),
+                        );
+                    }
+                }
+                ShellDialect::PowerShell => {
+                    if arg.starts_with('-') {
+                        if let Some((name, _)) = arg.split_once(':') {
+                            candidates.push(name.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Every invocation found in a command line, including the contents of subshells,
+/// command substitutions, script blocks, nested shells and `eval`/`Invoke-Expression`.
+/// `unresolved` lists constructs whose effect cannot be known without running them.
+/// Redirections and variable assignments are listed apart from the invocations, including
+/// those of statements that run no program (`> out`, `x=1`).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParsedCommand {
+    pub(crate) invocations: Vec<Invocation>,
+    pub(crate) unresolved: Vec<String>,
+    pub(crate) redirections: Vec<Redirection>,
+    pub(crate) assignments: Vec<String>,
+}
+
+/// An unquoted redirection such as `2>&1` or `> out.txt`. `target` is empty when the
+/// command ends before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Redirection {
+    pub(crate) operator: String,
+    pub(crate) target: String,
+}
+
+impl Redirection {
+    /// Splits a redirection word into its operator (`2>`, `>>`, `&>`, `*>&`) and target.
+    fn parse(word: &str) -> Self {
+        let digits = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+        let operator_len = word[digits..]
+            .find(|c: char| !matches!(c, '&' | '*' | '>' | '<' | '|'))
+            .map_or(word.len(), |len| digits + len);
+        Self {
+            operator: word[..operator_len].to_string(),
+            target: word[operator_len..].to_string(),
+        }
+    }
+
+    /// `2>&1` or `>&-`: duplicates or closes a descriptor instead of opening a file.
+    pub(crate) fn duplicates_descriptor(&self) -> bool {
+        let descriptor = !self.target.is_empty() && self.target.chars().all(|c| c.is_ascii_digit());
+        self.operator.ends_with('&') && (descriptor || self.target == "-")
+    }
+}
+
+pub(crate) fn parse_command(command: &str, dialect: ShellDialect) -> Result<ParsedCommand, String> {
+    let mut parsed = ParsedCommand::default();
+    Resolver::new(dialect, 0).run(command, &mut parsed)?;
+    Ok(parsed)
+}
+
+fn is_env_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Lowercased file name of `program` without directories or executable extensions.
+pub(crate) fn normalize_program(program: &str) -> String {
+    let name = program
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(program)
+        .to_lowercase();
+    for extension in [".exe", ".com", ".cmd", ".bat", ".ps1"] {
+        if let Some(stem) = name.strip_suffix(extension) {
+            if !stem.is_empty() {
+                return stem.to_string();
+            }
+        }
+    }
+    name
+}
+
+/// `.Name(` or `::Name(` somewhere in a PowerShell expression.
+fn looks_like_method_call(word: &str) -> bool {
+    word.match_indices(['.', ':']).any(|(index, _)| {
+        let rest = &word[index + 1..];
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        name_len > 0 && rest[name_len..].starts_with('(')
+    })
+}
+
+/// `name() body`, `name () body` or `function name body`: returns the name and body.
+fn posix_function_definition(words: &[String]) -> Option<(String, &[String])> {
+    let (name, body) = if words[0] == "function" {
+        let name = words.get(1)?;
+        (name.trim_end_matches("()"), &words[2..])
+    } else if let Some(name) = words[0].strip_suffix("()").filter(|name| !name.is_empty()) {
+        (name, &words[1..])
+    } else if words.get(1).is_some_and(|word| word == "()") {
+        (words[0].as_str(), &words[2..])
+    } else {
+        return None;
+    };
+    let body = match body.first() {
+        Some(first) if first == "()" => &body[1..],
+        _ => body,
+    };
+    Some((name.to_string(), body))
+}
+
+/// cmd ends a command name at `/`, so `rd/s/q out` runs `rd /s/q out`. Returns the
+/// words with the switches split off the program name.
+fn split_cmd_switches(raw: &str, args: &[String]) -> Option<Vec<String>> {
+    let (name, switches) = raw.split_once('/')?;
+    let plain_name = name.chars().any(|c| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !plain_name {
+        return None;
+    }
+    let mut words = vec![name.to_string(), format!("/{switches}")];
+    words.extend_from_slice(args);
+    Some(words)
+}
+
+fn skip_options<'a>(args: &'a [String], value_options: &[&str]) -> &'a [String] {
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            return &args[index + 1..];
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        index += if value_options.contains(&arg.as_str()) { 2 } else { 1 };
+    }
+    &args[index.min(args.len())..]
+}
+
+/// PowerShell parameter names may be abbreviated to any unambiguous prefix.
+fn is_parameter(arg: &str, name: &str, min_len: usize) -> bool {
+    let Some(given) = arg.strip_prefix('-').or_else(|| arg.strip_prefix('/')) else {
+        return false;
+    };
+    let given = given.split(':').next().unwrap_or_default().to_lowercase();
+    given.len() >= min_len && name.starts_with(&given)
+}
+
+fn decode_powershell_encoded(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for c in value.trim().chars() {
+        let digit = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => break,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | digit;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+enum ShellInput {
+    Script(String),
+    File,
+    Stdin,
+}
+
+fn posix_shell_input(args: &[String]) -> ShellInput {
+    let mut has_command = false;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        if matches!(arg.as_str(), "-o" | "+o" | "-O" | "+O" | "--rcfile" | "--init-file") {
+            index += 2;
+            continue;
+        }
+        if !(arg.starts_with('-') || arg.starts_with('+')) || arg == "-" {
+            break;
+        }
+        if arg.starts_with('-') && !arg.starts_with("--") && arg[1..].contains('c') {
+            has_command = true;
+        }
+        index += 1;
+    }
+
+    match (has_command, args.get(index)) {
+        (true, Some(script)) => ShellInput::Script(script.clone()),
+        (true, None) => ShellInput::Stdin,
+        (false, Some(_)) => ShellInput::File,
+        (false, None) => ShellInput::Stdin,
+    }
+}
+
+fn cmd_shell_input(args: &[String]) -> ShellInput {
+    match args
+        .iter()
+        .position(|arg| matches!(arg.to_lowercase().as_str(), "/c" | "/k" | "/r"))
+    {
+        Some(index) => ShellInput::Script(args[index + 1..].join(" ")),
+        None => ShellInput::Stdin,
+    }
+}
+
+fn powershell_input(program: &str, args: &[String]) -> ShellInput {
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if !(arg.starts_with('-') || arg.starts_with('/')) {
+            // Windows PowerShell treats a bare argument as -Command, pwsh as -File.
+            return if program == "pwsh" {
+                ShellInput::File
+            } else {
+                ShellInput::Script(args[index..].join(" "))
+            };
+        }
+        if is_parameter(arg, "command", 1) {
+            return match args.get(index + 1).map(String::as_str) {
+                Some("-") | None => ShellInput::Stdin,
+                Some(_) => ShellInput::Script(args[index + 1..].join(" ")),
+            };
+        }
+        if is_parameter(arg, "encodedcommand", 2) || is_parameter(arg, "ec", 2) || arg.eq_ignore_ascii_case("-e") {
+            return match args.get(index + 1).and_then(|value| decode_powershell_encoded(value)) {
+                Some(script) => ShellInput::Script(script),
+                None => ShellInput::Stdin,
+            };
+        }
+        if is_parameter(arg, "file", 1) {
+            return ShellInput::File;
+        }
+        let takes_value = is_parameter(arg, "executionpolicy", 2)
+            || is_parameter(arg, "ep", 2)
+            || is_parameter(arg, "windowstyle", 1)
+            || is_parameter(arg, "workingdirectory", 2)
+            || is_parameter(arg, "wd", 2)
+            || is_parameter(arg, "inputformat", 2)
+            || is_parameter(arg, "outputformat", 1)
+            || is_parameter(arg, "configurationname", 3)
+            || is_parameter(arg, "settingsfile", 2)
+            || is_parameter(arg, "version", 1);
+        index += if takes_value { 2 } else { 1 };
+    }
+    ShellInput::Stdin
+}
+
+struct Resolver {
+    dialect: ShellDialect,
+    depth: usize,
+    aliases: HashMap<String, Vec<String>>,
+    alias_expansions: usize,
+}
+
+impl Resolver {
+    fn new(dialect: ShellDialect, depth: usize) -> Self {
+        Self {
+            dialect,
+            depth,
+            aliases: HashMap::new(),
+            alias_expansions: 0,
+        }
+    }
+
+    fn run(&mut self, command: &str, out: &mut ParsedCommand) -> Result<(), String> {
+        let split = split_words(command, self.dialect, self.depth)?;
+        out.unresolved.extend(split.unresolved);
+        out.redirections.extend(split.redirections);
+        for words in split.invocations {
+            self.resolve(&words, None, out);
+        }
+        Ok(())
+    }
+
+    fn nested(&self, script: &str, dialect: ShellDialect, origin: &str, out: &mut ParsedCommand) {
+        if self.depth >= MAX_NESTING_DEPTH {
+            out.unresolved.push(format!("{origin}: command nesting is too deep"));
+            return;
+        }
+        if let Err(err) = Resolver::new(dialect, self.depth + 1).run(script, out) {
+            out.unresolved.push(format!("{origin}: {err}"));
+        }
+    }
+
+    fn shell_input(&self, program: &str, input: ShellInput, dialect: ShellDialect, out: &mut ParsedCommand) {
+        match input {
+            ShellInput::Script(script) => self.nested(&script, dialect, &format!("`{program}` script"), out),
+            ShellInput::File => {}
+            ShellInput::Stdin => out
+                .unresolved
+                .push(format!("`{program}` reads commands from its input")),
+        }
+    }
+
+    fn resolve(&mut self, words: &[String], alias: Option<&str>, out: &mut ParsedCommand) {
+        let mut words = words;
+        let mut called = false;
+        loop {
+            let Some(first) = words.first() else {
+                return;
+            };
+            if first.is_empty() {
+                words = &words[1..];
+            } else if self.dialect == ShellDialect::Posix && is_env_assignment(first) {
+                out.assignments.push(first.clone());
+                words = &words[1..];
+            } else if self.dialect == ShellDialect::Posix && POSIX_RESERVED_WORDS.contains(&first.as_str()) {
+                words = &words[1..];
+            } else if self.dialect == ShellDialect::PowerShell && (first == "&" || first == ".") {
+                called = true;
+                words = &words[1..];
+            } else if self.dialect == ShellDialect::PowerShell
+                && words.get(1).is_some_and(|op| {
+                    matches!(op.as_str(), "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "??=")
+                })
+            {
+                out.assignments.push(first.clone());
+                words = &words[2..];
+            } else if self.dialect == ShellDialect::Cmd && first.eq_ignore_ascii_case("else") {
+                words = &words[1..];
+            } else {
+                break;
+            }
+        }
+
+        if self.dialect == ShellDialect::Posix {
+            if let Some((name, body)) = posix_function_definition(words) {
+                // The body only runs when the function is called, possibly under another
+                // name or with other arguments; check what it contains but flag it.
+                out.unresolved.push(format!("function definition `{name}`"));
+                self.resolve(body, None, out);
+                return;
+            }
+        }
+
+        let raw = match self.dialect {
+            ShellDialect::Cmd => words[0].trim_start_matches('@'),
+            _ => words[0].as_str(),
+        };
+        let args = &words[1..];
+
+        if self.dialect == ShellDialect::Cmd {
+            if let Some(split) = split_cmd_switches(raw, args) {
+                self.resolve(&split, alias, out);
+                return;
+            }
+        }
+
+        match self.dialect {
+            ShellDialect::Posix if raw.starts_with('$') || raw.starts_with('`') => {
+                out.unresolved.push(format!("dynamic program `{raw}`"));
+                return;
+            }
+            ShellDialect::Cmd if raw.contains('%') || raw.contains('!') => {
+                out.unresolved.push(format!("dynamic program `{raw}`"));
+                return;
+            }
+            ShellDialect::PowerShell if raw.starts_with(['$', '(', '@', '{', '[']) => {
+                // Script blocks, subexpressions and variables were already split out; only
+                // calling them or invoking methods on them runs code we cannot see.
+                let opaque = (called && !raw.starts_with('{')) || looks_like_method_call(raw);
+                if opaque {
+                    out.unresolved.push(format!("dynamic invocation `{raw}`"));
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let normalized = normalize_program(raw);
+        if normalized.is_empty() {
+            return;
+        }
+
+        // An alias whose expansion starts with its own name is not expanded again.
+        let expansion = (alias != Some(normalized.as_str()))
+            .then(|| self.aliases.get(&normalized).cloned())
+            .flatten();
+        if let Some(expansion) = expansion {
+            if self.alias_expansions >= MAX_ALIAS_EXPANSIONS {
+                out.unresolved.push(format!("alias `{normalized}` expands recursively"));
+                return;
+            }
+            self.alias_expansions += 1;
+            let expanded = [expansion, args.to_vec()].concat();
+            self.resolve(&expanded, Some(&normalized), out);
+            return;
+        }
+
+        let builtin_alias = (self.dialect == ShellDialect::PowerShell && raw.to_lowercase() == normalized)
+            .then(|| {
+                POWERSHELL_ALIASES
+                    .iter()
+                    .find(|(name, _)| *name == normalized)
+                    .map(|(_, cmdlet)| cmdlet.to_string())
+            })
+            .flatten();
+        let (program, invoked_as) = match builtin_alias {
+            Some(cmdlet) => (cmdlet, Some(normalized)),
+            None => (normalized, alias.map(str::to_string)),
+        };
+
+        out.invocations.push(Invocation {
+            program: program.clone(),
+            invoked_as,
+            args: args.to_vec(),
+            dialect: self.dialect,
+        });
+
+        self.follow(&program, args, out);
+    }
+
+    /// Resolves what an invocation runs on behalf of the user: wrapped programs,
+    /// nested shell scripts, evaluated strings and alias definitions.
+    fn follow(&mut self, program: &str, args: &[String], out: &mut ParsedCommand) {
+        match program {
+            "sudo" | "doas" => {
+                let rest = skip_options(args, &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U", "-T"]);
+                self.resolve(rest, None, out);
+            }
+            "env" => {
+                let mut rest = skip_options(args, &["-u", "-C", "--unset", "--chdir"]);
+                while rest.first().is_some_and(|word| is_env_assignment(word)) {
+                    rest = &rest[1..];
+                }
+                self.resolve(rest, None, out);
+            }
+            "nohup" | "command" | "exec" | "builtin" | "setsid" | "time" | "busybox" => {
+                self.resolve(skip_options(args, &[]), None, out);
+            }
+            "nice" => self.resolve(skip_options(args, &["-n"]), None, out),
+            "ionice" => self.resolve(skip_options(args, &["-c", "-n", "-p"]), None, out),
+            "stdbuf" => self.resolve(skip_options(args, &["-i", "-o", "-e"]), None, out),
+            "timeout" => {
+                let rest = skip_options(args, &["-s", "-k", "--signal", "--kill-after"]);
+                self.resolve(rest.get(1..).unwrap_or_default(), None, out);
+            }
+            "xargs" => {
+                let rest = skip_options(args, &["-I", "-n", "-P", "-L", "-d", "-E", "-s", "-a"]);
+                self.resolve(rest, None, out);
+            }
+            "find" => {
+                let mut index = 0;
+                while index < args.len() {
+                    if matches!(args[index].as_str(), "-exec" | "-execdir" | "-ok" | "-okdir") {
+                        let start = index + 1;
+                        let end = args[start..]
+                            .iter()
+                            .position(|arg| arg == ";" || arg == "+")
+                            .map(|offset| start + offset)
+                            .unwrap_or(args.len());
+                        self.resolve(&args[start..end], None, out);
+                        index = end;
+                    }
+                    index += 1;
+                }
+            }
+            shell if POSIX_SHELLS.contains(&shell) => {
+                self.shell_input(program, posix_shell_input(args), ShellDialect::Posix, out);
+            }
+            "cmd" => self.shell_input(program, cmd_shell_input(args), ShellDialect::Cmd, out),
+            "powershell" | "pwsh" => {
+                self.shell_input(program, powershell_input(program, args), ShellDialect::PowerShell, out);
+            }
+            "eval" if self.dialect == ShellDialect::Posix => {
+                self.nested(&args.join(" "), ShellDialect::Posix, "`eval` argument", out);
+            }
+            "alias" if self.dialect == ShellDialect::Posix => {
+                for definition in args {
+                    if let Some((name, value)) = definition.split_once('=') {
+                        if let Ok(Some(expansion)) =
+                            split_words(value, ShellDialect::Posix, self.depth + 1).map(|split| split.invocations.into_iter().next())
+                        {
+                            self.aliases.insert(normalize_program(name), expansion);
+                        }
+                    }
+                }
+            }
+            _ if self.dialect == ShellDialect::Cmd => self.follow_cmd(program, args, out),
+            _ if self.dialect == ShellDialect::PowerShell => self.follow_powershell(program, args, out),
+            _ => {}
+        }
+    }
+
+    fn follow_cmd(&mut self, program: &str, args: &[String], out: &mut ParsedCommand) {
+        match program {
+            "call" => self.resolve(args, None, out),
+            "start" => {
+                let rest = args
+                    .iter()
+                    .position(|arg| !(arg.is_empty() || arg.starts_with('/')))
+                    .map(|index| &args[index..])
+                    .unwrap_or_default();
+                self.resolve(rest, None, out);
+            }
+            "if" => {
+                let mut rest = args;
+                while rest
+                    .first()
+                    .is_some_and(|word| word.eq_ignore_ascii_case("/i") || word.eq_ignore_ascii_case("not"))
+                {
+                    rest = &rest[1..];
+                }
+                let Some(first) = rest.first().map(|word| word.to_lowercase()) else {
+                    return;
+                };
+                let skip = if matches!(first.as_str(), "exist" | "defined" | "errorlevel" | "cmdextversion") {
+                    2
+                } else if first.contains("==") {
+                    1
+                } else if rest.get(1).is_some_and(|op| {
+                    matches!(op.to_lowercase().as_str(), "==" | "equ" | "neq" | "lss" | "leq" | "gtr" | "geq")
+                }) {
+                    3
+                } else {
+                    1
+                };
+                self.resolve(rest.get(skip..).unwrap_or_default(), None, out);
+            }
+            "for" => {
+                if let Some(index) = args.iter().position(|arg| arg.eq_ignore_ascii_case("do")) {
+                    self.resolve(&args[index + 1..], None, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn follow_powershell(&mut self, program: &str, args: &[String], out: &mut ParsedCommand) {
+        match program {
+            "invoke-expression" => {
+                let script = match args.iter().position(|arg| is_parameter(arg, "command", 1)) {
+                    Some(index) => args.get(index + 1),
+                    None => args.iter().find(|arg| !arg.starts_with('-')),
+                };
+                match script {
+                    Some(script) if script.starts_with(['$', '(', '@']) => out
+                        .unresolved
+                        .push(format!("Invoke-Expression evaluates `{script}` at run time")),
+                    Some(script) => self.nested(script, ShellDialect::PowerShell, "Invoke-Expression argument", out),
+                    None => out
+                        .unresolved
+                        .push("Invoke-Expression reads its script from the pipeline".to_string()),
+                }
+            }
+            "set-alias" | "new-alias" => {
+                let mut name: Option<&String> = None;
+                let mut value: Option<&String> = None;
+                let mut positional = Vec::new();
+                let mut index = 0;
+                while let Some(arg) = args.get(index) {
+                    if is_parameter(arg, "name", 1) {
+                        name = args.get(index + 1);
+                        index += 2;
+                    } else if is_parameter(arg, "value", 1) {
+                        value = args.get(index + 1);
+                        index += 2;
+                    } else if is_parameter(arg, "option", 1) || is_parameter(arg, "scope", 1) || is_parameter(arg, "description", 1) {
+                        index += 2;
+                    } else if arg.starts_with('-') {
+                        index += 1;
+                    } else {
+                        positional.push(arg);
+                        index += 1;
+                    }
+                }
+                let mut positional = positional.into_iter();
+                let name = name.or_else(|| positional.next());
+                let value = value.or_else(|| positional.next());
+                if let (Some(name), Some(value)) = (name, value) {
+                    self.aliases.insert(normalize_program(name), vec![value.clone()]);
+                }
+            }
+            "start-process" => {
+                let mut target: Option<&String> = None;
+                let mut target_args: Option<&String> = None;
+                let mut index = 0;
+                while let Some(arg) = args.get(index) {
+                    if is_parameter(arg, "filepath", 1) {
+                        target = args.get(index + 1);
+                        index += 2;
+                    } else if is_parameter(arg, "argumentlist", 2) || is_parameter(arg, "args", 4) {
+                        target_args = args.get(index + 1);
+                        index += 2;
+                    } else if is_parameter(arg, "verb", 2)
+                        || is_parameter(arg, "workingdirectory", 2)
+                        || is_parameter(arg, "windowstyle", 2)
+                        || is_parameter(arg, "credential", 1)
+                        || arg.to_lowercase().starts_with("-redirectstandard")
+                    {
+                        index += 2;
+                    } else if arg.starts_with('-') {
+                        index += 1;
+                    } else {
+                        if target.is_none() {
+                            target = Some(arg);
+                        } else if target_args.is_none() {
+                            target_args = Some(arg);
+                        }
+                        index += 1;
+                    }
+                }
+                if let Some(target) = target {
+                    let mut words = vec![target.clone()];
+                    if let Some(target_args) = target_args {
+                        words.extend(
+                            target_args
+                                .split([',', ' '])
+                                .filter(|part| !part.is_empty())
+                                .map(str::to_string),
+                        );
+                    }
+                    self.resolve(&words, None, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Words of each invocation in a command line, its redirections, and constructs the
+/// splitter saw but cannot expand.
+#[derive(Default)]
+struct SplitCommand {
+    invocations: Vec<Vec<String>>,
+    unresolved: Vec<String>,
+    redirections: Vec<Redirection>,
+}
+
+#[derive(Default)]
+struct Splitter {
+    invocations: Vec<Vec<String>>,
+    unresolved: Vec<String>,
+    redirections: Vec<Redirection>,
+    words: Vec<String>,
+    word: String,
+    word_started: bool,
+    /// The current word started with an unquoted redirection operator.
+    redirecting: bool,
+    /// A bare operator (`>` followed by a space) waiting for its target word.
+    pending_redirection: Option<String>,
+    /// Set inside an unquoted `{` of the current word; true once a `,` followed it.
+    brace: Option<bool>,
+}
+
+impl Splitter {
+    fn push(&mut self, c: char) {
+        self.word.push(c);
+        self.word_started = true;
+    }
+
+    fn push_str(&mut self, value: &str) {
+        self.word.push_str(value);
+        self.word_started = true;
+    }
+
+    fn end_word(&mut self) {
+        self.brace = None;
+        if !self.word_started {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        self.word_started = false;
+        if std::mem::take(&mut self.redirecting) {
+            let redirection = Redirection::parse(&word);
+            if redirection.target.is_empty() {
+                self.pending_redirection = Some(redirection.operator);
+            } else {
+                self.redirections.push(redirection);
+            }
+        } else if let Some(operator) = self.pending_redirection.take() {
+            self.redirections.push(Redirection { operator, target: word });
+        } else {
+            self.words.push(word);
+        }
+    }
+
+    fn end_invocation(&mut self) {
+        self.end_word();
+        if let Some(operator) = self.pending_redirection.take() {
+            self.redirections.push(Redirection {
+                operator,
+                target: String::new(),
+            });
+        }
+        if !self.words.is_empty() {
+            self.invocations.push(std::mem::take(&mut self.words));
+        }
+    }
+
+    fn at_word_start(&self) -> bool {
+        !self.word_started
+    }
+
+    fn at_statement_start(&self) -> bool {
+        self.words.is_empty() && self.at_word_start()
+    }
+
+    fn in_redirection(&self) -> bool {
+        self.word.ends_with('>') || self.word.ends_with('<')
+    }
+
+    /// Called before an unquoted `<` or `>`. The operator starts a redirection of its own
+    /// (`rm>/dev/null` is `rm` and `>/dev/null`) unless it continues one: `>>`, a file
+    /// descriptor (`2>`), or `&>` in POSIX and `*>` in PowerShell.
+    fn start_redirection(&mut self, dialect: ShellDialect) {
+        let prefix = match dialect {
+            ShellDialect::Posix => "&",
+            ShellDialect::Cmd => "",
+            ShellDialect::PowerShell => "*",
+        };
+        let continues = self.in_redirection()
+            || (!self.word.is_empty() && (self.word.chars().all(|c| c.is_ascii_digit()) || self.word == prefix));
+        if !continues {
+            self.end_word();
+        }
+        self.redirecting = true;
+    }
+
+    /// `in (...)` of a cmd `for`: a set of files or strings, not a block. Only the
+    /// quoted command of `for /f` runs.
+    fn at_cmd_for_set(&self) -> bool {
+        self.words.len() > 1
+            && self.words[0].trim_start_matches('@').eq_ignore_ascii_case("for")
+            && self.words[self.words.len() - 1].eq_ignore_ascii_case("in")
+    }
+
+    /// Splits a subshell, substitution or script block. Its invocations are recorded
+    /// before the enclosing one, which matches the order they run in.
+    fn nest(&mut self, inner: &str, dialect: ShellDialect, depth: usize) -> Result<(), String> {
+        let nested = split_words(inner, dialect, depth + 1)?;
+        self.invocations.extend(nested.invocations);
+        self.unresolved.extend(nested.unresolved);
+        self.redirections.extend(nested.redirections);
+        Ok(())
+    }
+
+    /// Tracks unquoted `{a,b}` in a POSIX word: brace expansion can assemble a command
+    /// (`{rm,-rf,out}`) that no single word shows.
+    fn push_posix_unquoted(&mut self, c: char) {
+        match c {
+            '{' if !self.word.ends_with('$') => self.brace = Some(false),
+            ',' if self.brace.is_some() => self.brace = Some(true),
+            '}' if self.brace == Some(true) => {
+                self.brace = None;
+                self.unresolved.push(format!("brace expansion `{}}}`", self.word));
+            }
+            _ => {}
+        }
+        self.push(c);
+    }
+
+    /// Consumes a POSIX `$(...)` after its `$(` and returns its text. `$((...))` is
+    /// arithmetic, where only the substitutions it contains run commands.
+    fn posix_dollar_paren(&mut self, chars: &mut Peekable<Chars>, depth: usize) -> Result<String, String> {
+        let dialect = ShellDialect::Posix;
+        let inner = take_balanced(chars, '(', ')', dialect)?;
+        match inner.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+            Some(expression) => {
+                let mut expression = expression.chars().peekable();
+                while let Some(c) = expression.next() {
+                    if c == '$' && expression.peek() == Some(&'(') {
+                        expression.next();
+                        self.posix_dollar_paren(&mut expression, depth)?;
+                    } else if c == '`' {
+                        let nested = take_backticks(&mut expression)?;
+                        self.nest(&nested, dialect, depth)?;
+                    }
+                }
+            }
+            None => self.nest(&inner, dialect, depth)?,
+        }
+        Ok(format!("$({inner})"))
+    }
+
+    fn finish(mut self) -> SplitCommand {
+        self.end_invocation();
+        SplitCommand {
+            invocations: self.invocations,
+            unresolved: self.unresolved,
+            redirections: self.redirections,
+        }
+    }
+}
+
+/// Splits `command` into the words of each invocation at unquoted command separators,
+/// following the quoting rules of `dialect`.
+fn split_words(command: &str, dialect: ShellDialect, depth: usize) -> Result<SplitCommand, String> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err("Command nesting is too deep.".to_string());
+    }
+    match dialect {
+        ShellDialect::Posix => split_posix(command, depth),
+        ShellDialect::Cmd => split_cmd(command, depth),
+        ShellDialect::PowerShell => split_powershell(command, depth),
+    }
+}
+
+fn skip_comment(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| *c != '\n') {
+        chars.next();
+    }
+}
+
+fn take_quoted(chars: &mut Peekable<Chars>, quote: char, escape: Option<char>, out: &mut String) -> Result<(), String> {
+    out.push(quote);
+    loop {
+        match chars.next() {
+            Some(c) if Some(c) == escape => {
+                out.push(c);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            Some(c) if c == quote => {
+                out.push(c);
+                return Ok(());
+            }
+            Some(c) => out.push(c),
+            None => return Err("Unterminated quote.".to_string()),
+        }
+    }
+}
+
+/// Consumes up to the `close` matching an already consumed `open` and returns the
+/// text in between, skipping over quoted strings.
+fn take_balanced(chars: &mut Peekable<Chars>, open: char, close: char, dialect: ShellDialect) -> Result<String, String> {
+    let escape = dialect.escape_char();
+    let mut depth = 1;
+    let mut inner = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c == escape => {
+                inner.push(c);
+                if let Some(next) = chars.next() {
+                    inner.push(next);
+                }
+            }
+            '\'' if dialect != ShellDialect::Cmd => take_quoted(chars, '\'', None, &mut inner)?,
+            '"' => {
+                let quote_escape = (dialect != ShellDialect::Cmd).then_some(escape);
+                take_quoted(chars, '"', quote_escape, &mut inner)?;
+            }
+            _ if c == open => {
+                depth += 1;
+                inner.push(c);
+            }
+            _ if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(inner);
+                }
+                inner.push(c);
+            }
+            _ => inner.push(c),
+        }
+    }
+    Err(format!("Unterminated `{open}`."))
+}
+
+fn take_backticks(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut inner = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ ('`' | '\\' | '$')) => inner.push(next),
+                Some(next) => {
+                    inner.push('\\');
+                    inner.push(next);
+                }
+                None => break,
+            },
+            '`' => return Ok(inner),
+            _ => inner.push(c),
+        }
+    }
+    Err("Unterminated backquote.".to_string())
+}
+
+fn split_posix(command: &str, depth: usize) -> Result<SplitCommand, String> {
+    let dialect = ShellDialect::Posix;
+    let mut splitter = Splitter::default();
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\r' => splitter.end_word(),
+            '\n' | ';' | ')' => splitter.end_invocation(),
+            '&' if splitter.in_redirection() => splitter.push(c),
+            '&' => {
+                if chars.peek() == Some(&'>') {
+                    splitter.end_word();
+                    splitter.push(c);
+                } else {
+                    if chars.peek() == Some(&'&') {
+                        chars.next();
+                    }
+                    splitter.end_invocation();
+                }
+            }
+            '|' if splitter.word.ends_with('>') => splitter.push(c),
+            '|' => {
+                if matches!(chars.peek(), Some('|') | Some('&')) {
+                    chars.next();
+                }
+                splitter.end_invocation();
+            }
+            '>' | '<' => {
+                splitter.start_redirection(dialect);
+                splitter.push(c);
+            }
+            '#' if splitter.at_word_start() => skip_comment(&mut chars),
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(next) => splitter.push(next),
+                None => splitter.push('\\'),
+            },
+            '(' => {
+                let inner = take_balanced(&mut chars, '(', ')', dialect)?;
+                splitter.nest(&inner, dialect, depth)?;
+                if !splitter.at_word_start() {
+                    splitter.push_str(&format!("({inner})"));
+                } else if inner.trim().is_empty() {
+                    // `name () { ...; }`: keep the parentheses so the definition is recognized.
+                    splitter.words.push("()".to_string());
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                let text = splitter.posix_dollar_paren(&mut chars, depth)?;
+                splitter.push_str(&text);
+            }
+            '`' => {
+                let inner = take_backticks(&mut chars)?;
+                splitter.nest(&inner, dialect, depth)?;
+                splitter.push_str(&format!("`{inner}`"));
+            }
+            '\'' => {
+                splitter.word_started = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(inner) => splitter.word.push(inner),
+                        None => return Err("Unterminated single quote.".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                splitter.word_started = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$' | '`')) => splitter.word.push(next),
+                            Some('\n') => {}
+                            Some(next) => {
+                                splitter.word.push('\\');
+                                splitter.word.push(next);
+                            }
+                            None => return Err("Unterminated double quote.".to_string()),
+                        },
+                        Some('$') if chars.peek() == Some(&'(') => {
+                            chars.next();
+                            let text = splitter.posix_dollar_paren(&mut chars, depth)?;
+                            splitter.word.push_str(&text);
+                        }
+                        Some('`') => {
+                            let inner = take_backticks(&mut chars)?;
+                            splitter.nest(&inner, dialect, depth)?;
+                            splitter.word.push_str(&format!("`{inner}`"));
+                        }
+                        Some(inner) => splitter.word.push(inner),
+                        None => return Err("Unterminated double quote.".to_string()),
+                    }
+                }
+            }
+            _ => splitter.push_posix_unquoted(c),
+        }
+    }
+
+    Ok(splitter.finish())
+}
+
+fn split_cmd(command: &str, depth: usize) -> Result<SplitCommand, String> {
+    let dialect = ShellDialect::Cmd;
+    let mut splitter = Splitter::default();
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\r' => splitter.end_word(),
+            '\n' | ')' => splitter.end_invocation(),
+            '&' if splitter.in_redirection() => splitter.push(c),
+            '&' | '|' => {
+                if chars.peek() == Some(&c) {
+                    chars.next();
+                }
+                splitter.end_invocation();
+            }
+            '^' => match chars.next() {
+                Some('\n') | None => {}
+                Some(next) => splitter.push(next),
+            },
+            '>' | '<' => {
+                splitter.start_redirection(dialect);
+                splitter.push(c);
+            }
+            '(' if splitter.at_word_start() && splitter.at_cmd_for_set() => {
+                let inner = take_balanced(&mut chars, '(', ')', dialect)?;
+                let set = inner.trim();
+                let command = ['\'', '`']
+                    .iter()
+                    .find_map(|quote| set.strip_prefix(*quote).and_then(|rest| rest.strip_suffix(*quote)));
+                let for_f = splitter.words.iter().any(|word| word.eq_ignore_ascii_case("/f"));
+                if let (true, Some(command)) = (for_f, command) {
+                    splitter.nest(command, dialect, depth)?;
+                }
+                splitter.push_str(&format!("({inner})"));
+            }
+            '(' if splitter.at_word_start() => {
+                let inner = take_balanced(&mut chars, '(', ')', dialect)?;
+                splitter.nest(&inner, dialect, depth)?;
+            }
+            '"' => {
+                // cmd tolerates a missing closing quote and treats the rest of the line as quoted.
+                splitter.word_started = true;
+                for inner in chars.by_ref() {
+                    if inner == '"' {
+                        break;
+                    }
+                    splitter.word.push(inner);
+                }
+            }
+            _ => splitter.push(c),
+        }
+    }
+
+    Ok(splitter.finish())
+}
+
+/// `@'` or `@"` followed by the end of the line opens a here-string.
+fn starts_here_string(chars: &Peekable<Chars>) -> bool {
+    let mut lookahead = chars.clone();
+    if !matches!(lookahead.next(), Some('\'' | '"')) {
+        return false;
+    }
+    lookahead.next_if_eq(&'\r');
+    lookahead.next() == Some('\n')
+}
+
+/// Consumes a here-string after its opening `@` and quote, up to the `'@` or `"@` at
+/// the start of a line, and returns its body without the surrounding line breaks.
+fn take_here_string(chars: &mut Peekable<Chars>, quote: char) -> Result<String, String> {
+    chars.next_if_eq(&'\r');
+    chars.next_if_eq(&'\n');
+    let mut body = String::new();
+    let mut at_line_start = true;
+    while let Some(c) = chars.next() {
+        if at_line_start && c == quote && chars.peek() == Some(&'@') {
+            chars.next();
+            let trimmed = body.strip_suffix('\n').unwrap_or(&body);
+            return Ok(trimmed.strip_suffix('\r').unwrap_or(trimmed).to_string());
+        }
+        at_line_start = c == '\n';
+        body.push(c);
+    }
+    Err("Unterminated here-string.".to_string())
+}
+
+fn split_powershell(command: &str, depth: usize) -> Result<SplitCommand, String> {
+    let dialect = ShellDialect::PowerShell;
+    let mut splitter = Splitter::default();
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\r' => splitter.end_word(),
+            '\n' | ';' | ')' | '}' => splitter.end_invocation(),
+            '&' if splitter.in_redirection() => splitter.push(c),
+            '&' => {
+                if chars.peek() == Some(&'&') {
+                    chars.next();
+                    splitter.end_invocation();
+                } else if splitter.at_statement_start() {
+                    // Call operator: kept as a word so the resolver knows the program is invoked.
+                    splitter.words.push("&".to_string());
+                } else {
+                    splitter.end_invocation();
+                }
+            }
+            '|' => {
+                if chars.peek() == Some(&'|') {
+                    chars.next();
+                }
+                splitter.end_invocation();
+            }
+            '<' if chars.peek() == Some(&'#') => {
+                let mut previous = '\0';
+                for inner in chars.by_ref() {
+                    if previous == '#' && inner == '>' {
+                        break;
+                    }
+                    previous = inner;
+                }
+            }
+            '*' if chars.peek() == Some(&'>') => {
+                splitter.end_word();
+                splitter.push(c);
+            }
+            '>' | '<' => {
+                splitter.start_redirection(dialect);
+                splitter.push(c);
+            }
+            '#' if splitter.at_word_start() => skip_comment(&mut chars),
+            '@' if starts_here_string(&chars) => {
+                let quote = chars.next().unwrap_or('\'');
+                let body = take_here_string(&mut chars, quote)?;
+                if quote == '"' {
+                    // Subexpressions in an expandable here-string run like any other.
+                    let mut body_chars = body.chars().peekable();
+                    while let Some(inner) = body_chars.next() {
+                        if inner == '$' && body_chars.peek() == Some(&'(') {
+                            body_chars.next();
+                            let nested = take_balanced(&mut body_chars, '(', ')', dialect)?;
+                            splitter.nest(&nested, dialect, depth)?;
+                        }
+                    }
+                }
+                splitter.push_str(&body);
+            }
+            '`' => match chars.next() {
+                Some('\n') => {}
+                Some(next) => splitter.push(next),
+                None => splitter.push('`'),
+            },
+            '{' if splitter.word.ends_with('$') => {
+                // `${name}` is a variable, not a script block.
+                splitter.push(c);
+                for inner in chars.by_ref() {
+                    splitter.push(inner);
+                    if inner == '}' {
+                        break;
+                    }
+                }
+            }
+            '(' | '{' => {
+                let close = if c == '(' { ')' } else { '}' };
+                let inner = take_balanced(&mut chars, c, close, dialect)?;
+                splitter.nest(&inner, dialect, depth)?;
+                splitter.push_str(&format!("{c}{inner}{close}"));
+            }
+            '\'' => {
+                splitter.word_started = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            splitter.word.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(inner) => splitter.word.push(inner),
+                        None => return Err("Unterminated single quote.".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                splitter.word_started = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            splitter.word.push('"');
+                        }
+                        Some('"') => break,
+                        Some('`') => match chars.next() {
+                            Some(next) => splitter.word.push(next),
+                            None => return Err("Unterminated double quote.".to_string()),
+                        },
+                        Some('$') if chars.peek() == Some(&'(') => {
+                            chars.next();
+                            let inner = take_balanced(&mut chars, '(', ')', dialect)?;
+                            splitter.nest(&inner, dialect, depth)?;
+                            splitter.word.push_str(&format!("$({inner})"));
+                        }
+                        Some(inner) => splitter.word.push(inner),
+                        None => return Err("Unterminated double quote.".to_string()),
+                    }
+                }
+            }
+            _ => splitter.push(c),
+        }
+    }
+
+    Ok(splitter.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(command: &str, dialect: ShellDialect) -> Vec<Vec<String>> {
+        split_words(command, dialect, 0).unwrap().invocations
+    }
+
+    fn words(items: &[&[&str]]) -> Vec<Vec<String>> {
+        items
+            .iter()
+            .map(|words| words.iter().map(|word| word.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn posix_quoting_and_escapes() {
+        assert_eq!(
+            split(r#"echo 'a  b' "c \"d\" $e" f\ g 'it'\''s'"#, ShellDialect::Posix),
+            words(&[&["echo", "a  b", r#"c "d" $e"#, "f g", "it's"]])
+        );
+        // A backslash-newline continues the line; `\\` escapes the backslash.
+        assert_eq!(split("echo a\\\nb", ShellDialect::Posix), words(&[&["echo", "ab"]]));
+        assert_eq!(split("echo a\\\\b", ShellDialect::Posix), words(&[&["echo", "a\\b"]]));
+        assert_eq!(split("echo a \\\n b", ShellDialect::Posix), words(&[&["echo", "a", "b"]]));
+        assert_eq!(split("echo '' \"\"", ShellDialect::Posix), words(&[&["echo", "", ""]]));
+        assert!(split_words("echo 'open", ShellDialect::Posix, 0).is_err());
+        assert!(split_words("echo \"open", ShellDialect::Posix, 0).is_err());
+    }
+
+    #[test]
+    fn posix_separators_and_nesting() {
+        assert_eq!(
+            split("a && b || c; d | e & f 2>&1", ShellDialect::Posix),
+            words(&[&["a"], &["b"], &["c"], &["d"], &["e"], &["f"]])
+        );
+        // Substitutions are recorded before the invocation that uses them.
+        assert_eq!(
+            split("echo $(date) `whoami`", ShellDialect::Posix),
+            words(&[&["date"], &["whoami"], &["echo", "$(date)", "`whoami`"]])
+        );
+        assert_eq!(split("echo # rm -rf /", ShellDialect::Posix), words(&[&["echo"]]));
+    }
+
+    fn redirections(command: &str, dialect: ShellDialect) -> Vec<(String, String)> {
+        split_words(command, dialect, 0)
+            .unwrap()
+            .redirections
+            .into_iter()
+            .map(|item| (item.operator, item.target))
+            .collect()
+    }
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(operator, target)| (operator.to_string(), target.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn glued_redirections_are_split_off() {
+        assert_eq!(
+            split("rm>/dev/null -rf out; rm<x -rf out", ShellDialect::Posix),
+            words(&[&["rm", "-rf", "out"], &["rm", "-rf", "out"]])
+        );
+        assert_eq!(
+            redirections("rm>/dev/null -rf out; rm<x -rf out", ShellDialect::Posix),
+            pairs(&[(">", "/dev/null"), ("<", "x")])
+        );
+        assert_eq!(
+            split("git 2>/dev/null>>log reset&>x --hard", ShellDialect::Posix),
+            words(&[&["git", "reset", "--hard"]])
+        );
+        assert_eq!(
+            redirections("git 2>/dev/null>>log reset&>x --hard", ShellDialect::Posix),
+            pairs(&[("2>", "/dev/null"), (">>", "log"), ("&>", "x")])
+        );
+        assert_eq!(split("echo hi >| x", ShellDialect::Posix), words(&[&["echo", "hi"]]));
+        assert_eq!(redirections("echo hi >| x", ShellDialect::Posix), pairs(&[(">|", "x")]));
+        assert_eq!(
+            redirections("echo a>|b 2>&1", ShellDialect::Posix),
+            pairs(&[(">|", "b"), ("2>&", "1")])
+        );
+        // Quoted or escaped operators are ordinary words.
+        assert_eq!(split("echo 'a>b' \\>c '>' d", ShellDialect::Posix), words(&[&["echo", "a>b", ">c", ">", "d"]]));
+        assert!(redirections("echo 'a>b' \\>c '>' d", ShellDialect::Posix).is_empty());
+        assert_eq!(
+            split("rd>nul /s /q out & del 2>nul /s x", ShellDialect::Cmd),
+            words(&[&["rd", "/s", "/q", "out"], &["del", "/s", "x"]])
+        );
+        assert_eq!(
+            split("Remove-Item>$null -Recurse*>&1 -Force out 2>&1", ShellDialect::PowerShell),
+            words(&[&["Remove-Item", "-Recurse", "-Force", "out"]])
+        );
+        assert_eq!(
+            redirections("Remove-Item>$null -Recurse*>&1 -Force out 2>&1", ShellDialect::PowerShell),
+            pairs(&[(">", "$null"), ("*>&", "1"), ("2>&", "1")])
+        );
+
+        let parsed = parse_command("echo hi >| x", ShellDialect::Posix).unwrap();
+        assert_eq!(parsed.invocations.len(), 1);
+        assert_eq!(parsed.invocations[0].program, "echo");
+    }
+
+    #[test]
+    fn statements_without_a_program_keep_their_redirections_and_assignments() {
+        assert!(split("> out; echo a;>b", ShellDialect::Posix).iter().all(|words| words == &["echo", "a"]));
+        assert_eq!(
+            redirections("> out; echo a;>b >", ShellDialect::Posix),
+            pairs(&[(">", "out"), (">", "b"), (">", "")])
+        );
+
+        let parsed = parse_command("x=1 >y; PATH=. ls", ShellDialect::Posix).unwrap();
+        assert_eq!(parsed.assignments, ["x=1", "PATH=."]);
+        assert_eq!(redirections("x=1 >y", ShellDialect::Posix), pairs(&[(">", "y")]));
+        assert_eq!(parsed.invocations[0].program, "ls");
+
+        let command = "$env:PATH = '.'; Get-ChildItem | ForEach-Object { $_ > x }";
+        let parsed = parse_command(command, ShellDialect::PowerShell).unwrap();
+        assert_eq!(parsed.assignments, ["$env:PATH"]);
+        assert_eq!(parsed.redirections[0].target, "x");
+    }
+
+    #[test]
+    fn posix_arithmetic_only_runs_its_substitutions() {
+        let parsed = parse_command("echo $((1)) \"$(( $(date +%s) + `id -u` ))\"", ShellDialect::Posix).unwrap();
+        let programs: Vec<&str> = parsed.invocations.iter().map(|item| item.program.as_str()).collect();
+        assert_eq!(programs, ["date", "id", "echo"]);
+        assert!(parsed.unresolved.is_empty(), "{:?}", parsed.unresolved);
+    }
+
+    #[test]
+    fn cmd_for_sets_are_not_blocks() {
+        let parsed = parse_command("for /r %f in (*) do del %f", ShellDialect::Cmd).unwrap();
+        let programs: Vec<&str> = parsed.invocations.iter().map(|item| item.program.as_str()).collect();
+        assert_eq!(programs, ["for", "del"]);
+        let parsed = parse_command("for /f %i in ('rd /s /q out') do echo %i", ShellDialect::Cmd).unwrap();
+        let programs: Vec<&str> = parsed.invocations.iter().map(|item| item.program.as_str()).collect();
+        assert_eq!(programs, ["rd", "for", "echo"]);
+    }
+
+    #[test]
+    fn posix_reports_brace_expansion_and_function_definitions() {
+        let brace = split_words("{rm,-rf,x}", ShellDialect::Posix, 0).unwrap();
+        assert_eq!(brace.unresolved, ["brace expansion `{rm,-rf,x}`"]);
+        for quiet in ["echo '{a,b}'", "echo \"{a,b}\"", "echo ${v,,}", "{ ls; }", "echo {}"] {
+            assert!(split_words(quiet, ShellDialect::Posix, 0).unwrap().unresolved.is_empty(), "{quiet}");
+        }
+
+        let parsed = parse_command("f() { rm -rf x; }; f", ShellDialect::Posix).unwrap();
+        assert_eq!(parsed.unresolved, ["function definition `f`"]);
+        let programs: Vec<&str> = parsed.invocations.iter().map(|item| item.program.as_str()).collect();
+        assert_eq!(programs, ["rm", "f"]);
+        let parsed = parse_command("function g () { git clean -fdx; }", ShellDialect::Posix).unwrap();
+        assert_eq!(parsed.unresolved, ["function definition `g`"]);
+        assert_eq!(parsed.invocations[0].program, "git");
+    }
+
+    #[test]
+    fn cmd_caret_quotes_and_glued_switches() {
+        assert_eq!(
+            split("echo a^&b ^| \"c & d\" & dir", ShellDialect::Cmd),
+            words(&[&["echo", "a&b", "|", "c & d"], &["dir"]])
+        );
+        assert_eq!(split("echo one^\ntwo", ShellDialect::Cmd), words(&[&["echo", "onetwo"]]));
+        // cmd has no escape inside quotes and tolerates an unterminated one.
+        assert_eq!(split("echo \"a^&b", ShellDialect::Cmd), words(&[&["echo", "a^&b"]]));
+        assert_eq!(split("echo 'a & b'", ShellDialect::Cmd), words(&[&["echo", "'a"], &["b'"]]));
+
+        let parsed = parse_command("rd/s/q out & @del/f x", ShellDialect::Cmd).unwrap();
+        assert_eq!(parsed.invocations[0].program, "rd");
+        assert_eq!(parsed.invocations[0].args, ["/s/q", "out"]);
+        assert_eq!(parsed.invocations[1].program, "del");
+        assert_eq!(parsed.invocations[1].args, ["/f", "x"]);
+        let parsed = parse_command("C:\\tools\\x.exe /s", ShellDialect::Cmd).unwrap();
+        assert_eq!(parsed.invocations[0].program, "x");
+    }
+
+    #[test]
+    fn powershell_separators_quotes_and_escapes() {
+        assert_eq!(
+            split("Get-Item a; Get-Item b | Out-Null && c || d", ShellDialect::PowerShell),
+            words(&[&["Get-Item", "a"], &["Get-Item", "b"], &["Out-Null"], &["c"], &["d"]])
+        );
+        assert_eq!(
+            split("Write-Output 'it''s; fine' \"say \"\"hi\"\" `\"x`\"\" a`;b", ShellDialect::PowerShell),
+            words(&[&["Write-Output", "it's; fine", "say \"hi\" \"x\"", "a;b"]])
+        );
+        assert_eq!(split("& git status", ShellDialect::PowerShell), words(&[&["&", "git", "status"]]));
+    }
+
+    #[test]
+    fn powershell_here_strings() {
+        let literal = "Write-Output @'\nit's; rm -rf out | x\n'@; Get-Date";
+        assert_eq!(
+            split(literal, ShellDialect::PowerShell),
+            words(&[&["Write-Output", "it's; rm -rf out | x"], &["Get-Date"]])
+        );
+
+        let expandable = "Write-Output @\"\r\nsay \"hi\"\r\n$(Remove-Item -Recurse out)\r\n\"@";
+        let split = split(expandable, ShellDialect::PowerShell);
+        assert_eq!(split[0], ["Remove-Item", "-Recurse", "out"]);
+        assert_eq!(split[1][1], "say \"hi\"\r\n$(Remove-Item -Recurse out)");
+
+        // `@'` on the same line as text is not a here-string.
+        assert!(split_words("Write-Output @'\nno end", ShellDialect::PowerShell, 0).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+/// Value of `format` in an encrypted store file. Plain files are the bare `AppDb` JSON.
+const ENVELOPE_FORMAT: &str = "codex-encrypted-db";
+const ENVELOPE_VERSION: u32 = 1;
+/// Value of `format` in an encrypted audit blob.
+const BLOB_FORMAT: &str = "codex-encrypted-blob";
+pub(crate) const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// Data key for the store. It lives in the secret store as `<id>:<base64>`.
+pub(crate) struct StoreKey {
+    pub(crate) id: String,
+    bytes: [u8; KEY_LEN],
+}
+
+/// The data key encrypted with the recovery key, so the store can be opened again if
+/// the keyring entry is lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecoveryWrap {
+    nonce: String,
+    wrapped_key: String,
+}
+
+/// On-disk form of an encrypted store. `format`, `version` and `key_id` are bound to
+/// the ciphertext as associated data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EncryptedStore {
+    format: String,
+    version: u32,
+    pub(crate) key_id: String,
+    nonce: String,
+    ciphertext: String,
+    pub(crate) recovery: RecoveryWrap,
+}
+
+/// On-disk form of an audit blob kept while the store is encrypted. The blob's name is
+/// bound to the ciphertext too, so one blob cannot stand in for another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedBlob {
+    format: String,
+    version: u32,
+    key_id: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_base64<const N: usize>(value: &str, what: &str) -> Result<[u8; N], String> {
+    BASE64
+        .decode(value)
+        .ok()
+        .and_then(|bytes| <[u8; N]>::try_from(bytes).ok())
+        .ok_or_else(|| format!("Encrypted store has an invalid {what}."))
+}
+
+pub(crate) fn seal(key: &[u8; KEY_LEN], plaintext: &[u8], aad: &[u8]) -> Result<(String, String), String> {
+    let nonce = random_bytes::<NONCE_LEN>();
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed.".to_string())?;
+    Ok((BASE64.encode(nonce), BASE64.encode(ciphertext)))
+}
+
+pub(crate) fn open(key: &[u8; KEY_LEN], nonce: &str, ciphertext: &str, aad: &[u8]) -> Option<Vec<u8>> {
+    let nonce = decode_base64::<NONCE_LEN>(nonce, "nonce").ok()?;
+    let ciphertext = BASE64.decode(ciphertext).ok()?;
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .ok()
+}
+
+fn envelope_aad(key_id: &str) -> Vec<u8> {
+    format!("{ENVELOPE_FORMAT}:{ENVELOPE_VERSION}:{key_id}").into_bytes()
+}
+
+fn blob_aad(key_id: &str, name: &str) -> Vec<u8> {
+    format!("{BLOB_FORMAT}:{ENVELOPE_VERSION}:{key_id}:{name}").into_bytes()
+}
+
+impl StoreKey {
+    pub(crate) fn generate() -> Self {
+        Self {
+            id: hex(&random_bytes::<8>()),
+            bytes: random_bytes(),
+        }
+    }
+
+    pub(crate) fn to_keyring_value(&self) -> String {
+        format!("{}:{}", self.id, BASE64.encode(self.bytes))
+    }
+
+    pub(crate) fn from_keyring_value(value: &str) -> Result<Self, String> {
+        let (id, key) = value
+            .split_once(':')
+            .ok_or_else(|| "Data store key in the secret store is malformed.".to_string())?;
+        Ok(Self {
+            id: id.to_string(),
+            bytes: decode_base64(key, "key").map_err(|_| "Data store key in the secret store is malformed.".to_string())?,
+        })
+    }
+
+    /// Encrypts this key under a fresh recovery key. The returned code is the only copy
+    /// of the recovery key and is shown to the user once.
+    pub(crate) fn wrap_for_recovery(&self) -> Result<(RecoveryWrap, String), String> {
+        let recovery_key = random_bytes::<KEY_LEN>();
+        let (nonce, wrapped_key) = seal(&recovery_key, &self.bytes, self.id.as_bytes())?;
+        Ok((RecoveryWrap { nonce, wrapped_key }, format_recovery_code(&recovery_key)))
+    }
+}
+
+/// Recovery keys are shown as eight dash-separated groups of hex digits.
+fn format_recovery_code(key: &[u8; KEY_LEN]) -> String {
+    hex(key)
+        .as_bytes()
+        .chunks(8)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn parse_recovery_code(code: &str) -> Result<[u8; KEY_LEN], String> {
+    let digits: String = code
+        .chars()
+        .filter(|ch| !ch.is_whitespace() && *ch != '-')
+        .collect();
+    let invalid = || "Recovery key is not valid.".to_string();
+    if digits.len() != KEY_LEN * 2 || !digits.is_ascii() {
+        return Err(invalid());
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+/// The envelope in `raw`, or `None` when the file holds a plain store.
+pub(crate) fn parse_envelope(raw: &str) -> Result<Option<EncryptedStore>, String> {
+    let value: serde_json::Value = serde_json::from_str(raw).map_err(|err| format!("Invalid db json: {err}"))?;
+    if value.get("format").and_then(|format| format.as_str()) != Some(ENVELOPE_FORMAT) {
+        return Ok(None);
+    }
+    let envelope: EncryptedStore =
+        serde_json::from_value(value).map_err(|err| format!("Invalid encrypted store: {err}"))?;
+    if envelope.version != ENVELOPE_VERSION {
+        return Err(format!("Unsupported encrypted store version {}.", envelope.version));
+    }
+    Ok(Some(envelope))
+}
+
+pub(crate) fn encrypt(key: &StoreKey, recovery: &RecoveryWrap, plaintext: &[u8]) -> Result<String, String> {
+    let (nonce, ciphertext) = seal(&key.bytes, plaintext, &envelope_aad(&key.id))?;
+    let envelope = EncryptedStore {
+        format: ENVELOPE_FORMAT.to_string(),
+        version: ENVELOPE_VERSION,
+        key_id: key.id.clone(),
+        nonce,
+        ciphertext,
+        recovery: recovery.clone(),
+    };
+    serde_json::to_string(&envelope).map_err(|err| format!("Failed serializing db: {err}"))
+}
+
+pub(crate) fn decrypt(envelope: &EncryptedStore, key: &StoreKey) -> Result<Vec<u8>, String> {
+    if envelope.key_id != key.id {
+        return Err(format!(
+            "Data store was encrypted with key {}, but the secret store holds key {}.",
+            envelope.key_id, key.id
+        ));
+    }
+    open(&key.bytes, &envelope.nonce, &envelope.ciphertext, &envelope_aad(&key.id))
+        .ok_or_else(|| "Failed to decrypt data store: the file is corrupt or was modified.".to_string())
+}
+
+/// Unwraps the data key of `envelope` with a recovery code.
+pub(crate) fn recover_key(envelope: &EncryptedStore, recovery_code: &str) -> Result<StoreKey, String> {
+    let recovery_key = parse_recovery_code(recovery_code)?;
+    let bytes = open(
+        &recovery_key,
+        &envelope.recovery.nonce,
+        &envelope.recovery.wrapped_key,
+        envelope.key_id.as_bytes(),
+    )
+    .and_then(|bytes| <[u8; KEY_LEN]>::try_from(bytes).ok())
+    .ok_or_else(|| "Recovery key does not match this data store.".to_string())?;
+    Ok(StoreKey {
+        id: envelope.key_id.clone(),
+        bytes,
+    })
+}
+
+pub(crate) fn seal_blob(key: &StoreKey, name: &str, plaintext: &[u8]) -> Result<String, String> {
+    let (nonce, ciphertext) = seal(&key.bytes, plaintext, &blob_aad(&key.id, name))?;
+    let blob = EncryptedBlob {
+        format: BLOB_FORMAT.to_string(),
+        version: ENVELOPE_VERSION,
+        key_id: key.id.clone(),
+        nonce,
+        ciphertext,
+    };
+    serde_json::to_string(&blob).map_err(|err| format!("Failed serializing audit blob: {err}"))
+}
+
+pub(crate) fn open_blob(key: &StoreKey, name: &str, raw: &[u8]) -> Result<Vec<u8>, String> {
+    let blob: EncryptedBlob =
+        serde_json::from_slice(raw).map_err(|err| format!("Invalid encrypted audit blob: {err}"))?;
+    if blob.format != BLOB_FORMAT || blob.version != ENVELOPE_VERSION {
+        return Err("Unsupported encrypted audit blob.".to_string());
+    }
+    if blob.key_id != key.id {
+        return Err(format!("Audit blob {name} was encrypted with key {}.", blob.key_id));
+    }
+    open(&key.bytes, &blob.nonce, &blob.ciphertext, &blob_aad(&key.id, name))
+        .ok_or_else(|| format!("Failed to decrypt audit blob {name}."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed(plaintext: &str) -> (StoreKey, String, EncryptedStore) {
+        let key = StoreKey::generate();
+        let (recovery, code) = key.wrap_for_recovery().unwrap();
+        let raw = encrypt(&key, &recovery, plaintext.as_bytes()).unwrap();
+        let envelope = parse_envelope(&raw).unwrap().unwrap();
+        (key, code, envelope)
+    }
+
+    #[test]
+    fn round_trips_through_keyring_value() {
+        let (key, _, envelope) = sealed(r#"{"projects":[]}"#);
+        let restored = StoreKey::from_keyring_value(&key.to_keyring_value()).unwrap();
+        assert_eq!(decrypt(&envelope, &restored).unwrap(), br#"{"projects":[]}"#);
+    }
+
+    #[test]
+    fn plain_store_is_not_an_envelope() {
+        assert!(parse_envelope(r#"{"projects":[],"settings":{}}"#).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_other_keys_and_tampering() {
+        let (_, _, mut envelope) = sealed("secret conversation");
+        assert!(decrypt(&envelope, &StoreKey::generate()).is_err());
+
+        let (key, _, _) = sealed("other");
+        envelope.key_id = key.id.clone();
+        assert!(decrypt(&envelope, &key).is_err());
+    }
+
+    #[test]
+    fn recovers_key_with_recovery_code() {
+        let (key, code, envelope) = sealed("messages");
+        let recovered = recover_key(&envelope, &code.to_uppercase().replace('-', " ")).unwrap();
+        assert_eq!(recovered.id, key.id);
+        assert_eq!(decrypt(&envelope, &recovered).unwrap(), b"messages");
+
+        let (_, other_code, _) = sealed("other");
+        assert!(recover_key(&envelope, &other_code).is_err());
+        assert!(recover_key(&envelope, "not-a-code").is_err());
+    }
+
+    #[test]
+    fn blobs_open_only_under_their_key_and_name() {
+        let key = StoreKey::generate();
+        let sealed = seal_blob(&key, "abc", b"previous contents").unwrap();
+        assert_eq!(open_blob(&key, "abc", sealed.as_bytes()).unwrap(), b"previous contents");
+        assert!(open_blob(&key, "def", sealed.as_bytes()).is_err());
+        assert!(open_blob(&StoreKey::generate(), "abc", sealed.as_bytes()).is_err());
+        assert!(open_blob(&key, "abc", b"previous contents").is_err());
+    }
+}
@@ -0,0 +1,456 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+const MAX_RECORDED_FAILURES: usize = 100;
+const MAX_FAILURE_DETAILS_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TestResults {
+    pub(crate) sources: Vec<String>,
+    pub(crate) passed: u32,
+    pub(crate) failed: u32,
+    pub(crate) skipped: u32,
+    pub(crate) duration_ms: Option<u64>,
+    pub(crate) failures: Vec<TestFailure>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TestFailure {
+    pub(crate) name: String,
+    pub(crate) suite: Option<String>,
+    pub(crate) message: String,
+    pub(crate) details: String,
+    pub(crate) duration_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl TestResults {
+    pub(crate) fn merge(&mut self, other: TestResults) {
+        self.sources.extend(other.sources);
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.duration_ms = match (self.duration_ms, other.duration_ms) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        for failure in other.failures {
+            self.push_failure(failure);
+        }
+    }
+
+    fn record(&mut self, outcome: Outcome, duration_ms: Option<u64>, failure: impl FnOnce() -> TestFailure) {
+        match outcome {
+            Outcome::Passed => self.passed += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Failed => {
+                self.failed += 1;
+                self.push_failure(failure());
+            }
+        }
+        if let Some(duration) = duration_ms {
+            self.duration_ms = Some(self.duration_ms.unwrap_or(0) + duration);
+        }
+    }
+
+    fn push_failure(&mut self, mut failure: TestFailure) {
+        if self.failures.len() >= MAX_RECORDED_FAILURES {
+            return;
+        }
+        if failure.details.chars().count() > MAX_FAILURE_DETAILS_CHARS {
+            failure.details = failure.details.chars().take(MAX_FAILURE_DETAILS_CHARS).collect();
+            failure.details.push_str("\n[truncated]");
+        }
+        self.failures.push(failure);
+    }
+
+    fn total(&self) -> u32 {
+        self.passed + self.failed + self.skipped
+    }
+}
+
+fn seconds_to_ms(value: &str) -> Option<u64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(|secs| (secs * 1000.0).round() as u64)
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .filter_map(Result::ok)
+        .find(|attr| attr.key.as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.to_string()))
+}
+
+#[derive(Default)]
+struct JunitCase {
+    name: String,
+    classname: Option<String>,
+    duration_ms: Option<u64>,
+    outcome: Option<Outcome>,
+    message: String,
+    details: String,
+}
+
+impl JunitCase {
+    fn from_element(element: &BytesStart) -> Self {
+        Self {
+            name: attribute(element, b"name").unwrap_or_default(),
+            classname: attribute(element, b"classname"),
+            duration_ms: attribute(element, b"time").and_then(|time| seconds_to_ms(&time)),
+            ..Self::default()
+        }
+    }
+
+    fn finish(self, suite: Option<&String>, results: &mut TestResults) {
+        let outcome = self.outcome.unwrap_or(Outcome::Passed);
+        let duration_ms = self.duration_ms;
+        results.record(outcome, duration_ms, || TestFailure {
+            name: self.name,
+            suite: self.classname.or_else(|| suite.cloned()),
+            message: self.message,
+            details: self.details.trim().to_string(),
+            duration_ms,
+        });
+    }
+
+    fn mark(&mut self, element: &BytesStart) {
+        match element.name().as_ref() {
+            b"failure" | b"error" => {
+                self.outcome = Some(Outcome::Failed);
+                if self.message.is_empty() {
+                    self.message = attribute(element, b"message")
+                        .or_else(|| attribute(element, b"type"))
+                        .unwrap_or_default();
+                }
+            }
+            b"skipped" if self.outcome.is_none() => self.outcome = Some(Outcome::Skipped),
+            _ => {}
+        }
+    }
+}
+
+/// Parses a JUnit XML report. Returns `Ok(None)` when the document does not look like one.
+pub(crate) fn parse_junit(xml: &str, source: &str) -> Result<Option<TestResults>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut results = TestResults {
+        sources: vec![format!("junit:{source}")],
+        ..TestResults::default()
+    };
+    let mut suites: Vec<String> = Vec::new();
+    let mut case: Option<JunitCase> = None;
+    let mut capturing_details = false;
+    let mut seen_root = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|err| format!("Invalid JUnit XML in {source}: {err}"))?;
+        match event {
+            Event::Start(element) => match element.name().as_ref() {
+                b"testsuites" => seen_root = true,
+                b"testsuite" => {
+                    seen_root = true;
+                    suites.push(attribute(&element, b"name").unwrap_or_default());
+                }
+                b"testcase" => case = Some(JunitCase::from_element(&element)),
+                b"failure" | b"error" | b"skipped" => {
+                    if let Some(current) = case.as_mut() {
+                        current.mark(&element);
+                        capturing_details = element.name().as_ref() != b"skipped";
+                    }
+                }
+                _ => {}
+            },
+            Event::Empty(element) => match element.name().as_ref() {
+                b"testsuite" | b"testsuites" => seen_root = true,
+                b"testcase" => JunitCase::from_element(&element).finish(suites.last(), &mut results),
+                b"failure" | b"error" | b"skipped" => {
+                    if let Some(current) = case.as_mut() {
+                        current.mark(&element);
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) if capturing_details => {
+                if let (Some(current), Ok(text)) = (case.as_mut(), text.decode()) {
+                    current.details.push_str(&text);
+                }
+            }
+            Event::GeneralRef(reference) if capturing_details => {
+                if let (Some(current), Ok(name)) = (case.as_mut(), reference.decode()) {
+                    let raw = format!("&{name};");
+                    let resolved = quick_xml::escape::unescape(&raw)
+                        .map(|value| value.to_string())
+                        .unwrap_or(raw);
+                    current.details.push_str(&resolved);
+                }
+            }
+            Event::CData(data) if capturing_details => {
+                if let (Some(current), Ok(text)) = (case.as_mut(), data.decode()) {
+                    current.details.push_str(&text);
+                }
+            }
+            Event::End(element) => match element.name().as_ref() {
+                b"testsuite" => {
+                    suites.pop();
+                }
+                b"testcase" => {
+                    if let Some(current) = case.take() {
+                        current.finish(suites.last(), &mut results);
+                    }
+                }
+                b"failure" | b"error" | b"skipped" => capturing_details = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(seen_root.then_some(results))
+}
+
+struct TapLine<'a> {
+    ok: bool,
+    description: &'a str,
+    directive: Option<String>,
+}
+
+fn parse_tap_test_line(line: &str) -> Option<TapLine<'_>> {
+    let (ok, rest) = if let Some(rest) = line.strip_prefix("not ok") {
+        (false, rest)
+    } else if let Some(rest) = line.strip_prefix("ok") {
+        (true, rest)
+    } else {
+        return None;
+    };
+    if !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start();
+    let rest = rest.strip_prefix("- ").unwrap_or(rest);
+
+    let (description, directive) = match rest.split_once(" # ").or_else(|| rest.split_once('#').filter(|(head, _)| head.is_empty())) {
+        Some((description, directive)) => (description, Some(directive.trim().to_ascii_uppercase())),
+        None => (rest, None),
+    };
+
+    Some(TapLine {
+        ok,
+        description: description.trim(),
+        directive,
+    })
+}
+
+/// Parses TAP output from a task's stdout. Returns `None` unless a plan (`1..N`) or
+/// `TAP version` header is present, so stray lines starting with "ok" are not counted.
+pub(crate) fn parse_tap<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<TestResults> {
+    let mut results = TestResults {
+        sources: vec!["tap:stdout".to_string()],
+        ..TestResults::default()
+    };
+    let mut has_header = false;
+    let mut pending: Option<TestFailure> = None;
+    let mut in_yaml = false;
+
+    let flush = |pending: &mut Option<TestFailure>, results: &mut TestResults| {
+        if let Some(failure) = pending.take() {
+            let duration = failure.duration_ms;
+            results.record(Outcome::Failed, duration, || failure);
+        }
+    };
+
+    for line in lines {
+        if in_yaml {
+            let trimmed = line.trim();
+            if trimmed == "..." {
+                in_yaml = false;
+            } else if let Some(failure) = pending.as_mut() {
+                if let Some(message) = trimmed.strip_prefix("message:") {
+                    failure.message = message.trim().trim_matches(|c| c == '\'' || c == '"').to_string();
+                } else if let Some(duration) = trimmed.strip_prefix("duration_ms:") {
+                    failure.duration_ms = duration.trim().parse::<f64>().ok().map(|ms| ms.round() as u64);
+                }
+                failure.details.push_str(trimmed);
+                failure.details.push('\n');
+            }
+            continue;
+        }
+
+        if line.trim() == "---" && line.starts_with(' ') {
+            in_yaml = true;
+            continue;
+        }
+
+        if line.starts_with("TAP version") {
+            has_header = true;
+            continue;
+        }
+        if let Some(plan) = line.strip_prefix("1..") {
+            if plan.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+                has_header = true;
+            }
+            continue;
+        }
+        if let Some(reason) = line.strip_prefix("Bail out!") {
+            flush(&mut pending, &mut results);
+            results.record(Outcome::Failed, None, || TestFailure {
+                name: "Bail out!".to_string(),
+                message: reason.trim().to_string(),
+                ..TestFailure::default()
+            });
+            continue;
+        }
+
+        let Some(test) = parse_tap_test_line(line) else {
+            continue;
+        };
+        flush(&mut pending, &mut results);
+
+        let skipped = test
+            .directive
+            .as_deref()
+            .is_some_and(|directive| directive.starts_with("SKIP") || directive.starts_with("TODO"));
+        if skipped {
+            results.record(Outcome::Skipped, None, TestFailure::default);
+        } else if test.ok {
+            results.record(Outcome::Passed, None, TestFailure::default);
+        } else {
+            pending = Some(TestFailure {
+                name: test.description.to_string(),
+                ..TestFailure::default()
+            });
+        }
+    }
+    flush(&mut pending, &mut results);
+
+    (has_header && results.total() > 0).then_some(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTSUITES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="all">
+  <testsuite name="math" tests="4">
+    <testcase name="adds" classname="math.Add" time="0.25"/>
+    <testcase name="divides" classname="math.Div" time="0.5">
+      <failure message="expected &lt;2&gt; but was &quot;3&quot;" type="AssertionError">at div.rs:10 &amp; more</failure>
+    </testcase>
+    <testcase name="panics" time="0.125">
+      <error type="Panic"><![CDATA[thread 'main' panicked]]></error>
+    </testcase>
+    <testcase name="later"><skipped message="not yet"/></testcase>
+  </testsuite>
+  <testsuite name="strings">
+    <testcase name="trims" time="1"></testcase>
+  </testsuite>
+</testsuites>"#;
+
+    #[test]
+    fn parses_testsuites_with_failures_errors_and_skips() {
+        let results = parse_junit(TESTSUITES, "junit.xml").unwrap().unwrap();
+        assert_eq!(results.sources, ["junit:junit.xml"]);
+        assert_eq!((results.passed, results.failed, results.skipped), (2, 2, 1));
+        assert_eq!(results.duration_ms, Some(1875));
+
+        let failure = &results.failures[0];
+        assert_eq!(failure.name, "divides");
+        assert_eq!(failure.suite.as_deref(), Some("math.Div"));
+        assert_eq!(failure.message, r#"expected <2> but was "3""#);
+        assert_eq!(failure.details, "at div.rs:10 & more");
+        assert_eq!(failure.duration_ms, Some(500));
+
+        // Without a classname the failure is attributed to the enclosing suite; the
+        // error type stands in for a missing message.
+        let error = &results.failures[1];
+        assert_eq!(error.suite.as_deref(), Some("math"));
+        assert_eq!(error.message, "Panic");
+        assert_eq!(error.details, "thread 'main' panicked");
+    }
+
+    #[test]
+    fn parses_a_bare_testsuite() {
+        let xml = r#"<testsuite name="solo"><testcase name="a"/><testcase name="b"><failure/></testcase></testsuite>"#;
+        let results = parse_junit(xml, "solo.xml").unwrap().unwrap();
+        assert_eq!((results.passed, results.failed, results.skipped), (1, 1, 0));
+        assert_eq!(results.failures[0].suite.as_deref(), Some("solo"));
+        assert_eq!(results.duration_ms, None);
+    }
+
+    #[test]
+    fn rejects_malformed_and_foreign_xml() {
+        let err = parse_junit("<testsuite><testcase name=\"a\"></testsuite>", "bad.xml").unwrap_err();
+        assert!(err.contains("bad.xml"), "{err}");
+        assert!(parse_junit("<project><build/></project>", "pom.xml").unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_tap_with_directives_and_yaml() {
+        let output = "TAP version 13
+1..6
+ok 1 - adds
+not ok 2 - divides
+  ---
+  message: 'expected 2'
+  duration_ms: 12.4
+  ...
+ok 3 - network # SKIP offline
+not ok 4 - flaky # TODO fix later
+ok 5
+not ok 6 - last";
+        let results = parse_tap(output.lines()).unwrap();
+        assert_eq!(results.sources, ["tap:stdout"]);
+        assert_eq!((results.passed, results.failed, results.skipped), (2, 2, 2));
+        assert_eq!(results.failures[0].name, "divides");
+        assert_eq!(results.failures[0].message, "expected 2");
+        assert_eq!(results.failures[0].duration_ms, Some(12));
+        assert_eq!(results.failures[1].name, "last");
+    }
+
+    #[test]
+    fn tap_plan_at_the_end_and_bail_out() {
+        let results = parse_tap(["ok 1 - one", "not ok 2 - two", "Bail out! database down", "1..5"]).unwrap();
+        assert_eq!((results.passed, results.failed), (1, 2));
+        assert_eq!(results.failures[1].name, "Bail out!");
+        assert_eq!(results.failures[1].message, "database down");
+    }
+
+    #[test]
+    fn ignores_ok_lines_without_a_tap_header() {
+        assert!(parse_tap(["ok so this is log output", "ok 1 - looks like a test"]).is_none());
+        assert!(parse_tap(["1..0"]).is_none());
+        assert!(parse_tap(["1..2", "okay", "ok1"]).is_none());
+    }
+
+    #[test]
+    fn merge_adds_counts_and_caps_details() {
+        let mut results = parse_junit(TESTSUITES, "a.xml").unwrap().unwrap();
+        let mut other = TestResults::default();
+        other.record(Outcome::Failed, None, || TestFailure {
+            details: "x".repeat(MAX_FAILURE_DETAILS_CHARS + 10),
+            ..TestFailure::default()
+        });
+        results.merge(other);
+        assert_eq!(results.failed, 3);
+        assert_eq!(results.duration_ms, Some(1875));
+        assert!(results.failures[2].details.ends_with("\n[truncated]"));
+    }
+}
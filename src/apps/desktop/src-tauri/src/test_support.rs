@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A path under the temp dir no other test or test run uses. Nothing is created.
+pub(crate) fn scratch_path(prefix: &str, name: &str) -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    std::env::temp_dir().join(format!("{prefix}-{name}-{}-{nanos}", std::process::id()))
+}
+
+/// A request received by a stand-in HTTP server. Header names are lowercased.
+pub(crate) struct ReceivedRequest {
+    pub(crate) request_line: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    pub(crate) fn form(&self) -> HashMap<String, String> {
+        url::form_urlencoded::parse(&self.body).into_owned().collect()
+    }
+}
+
+pub(crate) fn read_request(stream: &TcpStream) -> ReceivedRequest {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    let length = headers.get("content-length").map_or(0, |value| value.parse().unwrap());
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).unwrap();
+    ReceivedRequest {
+        request_line,
+        headers,
+        body,
+    }
+}
+
+pub(crate) fn respond(mut stream: &TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).unwrap();
+}
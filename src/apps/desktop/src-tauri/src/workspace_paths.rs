@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub(crate) fn ensure_safe_relative_path(relative_path: &str) -> Result<PathBuf, String> {
+    let rel = Path::new(relative_path);
+    if rel.is_absolute() {
+        return Err("Relative path expected.".to_string());
+    }
+
+    for component in rel.components() {
+        if matches!(component, Component::ParentDir | Component::RootDir | Component::Prefix(_)) {
+            return Err("Path traversal is not allowed.".to_string());
+        }
+    }
+
+    Ok(rel.to_path_buf())
+}
+
+/// Resolves `relative_path` under the canonical `workspace_root` one component at a
+/// time, following symlinks and NTFS junctions as the OS would. Every link has to land
+/// inside the workspace or one of `allowed_roots` (also canonical); anything else,
+/// including links that point nowhere, is refused. Components that do not exist yet
+/// are appended as-is, since nothing below them can be a link.
+pub(crate) fn resolve_within(
+    workspace_root: &Path,
+    relative_path: &str,
+    allowed_roots: &[PathBuf],
+) -> Result<PathBuf, String> {
+    let rel = ensure_safe_relative_path(relative_path)?;
+    let permitted = |path: &Path| path.starts_with(workspace_root) || allowed_roots.iter().any(|root| path.starts_with(root));
+
+    let mut current = workspace_root.to_path_buf();
+    let mut exists = true;
+    for component in rel.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        current.push(name);
+        if !exists {
+            continue;
+        }
+
+        match fs::symlink_metadata(&current) {
+            Ok(_) => {
+                let resolved = fs::canonicalize(&current)
+                    .map_err(|_| format!("Target path goes through a broken link: {relative_path}"))?;
+                if !permitted(&resolved) {
+                    return Err(format!("Target path escapes the workspace through a link: {relative_path}"));
+                }
+                current = resolved;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => exists = false,
+            Err(err) => return Err(format!("Failed to inspect {relative_path}: {err}")),
+        }
+    }
+
+    Ok(current)
+}
+
+/// Canonical forms of the configured external roots. Roots that no longer exist are
+/// skipped rather than failing every file operation.
+pub(crate) fn canonical_allowed_roots(roots: &[String]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .filter_map(|root| fs::canonicalize(root.trim()).ok())
+        .filter(|root| root.is_dir())
+        .collect()
+}
+
+pub(crate) fn validate_allowed_root(root: &str) -> Result<(), String> {
+    let path = Path::new(root.trim());
+    if !path.is_absolute() {
+        return Err(format!("Allowed external root must be an absolute path: {root}"));
+    }
+    let canonical = fs::canonicalize(path).map_err(|err| format!("Invalid allowed external root {root}: {err}"))?;
+    if !canonical.is_dir() {
+        return Err(format!("Allowed external root must be a directory: {root}"));
+    }
+    if canonical.parent().is_none() {
+        return Err(format!("Allowed external root cannot be a filesystem root: {root}"));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_path;
+    use std::os::unix::fs::symlink;
+
+    /// A fresh directory holding a `workspace` and an `outside` sibling.
+    struct Fixture {
+        base: PathBuf,
+        workspace: PathBuf,
+        outside: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let base = scratch_path("workspace-paths", name);
+            fs::create_dir_all(base.join("workspace/src")).unwrap();
+            fs::create_dir_all(base.join("outside")).unwrap();
+            fs::write(base.join("workspace/src/main.rs"), "fn main() {}").unwrap();
+            fs::write(base.join("outside/secret.txt"), "secret").unwrap();
+            let base = fs::canonicalize(base).unwrap();
+            Self {
+                workspace: base.join("workspace"),
+                outside: base.join("outside"),
+                base,
+            }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[test]
+    fn resolves_plain_paths_inside_workspace() {
+        let fixture = Fixture::new("plain");
+        let resolved = resolve_within(&fixture.workspace, "src/main.rs", &[]).unwrap();
+        assert_eq!(resolved, fixture.workspace.join("src/main.rs"));
+        let missing = resolve_within(&fixture.workspace, "src/new/file.rs", &[]).unwrap();
+        assert_eq!(missing, fixture.workspace.join("src/new/file.rs"));
+    }
+
+    #[test]
+    fn rejects_traversal_and_absolute_paths() {
+        let fixture = Fixture::new("traversal");
+        assert!(resolve_within(&fixture.workspace, "../outside/secret.txt", &[]).is_err());
+        assert!(resolve_within(&fixture.workspace, "/etc/passwd", &[]).is_err());
+    }
+
+    #[test]
+    fn follows_links_that_stay_inside() {
+        let fixture = Fixture::new("inside-link");
+        symlink(fixture.workspace.join("src"), fixture.workspace.join("code")).unwrap();
+        symlink(fixture.workspace.join("src/main.rs"), fixture.workspace.join("entry.rs")).unwrap();
+        assert_eq!(
+            resolve_within(&fixture.workspace, "code/main.rs", &[]).unwrap(),
+            fixture.workspace.join("src/main.rs")
+        );
+        assert_eq!(
+            resolve_within(&fixture.workspace, "entry.rs", &[]).unwrap(),
+            fixture.workspace.join("src/main.rs")
+        );
+    }
+
+    #[test]
+    fn refuses_directory_link_escaping_workspace() {
+        let fixture = Fixture::new("dir-escape");
+        symlink(&fixture.outside, fixture.workspace.join("escape")).unwrap();
+        let err = resolve_within(&fixture.workspace, "escape/secret.txt", &[]).unwrap_err();
+        assert!(err.contains("escapes the workspace"), "{err}");
+        // Creating a new file below the link must be refused as well.
+        assert!(resolve_within(&fixture.workspace, "escape/new.txt", &[]).is_err());
+    }
+
+    #[test]
+    fn refuses_file_link_escaping_workspace() {
+        let fixture = Fixture::new("file-escape");
+        symlink(fixture.outside.join("secret.txt"), fixture.workspace.join("notes.txt")).unwrap();
+        assert!(resolve_within(&fixture.workspace, "notes.txt", &[]).is_err());
+    }
+
+    #[test]
+    fn refuses_chained_links_that_end_outside() {
+        let fixture = Fixture::new("chain");
+        symlink(&fixture.outside, fixture.workspace.join("hop2")).unwrap();
+        symlink(fixture.workspace.join("hop2"), fixture.workspace.join("hop1")).unwrap();
+        assert!(resolve_within(&fixture.workspace, "hop1/secret.txt", &[]).is_err());
+    }
+
+    #[test]
+    fn refuses_relative_link_climbing_out() {
+        let fixture = Fixture::new("relative");
+        symlink("../../outside", fixture.workspace.join("src/up")).unwrap();
+        assert!(resolve_within(&fixture.workspace, "src/up/secret.txt", &[]).is_err());
+    }
+
+    #[test]
+    fn refuses_broken_links() {
+        let fixture = Fixture::new("broken");
+        symlink(fixture.outside.join("missing.txt"), fixture.workspace.join("dangling.txt")).unwrap();
+        let err = resolve_within(&fixture.workspace, "dangling.txt", &[]).unwrap_err();
+        assert!(err.contains("broken link"), "{err}");
+    }
+
+    #[test]
+    fn allows_links_into_configured_external_roots() {
+        let fixture = Fixture::new("allowed");
+        symlink(&fixture.outside, fixture.workspace.join("shared")).unwrap();
+        let allowed = canonical_allowed_roots(&[fixture.outside.to_string_lossy().to_string()]);
+        assert_eq!(
+            resolve_within(&fixture.workspace, "shared/secret.txt", &allowed).unwrap(),
+            fixture.outside.join("secret.txt")
+        );
+    }
+
+    #[test]
+    fn validates_allowed_roots() {
+        let fixture = Fixture::new("validate");
+        assert!(validate_allowed_root(&fixture.outside.to_string_lossy()).is_ok());
+        assert!(validate_allowed_root("relative/dir").is_err());
+        assert!(validate_allowed_root("/").is_err());
+        assert!(validate_allowed_root(&fixture.outside.join("secret.txt").to_string_lossy()).is_err());
+    }
+}
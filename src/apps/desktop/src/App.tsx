@@ -1,26 +1,34 @@
 import { useEffect, useMemo, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { openUrl } from "@tauri-apps/plugin-opener";
 import { FolderOpen, MessageSquarePlus, Settings } from "lucide-react";
 import { Button } from "./components/ui/button";
 import { Dialog, DialogClose, DialogContent, DialogTrigger } from "./components/ui/dialog";
 import { Input } from "./components/ui/input";
 import { Textarea } from "./components/ui/textarea";
 import { useAppStore } from "./state/app-store";
-import type { AppSettingsRecord, SkillRecord } from "./features/mvp/types";
-import type { AuthSession, OAuthConfig } from "./modules/auth/types";
+import type {
+  AppSettingsRecord,
+  ChatDeltaEvent,
+  PermissionMode,
+  ProjectTrust,
+  SkillRecord,
+} from "./features/mvp/types";
+import type { AuthStateEvent, DeviceLoginPrompt, OAuthConfig, OAuthFlow } from "./modules/auth/types";
 import { OAuthService } from "./modules/auth/oauth-service";
 import {
   SecureTokenStore,
   clearApiKey,
-  loadApiKey,
+  hasApiKey,
   saveApiKey,
 } from "./modules/auth/token-store";
-import { requestAssistantReply } from "./modules/session/codex-client";
-import { addThreadMessage } from "./features/mvp/api";
+import { addThreadMessage, sendChatMessage } from "./features/mvp/api";
 
 type AuthMethod = "oauth" | "api_key";
 
 interface AuthSettings {
   method: AuthMethod;
+  oauthFlow: OAuthFlow;
   oauth: OAuthConfig;
   apiModel: string;
   apiBaseUrl: string;
@@ -35,12 +43,15 @@ function defaultOAuthConfig(): OAuthConfig {
     tokenUrl: import.meta.env.VITE_OAUTH_TOKEN_URL ?? "",
     redirectUri: import.meta.env.VITE_OAUTH_REDIRECT_URI ?? "http://127.0.0.1:4815/callback",
     scope: import.meta.env.VITE_OAUTH_SCOPE ?? "openid profile offline_access",
+    revocationUrl: import.meta.env.VITE_OAUTH_REVOCATION_URL ?? "",
+    deviceAuthorizationUrl: import.meta.env.VITE_OAUTH_DEVICE_AUTHORIZATION_URL ?? "",
   };
 }
 
 function loadAuthSettings(): AuthSettings {
   const defaults: AuthSettings = {
     method: "oauth",
+    oauthFlow: "pkce",
     oauth: defaultOAuthConfig(),
     apiModel: "gpt-4.1-mini",
     apiBaseUrl: "https://api.openai.com/v1",
@@ -53,12 +64,15 @@ function loadAuthSettings(): AuthSettings {
     const parsed = JSON.parse(raw) as Partial<AuthSettings> & { oauth?: Partial<OAuthConfig> };
     return {
       method: parsed.method === "api_key" ? "api_key" : "oauth",
+      oauthFlow: parsed.oauthFlow === "device" ? "device" : "pkce",
       oauth: {
         clientId: parsed.oauth?.clientId ?? defaults.oauth.clientId,
         authorizeUrl: parsed.oauth?.authorizeUrl ?? defaults.oauth.authorizeUrl,
         tokenUrl: parsed.oauth?.tokenUrl ?? defaults.oauth.tokenUrl,
         redirectUri: parsed.oauth?.redirectUri ?? defaults.oauth.redirectUri,
         scope: parsed.oauth?.scope ?? defaults.oauth.scope,
+        revocationUrl: parsed.oauth?.revocationUrl ?? defaults.oauth.revocationUrl,
+        deviceAuthorizationUrl: parsed.oauth?.deviceAuthorizationUrl ?? defaults.oauth.deviceAuthorizationUrl,
       },
       apiModel: parsed.apiModel ?? defaults.apiModel,
       apiBaseUrl: parsed.apiBaseUrl ?? defaults.apiBaseUrl,
@@ -68,12 +82,31 @@ function loadAuthSettings(): AuthSettings {
   }
 }
 
-function missingOAuthFields(config: OAuthConfig): string[] {
+function describeAuthState(event: AuthStateEvent): string {
+  switch (event.state) {
+    case "active":
+      return "Connected with OAuth";
+    case "signing_in":
+      return "Waiting for browser login...";
+    case "refreshing":
+      return event.error ? `Refreshing OAuth session (retrying: ${event.error})` : "Refreshing OAuth session...";
+    case "expired":
+      return event.error ? `OAuth session expired: ${event.error}` : "OAuth session expired";
+    default:
+      return event.error ?? "OAuth session not active";
+  }
+}
+
+function missingOAuthFields(config: OAuthConfig, flow: OAuthFlow): string[] {
   const missing: string[] = [];
   if (!config.clientId.trim()) missing.push("Client ID");
-  if (!config.authorizeUrl.trim()) missing.push("Authorize URL");
   if (!config.tokenUrl.trim()) missing.push("Token URL");
-  if (!config.redirectUri.trim()) missing.push("Redirect URI");
+  if (flow === "device") {
+    if (!config.deviceAuthorizationUrl?.trim()) missing.push("Device authorization URL");
+  } else {
+    if (!config.authorizeUrl.trim()) missing.push("Authorize URL");
+    if (!config.redirectUri.trim()) missing.push("Redirect URI");
+  }
   return missing;
 }
 
@@ -87,6 +120,7 @@ function App() {
     tasks,
     selectedTaskId,
     taskLogs,
+    taskProgress,
     skills,
     settings,
     statusText,
@@ -106,21 +140,24 @@ function App() {
     saveSettings,
     createThreadWorktree,
     setThreadPermissionMode,
+    setProjectTrust,
   } = useAppStore();
 
   const [projectPathInput, setProjectPathInput] = useState("");
   const [threadNameInput, setThreadNameInput] = useState("");
   const [chatInput, setChatInput] = useState("");
+  const [streamingReply, setStreamingReply] = useState<ChatDeltaEvent | null>(null);
   const [taskCommandInput, setTaskCommandInput] = useState("");
   const [worktreeBranchInput, setWorktreeBranchInput] = useState("");
   const [worktreePathInput, setWorktreePathInput] = useState("");
 
   const [authSettings, setAuthSettings] = useState<AuthSettings>(() => loadAuthSettings());
   const [authDraft, setAuthDraft] = useState<AuthSettings>(authSettings);
-  const [apiKey, setApiKey] = useState("");
+  const [apiKeySaved, setApiKeySaved] = useState(false);
   const [apiKeyDraft, setApiKeyDraft] = useState("");
   const [authStatus, setAuthStatus] = useState("Not connected");
-  const [authSession, setAuthSession] = useState<AuthSession | null>(null);
+  const [oauthPending, setOauthPending] = useState(false);
+  const [devicePrompt, setDevicePrompt] = useState<DeviceLoginPrompt | null>(null);
 
   const [settingsDraft, setSettingsDraft] = useState<AppSettingsRecord>(settings);
   const [skillDraft, setSkillDraft] = useState<Pick<SkillRecord, "name" | "systemPrompt" | "checklist">>({
@@ -152,11 +189,11 @@ function App() {
 
   useEffect(() => {
     async function bootstrapAuth() {
-      const key = await loadApiKey();
-      setApiKey(key ?? "");
+      const saved = await hasApiKey();
+      setApiKeySaved(saved);
 
       if (authSettings.method === "api_key") {
-        setAuthStatus(key ? "Connected with API key" : "API key not configured");
+        setAuthStatus(saved ? "Connected with API key" : "API key not configured");
         return;
       }
 
@@ -166,9 +203,7 @@ function App() {
       }
 
       try {
-        const restored = await authService.refreshSession();
-        setAuthSession(restored);
-        setAuthStatus("Connected with OAuth");
+        setAuthStatus(describeAuthState(await authService.restoreSession()));
       } catch {
         setAuthStatus("OAuth session not active");
       }
@@ -177,7 +212,34 @@ function App() {
     void bootstrapAuth();
   }, [authService, authSettings.method]);
 
+  useEffect(() => {
+    if (authSettings.method !== "oauth") return;
+    const unlisten = listen<AuthStateEvent>("auth:state", (event) => {
+      setAuthStatus(describeAuthState(event.payload));
+    });
+    const unlistenDevice = listen<DeviceLoginPrompt>("auth:device-code", (event) => {
+      setDevicePrompt(event.payload);
+    });
+    return () => {
+      void unlisten.then((fn) => fn());
+      void unlistenDevice.then((fn) => fn());
+    };
+  }, [authSettings.method]);
+
+  useEffect(() => {
+    const unlisten = listen<ChatDeltaEvent>("chat:delta", (event) => {
+      const { threadId, delta } = event.payload;
+      setStreamingReply((prev) =>
+        prev?.threadId === threadId ? { threadId, delta: prev.delta + delta } : { threadId, delta },
+      );
+    });
+    return () => {
+      void unlisten.then((fn) => fn());
+    };
+  }, []);
+
   const activeThread = threads.find((t) => t.id === activeThreadId);
+  const activeProject = projects.find((p) => p.id === activeProjectId);
 
   async function onAddProject() {
     if (!projectPathInput.trim()) return;
@@ -199,30 +261,20 @@ function App() {
     await sendUserMessage(content);
 
     try {
-      const assistant = await requestAssistantReply(
-        messages.map((m) => ({
-          id: m.id,
-          role: m.role as "user" | "assistant" | "system",
-          content: m.content,
-          createdAt: m.createdAt,
-        })),
-        content,
-        {
-          method: authSettings.method,
-          accessToken: authSession?.accessToken,
-          apiKey,
-          model: authSettings.apiModel,
-          apiBaseUrl: authSettings.apiBaseUrl,
-        },
-      );
-      await addThreadMessage(activeThreadId, "assistant", assistant);
-      await selectThread(activeThreadId);
+      // The backend resolves the thread's credentials and stores the reply; the
+      // settings here only fill what the thread's project leaves empty.
+      await sendChatMessage(activeThreadId, {
+        authMethod: authSettings.method,
+        model: authSettings.apiModel,
+      });
     } catch (error) {
       await addThreadMessage(
         activeThreadId,
         "system",
-        error instanceof Error ? error.message : "Assistant request failed",
+        typeof error === "string" ? error : error instanceof Error ? error.message : "Assistant request failed",
       );
+    } finally {
+      setStreamingReply(null);
       await selectThread(activeThreadId);
     }
   }
@@ -233,37 +285,68 @@ function App() {
     setTaskCommandInput("");
   }
 
+  async function onClearApiKey() {
+    await clearApiKey();
+    setApiKeySaved(false);
+    setAuthStatus("API key cleared");
+  }
+
   async function onSaveAuthSettings() {
+    // The backend only sends credentials to a base URL saved in the app settings.
+    await saveSettings({ ...settings, apiBaseUrl: authDraft.apiBaseUrl.trim() || undefined });
     localStorage.setItem(AUTH_SETTINGS_KEY, JSON.stringify(authDraft));
     setAuthSettings(authDraft);
 
     if (authDraft.method === "api_key") {
+      // The saved key is never shown, so an empty field keeps it.
       if (apiKeyDraft.trim()) {
         await saveApiKey(apiKeyDraft.trim());
-        setApiKey(apiKeyDraft.trim());
-        setAuthSession(null);
-        setAuthStatus("Connected with API key");
-      } else {
-        await clearApiKey();
-        setApiKey("");
-        setAuthStatus("API key cleared");
+        setApiKeySaved(true);
+        setApiKeyDraft("");
       }
+      setAuthStatus(apiKeyDraft.trim() || apiKeySaved ? "Connected with API key" : "API key not configured");
       return;
     }
 
-    const missing = missingOAuthFields(authDraft.oauth);
+    const missing = missingOAuthFields(authDraft.oauth, authDraft.oauthFlow);
     if (missing.length > 0) {
       setAuthStatus(`Missing OAuth fields: ${missing.join(", ")}`);
       return;
     }
 
     try {
+      setOauthPending(true);
       const oauth = new OAuthService(authDraft.oauth, new SecureTokenStore());
-      const session = await oauth.beginLoginWithLoopback();
-      setAuthSession(session);
+      if (authDraft.oauthFlow === "device") {
+        setAuthStatus("Requesting device code...");
+        await oauth.beginDeviceLogin();
+      } else {
+        setAuthStatus("Waiting for browser login...");
+        await oauth.beginLoginWithLoopback();
+      }
       setAuthStatus("Connected with OAuth");
     } catch (error) {
       setAuthStatus(error instanceof Error ? error.message : "OAuth connection failed");
+    } finally {
+      setOauthPending(false);
+      setDevicePrompt(null);
+    }
+  }
+
+  async function onCancelOAuthLogin() {
+    await authService.cancelLogin();
+  }
+
+  async function onSignOut() {
+    try {
+      const report = await authService.logout();
+      setAuthStatus(
+        report.failures.length > 0
+          ? `Signed out with errors: ${report.failures.map((f) => `${f.step}: ${f.error}`).join("; ")}`
+          : "Signed out",
+      );
+    } catch (error) {
+      setAuthStatus(error instanceof Error ? error.message : "Sign out failed");
     }
   }
 
@@ -290,7 +373,7 @@ function App() {
     await saveSettings(settingsDraft);
   }
 
-  const missingDraft = missingOAuthFields(authDraft.oauth);
+  const missingDraft = missingOAuthFields(authDraft.oauth, authDraft.oauthFlow);
 
   return (
     <div className="h-full p-3">
@@ -370,7 +453,7 @@ function App() {
                 className="mt-2 justify-start px-2 text-zinc-700"
                 onClick={() => {
                   setAuthDraft(authSettings);
-                  setApiKeyDraft(apiKey);
+                  setApiKeyDraft("");
                 }}
               >
                 <Settings className="h-4 w-4" />
@@ -401,10 +484,16 @@ function App() {
                   {authDraft.method === "api_key" ? (
                     <div className="space-y-2">
                       <Input
-                        placeholder="OpenAI API key"
+                        type="password"
+                        placeholder={apiKeySaved ? "API key saved; enter a new one to replace it" : "OpenAI API key"}
                         value={apiKeyDraft}
                         onChange={(e) => setApiKeyDraft(e.currentTarget.value)}
                       />
+                      {apiKeySaved && (
+                        <Button variant="outline" onClick={() => void onClearApiKey()}>
+                          Remove saved API key
+                        </Button>
+                      )}
                       <Input
                         placeholder="Model"
                         value={authDraft.apiModel}
@@ -422,6 +511,16 @@ function App() {
                     </div>
                   ) : (
                     <div className="space-y-2">
+                      <select
+                        className="h-10 w-full rounded-md border border-zinc-300 bg-white px-3 text-sm"
+                        value={authDraft.oauthFlow}
+                        onChange={(e) =>
+                          setAuthDraft((prev) => ({ ...prev, oauthFlow: e.currentTarget.value as OAuthFlow }))
+                        }
+                      >
+                        <option value="pkce">Browser login (PKCE)</option>
+                        <option value="device">Device code</option>
+                      </select>
                       <Input
                         placeholder="OAuth Client ID"
                         value={authDraft.oauth.clientId}
@@ -472,13 +571,67 @@ function App() {
                           }))
                         }
                       />
+                      <Input
+                        placeholder="Revocation URL (optional)"
+                        value={authDraft.oauth.revocationUrl ?? ""}
+                        onChange={(e) =>
+                          setAuthDraft((prev) => ({
+                            ...prev,
+                            oauth: { ...prev.oauth, revocationUrl: e.currentTarget.value },
+                          }))
+                        }
+                      />
+                      {authDraft.oauthFlow === "device" && (
+                        <Input
+                          placeholder="Device authorization URL"
+                          value={authDraft.oauth.deviceAuthorizationUrl ?? ""}
+                          onChange={(e) =>
+                            setAuthDraft((prev) => ({
+                              ...prev,
+                              oauth: { ...prev.oauth, deviceAuthorizationUrl: e.currentTarget.value },
+                            }))
+                          }
+                        />
+                      )}
+                      {devicePrompt && (
+                        <div className="rounded-md bg-zinc-100 p-2 text-sm">
+                          <p>
+                            Open{" "}
+                            <button
+                              type="button"
+                              className="underline"
+                              onClick={() =>
+                                void openUrl(devicePrompt.verificationUriComplete ?? devicePrompt.verificationUri)
+                              }
+                            >
+                              {devicePrompt.verificationUri}
+                            </button>{" "}
+                            and enter the code:
+                          </p>
+                          <p className="font-mono text-lg font-semibold tracking-widest">{devicePrompt.userCode}</p>
+                        </div>
+                      )}
                       {missingDraft.length > 0 && (
                         <p className="text-xs text-red-600">Missing: {missingDraft.join(", ")}</p>
                       )}
                     </div>
                   )}
 
-                  <Button onClick={onSaveAuthSettings}>Save auth</Button>
+                  <div className="flex gap-2">
+                    <Button onClick={onSaveAuthSettings} disabled={oauthPending}>
+                      Save auth
+                    </Button>
+                    {oauthPending && (
+                      <Button variant="outline" onClick={onCancelOAuthLogin}>
+                        Cancel login
+                      </Button>
+                    )}
+                    {authSettings.method === "oauth" && !oauthPending && (
+                      <Button variant="outline" onClick={onSignOut}>
+                        Sign out
+                      </Button>
+                    )}
+                  </div>
                 </div>
 
                 <div className="space-y-3 rounded-md border border-zinc-200 p-3">
@@ -595,13 +748,32 @@ function App() {
                   className="h-7 rounded-md border border-zinc-300 bg-white px-2 text-xs"
                   value={activeThread?.permissionMode ?? "normal"}
                   onChange={(e) =>
-                    setThreadPermissionMode(e.currentTarget.value as "safe" | "normal" | "danger-confirm")
+                    setThreadPermissionMode(e.currentTarget.value as PermissionMode)
                   }
                 >
                   <option value="safe">safe</option>
                   <option value="normal">normal</option>
                   <option value="danger-confirm">danger-confirm</option>
+                  <option value="autonomous">autonomous</option>
                 </select>
+                {activeProject && (
+                  <>
+                    <span className="ml-3 text-zinc-500">Project trust</span>
+                    <select
+                      className="h-7 rounded-md border border-zinc-300 bg-white px-2 text-xs"
+                      value={activeProject.trust}
+                      onChange={(e) =>
+                        setProjectTrust(activeProject.id, e.currentTarget.value as ProjectTrust)
+                      }
+                    >
+                      <option value="untrusted" disabled>
+                        untrusted
+                      </option>
+                      <option value="trusted">trusted</option>
+                      <option value="restricted">restricted</option>
+                    </select>
+                  </>
+                )}
               </div>
             </header>
 
@@ -613,6 +785,12 @@ function App() {
                     <pre className="whitespace-pre-wrap text-sm leading-6">{message.content}</pre>
                   </article>
                 ))}
+                {streamingReply && streamingReply.threadId === activeThreadId && (
+                  <article className="rounded-lg border border-zinc-200 bg-zinc-50 p-3">
+                    <p className="mb-1 text-[10px] uppercase tracking-wide text-zinc-500">assistant</p>
+                    <pre className="whitespace-pre-wrap text-sm leading-6">{streamingReply.delta}</pre>
+                  </article>
+                )}
               </div>
             </div>
 
@@ -679,6 +857,11 @@ function App() {
                     {line.stream === "stderr" ? "[err]" : "[out]"} {line.line}
                   </pre>
                 ))}
+                {Object.entries(taskProgress).map(([stream, line]) => (
+                  <pre key={`progress-${stream}`} className="mb-1 whitespace-pre-wrap text-xs text-zinc-500">
+                    {stream === "stderr" ? "[err]" : "[out]"} {line}
+                  </pre>
+                ))}
               </div>
               <Input
                 value={worktreeBranchInput}
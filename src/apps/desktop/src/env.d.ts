@@ -6,7 +6,6 @@ interface ImportMetaEnv {
   readonly VITE_OAUTH_TOKEN_URL?: string;
   readonly VITE_OAUTH_REDIRECT_URI?: string;
   readonly VITE_OAUTH_SCOPE?: string;
-  readonly VITE_CODEX_CHAT_URL?: string;
 }
 
 interface ImportMeta {
@@ -1,11 +1,29 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
   AppSettingsRecord,
+  AuditVerification,
+  ChatDefaults,
+  CommandPolicyRule,
+  CommandPolicyRuleInput,
+  CredentialProfile,
+  CredentialProfileInput,
+  DataStoreRecovery,
+  DataStoreStatus,
   GitStatusResult,
+  ModelConnection,
+  PermissionMode,
+  PolicyEvaluation,
+  ProfileSecretKind,
   ProjectRecord,
+  ProjectTrust,
+  SecretBackend,
+  SecretStoreStatus,
   SkillRecord,
+  TaskArtifactRecord,
   TaskLogRecord,
   TaskRecord,
+  TaskStats,
+  TestResults,
   ThreadMessage,
   ThreadRecord,
   WorktreeResult,
@@ -23,6 +41,10 @@ export function touchProject(projectId: string) {
   return invoke<void>("touch_project", { projectId });
 }
 
+export function setProjectTrust(projectId: string, trust: ProjectTrust) {
+  return invoke<ProjectRecord>("set_project_trust", { projectId, trust });
+}
+
 export function createThread(projectId: string, name: string, description?: string, skillId?: string) {
   return invoke<ThreadRecord>("create_thread", { projectId, name, description, skillId });
 }
@@ -44,15 +66,37 @@ export function runTask(
   command: string,
   cwd: string,
   shell?: string,
-  confirmDestructive?: boolean,
+  artifacts?: string[],
+  disableNetwork?: boolean,
+  approvalTimeoutSecs?: number,
 ) {
-  return invoke<TaskRecord>("run_task", { threadId, command, cwd, shell, confirmDestructive });
+  return invoke<TaskRecord>("run_task", {
+    threadId,
+    command,
+    cwd,
+    shell,
+    artifacts,
+    disableNetwork,
+    approvalTimeoutSecs,
+  });
 }
 
 export function cancelTask(taskId: string) {
   return invoke<void>("cancel_task", { taskId });
 }
 
+export function approveTask(taskId: string, approver?: string, note?: string) {
+  return invoke<TaskRecord>("approve_task", { taskId, approver, note });
+}
+
+export function rejectTask(taskId: string, approver?: string, reason?: string) {
+  return invoke<TaskRecord>("reject_task", { taskId, approver, reason });
+}
+
+export function listPendingApprovals() {
+  return invoke<TaskRecord[]>("list_pending_approvals");
+}
+
 export function listTasks(threadId: string) {
   return invoke<TaskRecord[]>("list_tasks", { threadId });
 }
@@ -61,6 +105,45 @@ export function listTaskLogs(taskId: string) {
   return invoke<TaskLogRecord[]>("list_task_logs", { taskId });
 }
 
+export function listTaskArtifacts(taskId: string) {
+  return invoke<TaskArtifactRecord[]>("list_task_artifacts", { taskId });
+}
+
+export function openTaskArtifact(artifactId: string) {
+  return invoke<void>("open_task_artifact", { artifactId });
+}
+
+export function getTaskTestResults(taskId: string) {
+  return invoke<TestResults | null>("get_task_test_results", { taskId });
+}
+
+export function getTaskStats(taskId: string) {
+  return invoke<TaskStats>("get_task_stats", { taskId });
+}
+
+export function listCommandPolicyRules() {
+  return invoke<CommandPolicyRule[]>("list_command_policy_rules");
+}
+
+export function createCommandPolicyRule(rule: CommandPolicyRuleInput) {
+  return invoke<CommandPolicyRule>("create_command_policy_rule", { rule });
+}
+
+export function updateCommandPolicyRule(ruleId: string, rule: CommandPolicyRuleInput) {
+  return invoke<CommandPolicyRule>("update_command_policy_rule", { ruleId, rule });
+}
+
+export function deleteCommandPolicyRule(ruleId: string) {
+  return invoke<void>("delete_command_policy_rule", { ruleId });
+}
+
+export function evaluateCommandPolicy(
+  command: string,
+  options?: { shell?: string; threadId?: string; projectId?: string },
+) {
+  return invoke<PolicyEvaluation>("evaluate_command_policy", { command, ...options });
+}
+
 export function setMaxParallelTasks(value: number) {
   return invoke<void>("set_max_parallel_tasks", { value });
 }
@@ -124,7 +207,95 @@ export function gitDiff(path: string, file?: string) {
 
 export function setThreadPermission(
   threadId: string,
-  permissionMode: "safe" | "normal" | "danger-confirm",
+  permissionMode: PermissionMode,
 ) {
   return invoke<ThreadRecord>("set_thread_permission", { threadId, permissionMode });
 }
+
+export function setThreadSandbox(threadId: string, enabled: boolean) {
+  return invoke<ThreadRecord>("set_thread_sandbox", { threadId, enabled });
+}
+
+export function setThreadNetwork(threadId: string, disabled: boolean) {
+  return invoke<ThreadRecord>("set_thread_network", { threadId, disabled });
+}
+
+export function verifyAuditLog() {
+  return invoke<AuditVerification>("verify_audit_log");
+}
+
+export function exportAuditLog(destinationPath: string) {
+  return invoke<AuditVerification>("export_audit_log", { destinationPath });
+}
+
+export function getDataStoreStatus() {
+  return invoke<DataStoreStatus>("get_data_store_status");
+}
+
+export function enableDataStoreEncryption() {
+  return invoke<DataStoreRecovery>("enable_data_store_encryption");
+}
+
+export function rotateDataStoreKey() {
+  return invoke<DataStoreRecovery>("rotate_data_store_key");
+}
+
+export function disableDataStoreEncryption() {
+  return invoke<void>("disable_data_store_encryption");
+}
+
+export function recoverDataStore(recoveryKey: string) {
+  return invoke<DataStoreStatus>("recover_data_store", { recoveryKey });
+}
+
+export function getSecretStoreStatus() {
+  return invoke<SecretStoreStatus>("get_secret_store_status");
+}
+
+export function unlockSecretStore(passphrase: string) {
+  return invoke<SecretStoreStatus>("unlock_secret_store", { passphrase });
+}
+
+export function setSecretBackend(backend: SecretBackend, passphrase?: string) {
+  return invoke<SecretStoreStatus>("set_secret_backend", { backend, passphrase });
+}
+
+export function listCredentialProfiles() {
+  return invoke<CredentialProfile[]>("list_credential_profiles");
+}
+
+export function createCredentialProfile(profile: CredentialProfileInput) {
+  return invoke<CredentialProfile>("create_credential_profile", { profile });
+}
+
+export function updateCredentialProfile(profileId: string, profile: CredentialProfileInput) {
+  return invoke<CredentialProfile>("update_credential_profile", { profileId, profile });
+}
+
+export function deleteCredentialProfile(profileId: string) {
+  return invoke<void>("delete_credential_profile", { profileId });
+}
+
+export function saveProfileSecret(profileId: string, kind: ProfileSecretKind, value: string) {
+  return invoke<void>("save_profile_secret", { profileId, kind, value });
+}
+
+export function clearProfileSecret(profileId: string, kind: ProfileSecretKind) {
+  return invoke<void>("clear_profile_secret", { profileId, kind });
+}
+
+export function setProjectCredentialProfile(projectId: string, profileId?: string) {
+  return invoke<ProjectRecord>("set_project_credential_profile", { projectId, profileId });
+}
+
+export function setProjectApiBaseUrl(projectId: string, apiBaseUrl?: string) {
+  return invoke<ProjectRecord>("set_project_api_base_url", { projectId, apiBaseUrl });
+}
+
+export function resolveThreadConnection(threadId: string) {
+  return invoke<ModelConnection>("resolve_thread_connection", { threadId });
+}
+
+export function sendChatMessage(threadId: string, defaults?: ChatDefaults) {
+  return invoke<ThreadMessage>("send_chat_message", { threadId, defaults });
+}
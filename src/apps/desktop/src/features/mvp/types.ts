@@ -1,9 +1,23 @@
+export type PermissionMode = "safe" | "normal" | "danger-confirm" | "autonomous";
+
+export type ProjectTrust = "untrusted" | "trusted" | "restricted";
+
 export interface ProjectRecord {
   id: string;
   name: string;
   path: string;
   lastAccessedAt: number;
   createdAt: number;
+  trust: ProjectTrust;
+  trustDecidedAt?: number;
+  credentialProfileId?: string;
+  apiBaseUrl?: string;
+}
+
+export interface ProjectTrustRequiredEvent {
+  projectId: string;
+  name: string;
+  path: string;
 }
 
 export interface ThreadRecord {
@@ -14,7 +28,9 @@ export interface ThreadRecord {
   skillId?: string;
   worktreePath?: string;
   worktreeBranch?: string;
-  permissionMode: "safe" | "normal" | "danger-confirm" | string;
+  permissionMode: PermissionMode | string;
+  sandboxEnabled: boolean;
+  networkDisabled: boolean;
   status: string;
   createdAt: number;
   updatedAt: number;
@@ -39,6 +55,80 @@ export interface TaskRecord {
   startedAt?: number;
   finishedAt?: number;
   exitCode?: number;
+  exitSignal?: number;
+  wallTimeMs?: number;
+  cpuTimeMs?: number;
+  peakMemoryBytes?: number;
+  artifactPatterns: string[];
+  testResults?: TestResults;
+  sandboxed: boolean;
+  sandboxViolations: SandboxViolation[];
+  networkDisabled: boolean;
+  sandboxRoot?: string;
+  approval?: TaskApproval;
+}
+
+export type TaskApprovalStatus = "pending" | "approved" | "rejected" | "expired" | "cancelled";
+
+export interface TaskApproval {
+  reason: string;
+  status: TaskApprovalStatus;
+  requestedAt: number;
+  expiresAt?: number;
+  decidedBy?: string;
+  decidedAt?: number;
+  note?: string;
+}
+
+export interface TaskApprovalRequestEvent {
+  taskId: string;
+  threadId: string;
+  command: string;
+  reason: string;
+  expiresAt?: number;
+}
+
+export interface TestFailure {
+  name: string;
+  suite?: string;
+  message: string;
+  details: string;
+  durationMs?: number;
+}
+
+export interface TestResults {
+  sources: string[];
+  passed: number;
+  failed: number;
+  skipped: number;
+  durationMs?: number;
+  failures: TestFailure[];
+}
+
+export interface TaskArtifactRecord {
+  id: string;
+  taskId: string;
+  relativePath: string;
+  storedPath: string;
+  size: number;
+  createdAt: number;
+}
+
+export interface TaskRunStats {
+  taskId: string;
+  status: string;
+  startedAt?: number;
+  exitCode?: number;
+  exitSignal?: number;
+  wallTimeMs?: number;
+  cpuTimeMs?: number;
+  peakMemoryBytes?: number;
+}
+
+export interface TaskStats {
+  command: string;
+  current: TaskRunStats;
+  previousRuns: TaskRunStats[];
 }
 
 export interface TaskLogRecord {
@@ -62,6 +152,18 @@ export interface TaskStatusEvent {
   exitCode?: number;
 }
 
+export interface SandboxViolation {
+  path?: string;
+  message: string;
+  createdAt: number;
+}
+
+export interface TaskSandboxViolationEvent {
+  taskId: string;
+  threadId: string;
+  violation: SandboxViolation;
+}
+
 export interface TaskLogEvent {
   taskId: string;
   threadId: string;
@@ -84,9 +186,145 @@ export interface AppSettingsRecord {
   defaultShell: string;
   defaultWorkspaceRoot: string;
   theme: string;
+  defaultPermissionMode: PermissionMode;
+  requireThreadContext: boolean;
+  allowedExternalRoots: string[];
+  redactionPatterns: string[];
+  apiBaseUrl?: string;
 }
 
 export interface WorktreeResult {
   branchName: string;
   worktreePath: string;
 }
+
+export type PolicyAction = "allow" | "confirm" | "deny";
+export type PolicyScope = "builtin" | "global" | "project" | "thread";
+export type PatternKind = "glob" | "regex";
+
+export interface CommandPolicyRule {
+  id: string;
+  scope: PolicyScope;
+  scopeId?: string;
+  action: PolicyAction;
+  kind: PatternKind;
+  program: string;
+  args: string[];
+  description: string;
+  enabled: boolean;
+  createdAt: number;
+}
+
+export interface CommandPolicyRuleInput {
+  scope: Exclude<PolicyScope, "builtin">;
+  scopeId?: string;
+  action: PolicyAction;
+  kind?: PatternKind;
+  program: string;
+  args?: string[];
+  description?: string;
+  enabled?: boolean;
+}
+
+export type ShellDialect = "posix" | "cmd" | "powershell";
+
+export interface InvocationVerdict {
+  program: string;
+  invokedAs?: string;
+  args: string[];
+  dialect: ShellDialect;
+  action: PolicyAction;
+  ruleId?: string;
+  ruleDescription?: string;
+}
+
+export interface Redirection {
+  operator: string;
+  target: string;
+}
+
+export interface PolicyEvaluation {
+  command: string;
+  dialect: ShellDialect;
+  action: PolicyAction;
+  parseError?: string;
+  unresolved: string[];
+  invocations: InvocationVerdict[];
+  redirections: Redirection[];
+  assignments: string[];
+}
+
+export interface AuditVerification {
+  valid: boolean;
+  entries: number;
+  headHash?: string;
+  brokenAtLine?: number;
+  error?: string;
+  anchoredSeq?: number;
+}
+
+export interface DataStoreStatus {
+  encrypted: boolean;
+  locked: boolean;
+  keyId?: string;
+  lockReason?: string;
+}
+
+export interface DataStoreRecovery {
+  keyId: string;
+  recoveryKey: string;
+}
+
+export type AuthMethod = "api_key" | "oauth";
+
+export interface CredentialProfile {
+  id: string;
+  name: string;
+  baseUrl: string;
+  authMethod: AuthMethod;
+  organization?: string;
+  defaultModel?: string;
+  createdAt: number;
+  updatedAt: number;
+}
+
+export interface CredentialProfileInput {
+  name: string;
+  baseUrl?: string;
+  authMethod?: AuthMethod;
+  organization?: string;
+  defaultModel?: string;
+}
+
+export type ProfileSecretKind = "api_key" | "refresh_token";
+
+export interface ModelConnection {
+  projectId: string;
+  profileId?: string;
+  profileName?: string;
+  authMethod?: AuthMethod;
+  baseUrl?: string;
+  organization?: string;
+  model?: string;
+}
+
+/**
+ * App-wide connection settings used where the thread's project leaves a field empty.
+ * The base URL is read from the saved app settings by the backend instead.
+ */
+export interface ChatDefaults {
+  authMethod?: AuthMethod;
+  model?: string;
+}
+
+export interface ChatDeltaEvent {
+  threadId: string;
+  delta: string;
+}
+
+export type SecretBackend = "keyring" | "file";
+
+export interface SecretStoreStatus {
+  backend: SecretBackend;
+  unlocked: boolean;
+}
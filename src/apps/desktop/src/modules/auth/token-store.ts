@@ -3,21 +3,13 @@ import { invoke } from "@tauri-apps/api/core";
 const FALLBACK_KEY = "codex.auth.refresh_token";
 const API_FALLBACK_KEY = "codex.auth.api_key";
 
+/** Writes and clears the refresh token. It is only ever read by the backend. */
 export interface TokenStore {
-  getRefreshToken(): Promise<string | null>;
   saveRefreshToken(refreshToken: string): Promise<void>;
   clear(): Promise<void>;
 }
 
 export class SecureTokenStore implements TokenStore {
-  async getRefreshToken(): Promise<string | null> {
-    try {
-      return await invoke<string | null>("load_refresh_token");
-    } catch {
-      return localStorage.getItem(FALLBACK_KEY);
-    }
-  }
-
   async saveRefreshToken(refreshToken: string): Promise<void> {
     try {
       await invoke("save_refresh_token", { refreshToken });
@@ -27,19 +19,22 @@ export class SecureTokenStore implements TokenStore {
   }
 
   async clear(): Promise<void> {
+    // A copy may be left in the fallback from a run where the keyring was unavailable.
+    localStorage.removeItem(FALLBACK_KEY);
     try {
       await invoke("clear_refresh_token");
     } catch {
-      localStorage.removeItem(FALLBACK_KEY);
+      // Only the fallback copy existed.
     }
   }
 }
 
-export async function loadApiKey(): Promise<string | null> {
+/** Whether an API key is saved. The key itself never comes back from the backend. */
+export async function hasApiKey(): Promise<boolean> {
   try {
-    return await invoke<string | null>("load_api_key");
+    return await invoke<boolean>("has_api_key");
   } catch {
-    return localStorage.getItem(API_FALLBACK_KEY);
+    return localStorage.getItem(API_FALLBACK_KEY) !== null;
   }
 }
 
@@ -1,16 +1,8 @@
-export interface AuthSession {
-  accessToken: string;
-  refreshToken?: string;
-  expiresAt: number;
+/** Session details the backend shares after an OAuth login; never the tokens. */
+export interface AuthSessionInfo {
   tokenType: string;
   scope?: string;
-}
-
-export interface PendingAuth {
-  codeVerifier: string;
-  state: string;
-  nonce: string;
-  createdAt: number;
+  expiresAt?: number;
 }
 
 export interface OAuthConfig {
@@ -19,12 +11,42 @@ export interface OAuthConfig {
   tokenUrl: string;
   redirectUri: string;
   scope: string;
+  /** Token revocation endpoint; logout skips revocation without one. */
+  revocationUrl?: string;
+  /** Device authorization endpoint, needed for the device code login. */
+  deviceAuthorizationUrl?: string;
+}
+
+/** How to sign in: browser redirect with PKCE, or a device code entered elsewhere. */
+export type OAuthFlow = "pkce" | "device";
+
+/** Payload of `auth:device-code`: what the user enters to approve a device login. */
+export interface DeviceLoginPrompt {
+  userCode: string;
+  verificationUri: string;
+  verificationUriComplete?: string;
+  expiresAt: number;
+}
+
+export type AuthState = "signed_out" | "signing_in" | "active" | "refreshing" | "expired";
+
+export interface LogoutFailure {
+  step: string;
+  error: string;
+}
+
+export interface LogoutReport {
+  revoked: string[];
+  revocationConfigured: boolean;
+  /** Credential profiles whose refresh token was deleted; their API keys are kept. */
+  clearedProfiles: string[];
+  failures: LogoutFailure[];
 }
 
-export interface TokenResponse {
-  access_token: string;
-  token_type: string;
-  expires_in: number;
-  refresh_token?: string;
+/** Payload of the backend's `auth:state` event. */
+export interface AuthStateEvent {
+  state: AuthState;
+  expiresAt?: number;
   scope?: string;
+  error?: string;
 }
@@ -4,6 +4,9 @@ import type { CommandResult } from "../common/types";
 export async function runWorkspaceCommand(
   workspacePath: string,
   command: string,
+  confirm?: boolean,
+  threadId?: string,
+  approver?: string,
 ): Promise<CommandResult> {
   if (!workspacePath.trim()) {
     throw new Error("Define a workspace path before running commands.");
@@ -16,5 +19,8 @@ export async function runWorkspaceCommand(
   return invoke<CommandResult>("run_terminal_command", {
     workspacePath,
     command,
+    confirm,
+    threadId,
+    approver,
   });
 }
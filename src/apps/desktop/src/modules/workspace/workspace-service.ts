@@ -29,10 +29,16 @@ export async function writeWorkspaceFile(
   workspacePath: string,
   relativePath: string,
   content: string,
+  confirm?: boolean,
+  threadId?: string,
+  approver?: string,
 ): Promise<void> {
   await invoke("write_workspace_file", {
     workspacePath,
     relativePath,
     content,
+    confirm,
+    threadId,
+    approver,
   });
 }
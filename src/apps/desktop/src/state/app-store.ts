@@ -19,6 +19,7 @@ import {
   listThreadMessages,
   listThreads,
   runTask,
+  setProjectTrust,
   setThreadPermission,
   updateAppSettings,
   updateSkill,
@@ -27,7 +28,9 @@ import {
 import type {
   AppSettingsRecord,
   GitStatusResult,
+  PermissionMode,
   ProjectRecord,
+  ProjectTrust,
   SkillRecord,
   TaskLogEvent,
   TaskLogRecord,
@@ -46,6 +49,7 @@ interface AppStore {
   tasks: TaskRecord[];
   selectedTaskId: string;
   taskLogs: TaskLogRecord[];
+  taskProgress: Record<string, string>;
   git: GitStatusResult | null;
   gitPatch: string;
   selectedDiffFile: string;
@@ -73,7 +77,8 @@ interface AppStore {
   loadSettings: () => Promise<void>;
   saveSettings: (settings: AppSettingsRecord) => Promise<void>;
   createThreadWorktree: (branchName: string, worktreePath: string) => Promise<void>;
-  setThreadPermissionMode: (permissionMode: "safe" | "normal" | "danger-confirm") => Promise<void>;
+  setThreadPermissionMode: (permissionMode: PermissionMode) => Promise<void>;
+  setProjectTrust: (projectId: string, trust: ProjectTrust) => Promise<void>;
 }
 
 let listeners: UnlistenFn[] = [];
@@ -94,6 +99,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
   tasks: [],
   selectedTaskId: "",
   taskLogs: [],
+  taskProgress: {},
   git: null,
   gitPatch: "",
   selectedDiffFile: "",
@@ -103,6 +109,10 @@ export const useAppStore = create<AppStore>((set, get) => ({
     defaultShell: "powershell",
     defaultWorkspaceRoot: "",
     theme: "light",
+    defaultPermissionMode: "normal",
+    requireThreadContext: false,
+    allowedExternalRoots: [],
+    redactionPatterns: [],
   },
   loading: false,
   statusText: "Ready",
@@ -141,18 +151,23 @@ export const useAppStore = create<AppStore>((set, get) => ({
           return;
         }
 
-        set((state) => ({
-          taskLogs: [
-            ...state.taskLogs,
-            {
-              id: `${payload.taskId}-${Date.now()}-${Math.random()}`,
-              taskId: payload.taskId,
-              stream: payload.stream,
-              line: payload.line,
-              createdAt: Date.now(),
-            },
-          ],
-        }));
+        set((state) => {
+          const taskProgress = { ...state.taskProgress };
+          delete taskProgress[payload.stream];
+          return {
+            taskLogs: [
+              ...state.taskLogs,
+              {
+                id: `${payload.taskId}-${Date.now()}-${Math.random()}`,
+                taskId: payload.taskId,
+                stream: payload.stream,
+                line: payload.line,
+                createdAt: Date.now(),
+              },
+            ],
+            taskProgress,
+          };
+        });
       });
 
       const stderrUnlisten = await listen<TaskLogEvent>("task:stderr", async (event) => {
@@ -167,17 +182,39 @@ export const useAppStore = create<AppStore>((set, get) => ({
           return;
         }
 
+        set((state) => {
+          const taskProgress = { ...state.taskProgress };
+          delete taskProgress[payload.stream];
+          return {
+            taskLogs: [
+              ...state.taskLogs,
+              {
+                id: `${payload.taskId}-${Date.now()}-${Math.random()}`,
+                taskId: payload.taskId,
+                stream: payload.stream,
+                line: payload.line,
+                createdAt: Date.now(),
+              },
+            ],
+            taskProgress,
+          };
+        });
+      });
+
+      const progressUnlisten = await listen<TaskLogEvent>("task:progress", async (event) => {
+        const payload = event.payload;
+        const activeThreadId = get().activeThreadId;
+        if (payload.threadId !== activeThreadId) {
+          return;
+        }
+
+        const selectedTaskId = get().selectedTaskId || payload.taskId;
+        if (selectedTaskId !== payload.taskId) {
+          return;
+        }
+
         set((state) => ({
-          taskLogs: [
-            ...state.taskLogs,
-            {
-              id: `${payload.taskId}-${Date.now()}-${Math.random()}`,
-              taskId: payload.taskId,
-              stream: payload.stream,
-              line: payload.line,
-              createdAt: Date.now(),
-            },
-          ],
+          taskProgress: { ...state.taskProgress, [payload.stream]: payload.line },
         }));
       });
 
@@ -202,7 +239,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
         }));
       });
 
-      listeners = [stdoutUnlisten, stderrUnlisten, statusUnlisten];
+      listeners = [stdoutUnlisten, stderrUnlisten, progressUnlisten, statusUnlisten];
       set({ listenersReady: true });
     }
   },
@@ -222,6 +259,16 @@ export const useAppStore = create<AppStore>((set, get) => ({
 
   selectProject: async (projectId: string) => {
     await touchProject(projectId);
+    const project = get().projects.find((p) => p.id === projectId);
+    if (project?.trust === "untrusted") {
+      // First open: nothing from the repository runs until the user decides.
+      const trusted = window.confirm(
+        `Do you trust the files in ${project.path}?\n\n` +
+          "Trusted projects can run tasks, terminal commands and repository git hooks. " +
+          "Choose Cancel to open it in restricted mode.",
+      );
+      await get().setProjectTrust(projectId, trusted ? "trusted" : "restricted");
+    }
     const threads = await listThreads(projectId);
 
     set({
@@ -232,6 +279,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
       tasks: [],
       selectedTaskId: "",
       taskLogs: [],
+      taskProgress: {},
     });
 
     if (threads[0]?.id) {
@@ -264,6 +312,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
       tasks,
       selectedTaskId,
       taskLogs,
+      taskProgress: {},
       statusText: "Thread loaded",
     });
   },
@@ -287,9 +336,9 @@ export const useAppStore = create<AppStore>((set, get) => ({
       return;
     }
 
-    const task = await runTask(threadId, command.trim(), project.path, "powershell", false);
+    const task = await runTask(threadId, command.trim(), project.path, "powershell");
     const tasks = await listTasks(threadId);
-    set({ tasks, selectedTaskId: task.id, taskLogs: [], statusText: `Task queued: ${task.id}` });
+    set({ tasks, selectedTaskId: task.id, taskLogs: [], taskProgress: {}, statusText: `Task queued: ${task.id}` });
   },
 
   cancelTask: async (taskId: string) => {
@@ -302,12 +351,13 @@ export const useAppStore = create<AppStore>((set, get) => ({
 
   selectTask: async (taskId: string) => {
     const logs = await listTaskLogs(taskId);
-    set({ selectedTaskId: taskId, taskLogs: logs });
+    set({ selectedTaskId: taskId, taskLogs: logs, taskProgress: {} });
   },
 
   refreshGit: async () => {
     const project = get().projects.find((p) => p.id === get().activeProjectId);
-    if (!project) {
+    // Git runs repository-defined filters and drivers, so untrusted projects show none.
+    if (!project || project.trust !== "trusted") {
       set({ git: null });
       return;
     }
@@ -318,7 +368,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
 
   loadGitDiff: async (file?: string) => {
     const project = get().projects.find((p) => p.id === get().activeProjectId);
-    if (!project) {
+    if (!project || project.trust !== "trusted") {
       set({ gitPatch: "" });
       return;
     }
@@ -372,7 +422,7 @@ export const useAppStore = create<AppStore>((set, get) => ({
     set({ threads, statusText: `Worktree attached to thread (${result.branchName})` });
   },
 
-  setThreadPermissionMode: async (permissionMode: "safe" | "normal" | "danger-confirm") => {
+  setThreadPermissionMode: async (permissionMode: PermissionMode) => {
     const threadId = get().activeThreadId;
     const projectId = get().activeProjectId;
     if (!threadId || !projectId) {
@@ -383,4 +433,15 @@ export const useAppStore = create<AppStore>((set, get) => ({
     const threads = await listThreads(projectId);
     set({ threads, statusText: `Thread permission set to ${permissionMode}` });
   },
+
+  setProjectTrust: async (projectId: string, trust: ProjectTrust) => {
+    const updated = await setProjectTrust(projectId, trust);
+    set((state) => ({
+      projects: state.projects.map((project) => (project.id === projectId ? updated : project)),
+      statusText: `Project ${updated.name} is ${trust}`,
+    }));
+    if (projectId === get().activeProjectId) {
+      await get().refreshGit();
+    }
+  },
 }));